@@ -0,0 +1,4 @@
+-- Publish date of the comic on xkcd.com, Format: "2024-11-15"
+ALTER TABLE xkcd_comics ADD COLUMN published_at TEXT;
+
+CREATE INDEX idx_published_at ON xkcd_comics(published_at);
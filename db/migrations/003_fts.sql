@@ -0,0 +1,37 @@
+-- Full-text index over chunks, with the comic's number, title and hover text
+-- copied onto every chunk so a lexical hit on any of them finds the chunk.
+-- rowid = xkcd_chunks.id
+CREATE VIRTUAL TABLE xkcd_chunks_fts USING fts5(
+    comic_number,
+    chunk_text,
+    title,
+    hover_text,
+    tokenize = 'porter unicode61'
+);
+
+INSERT INTO xkcd_chunks_fts (rowid, comic_number, chunk_text, title, hover_text)
+SELECT xc.id, xc.comic_number, xc.chunk_text, c.title, c.hover_text
+FROM xkcd_chunks xc
+JOIN xkcd_comics c ON c.comic_number = xc.comic_number;
+
+CREATE TRIGGER xkcd_chunks_fts_insert AFTER INSERT ON xkcd_chunks BEGIN
+    INSERT INTO xkcd_chunks_fts (rowid, comic_number, chunk_text, title, hover_text)
+    SELECT new.id, new.comic_number, new.chunk_text, c.title, c.hover_text
+    FROM xkcd_comics c
+    WHERE c.comic_number = new.comic_number;
+END;
+
+-- also fires for chunks removed by ON DELETE CASCADE from xkcd_comics
+CREATE TRIGGER xkcd_chunks_fts_delete AFTER DELETE ON xkcd_chunks BEGIN
+    DELETE FROM xkcd_chunks_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER xkcd_chunks_fts_update AFTER UPDATE OF chunk_text ON xkcd_chunks BEGIN
+    UPDATE xkcd_chunks_fts SET chunk_text = new.chunk_text WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER xkcd_comics_fts_update AFTER UPDATE OF title, hover_text ON xkcd_comics BEGIN
+    UPDATE xkcd_chunks_fts
+    SET title = new.title, hover_text = new.hover_text
+    WHERE rowid IN (SELECT id FROM xkcd_chunks WHERE comic_number = new.comic_number);
+END;
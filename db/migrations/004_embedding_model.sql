@@ -0,0 +1,8 @@
+-- Record which model produced the stored embeddings. Databases created
+-- before this migration were always filled by Qwen3-Embedding-0.6B; new
+-- databases overwrite these rows with the model they are created for.
+INSERT OR IGNORE INTO metadata (key, value) VALUES
+    ('EMBEDDING_MODEL_ID', 'Qwen/Qwen3-Embedding-0.6B'),
+    ('EMBEDDING_DIMENSION', '1024'),
+    ('EMBEDDING_NORMALIZED', 'true'),
+    ('EMBEDDING_METRIC', 'cosine');
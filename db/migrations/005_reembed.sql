@@ -0,0 +1,7 @@
+-- Vectors from the next embedding model, written by a re-embedding job and
+-- copied over xkcd_chunks.embedding in one transaction when it finishes.
+-- Deleting a chunk drops its pending vector with it.
+CREATE TABLE xkcd_chunks_reembed (
+    chunk_id INTEGER PRIMARY KEY REFERENCES xkcd_chunks(id) ON DELETE CASCADE,
+    embedding F32_BLOB(1024) NOT NULL
+);
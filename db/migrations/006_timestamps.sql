@@ -0,0 +1,27 @@
+-- Store every comic timestamp as RFC 3339 UTC to the second,
+-- "2025-01-27T00:00:00Z", so they sort and compare correctly as text.
+-- Older rows can hold MediaWiki "20250127000000" values or RFC 3339 with an
+-- offset or fractional seconds. Values SQLite can't parse are left as they
+-- are.
+
+-- MediaWiki values first: strftime would read 14 digits as a Julian day.
+UPDATE xkcd_comics SET last_revision_timestamp =
+  substr(last_revision_timestamp, 1, 4) || '-' || substr(last_revision_timestamp, 5, 2) || '-' || substr(last_revision_timestamp, 7, 2) || 'T' ||
+  substr(last_revision_timestamp, 9, 2) || ':' || substr(last_revision_timestamp, 11, 2) || ':' || substr(last_revision_timestamp, 13, 2) || 'Z'
+WHERE last_revision_timestamp GLOB '[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9]';
+
+UPDATE xkcd_comics SET scraped_at =
+  substr(scraped_at, 1, 4) || '-' || substr(scraped_at, 5, 2) || '-' || substr(scraped_at, 7, 2) || 'T' ||
+  substr(scraped_at, 9, 2) || ':' || substr(scraped_at, 11, 2) || ':' || substr(scraped_at, 13, 2) || 'Z'
+WHERE scraped_at GLOB '[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9]';
+
+UPDATE xkcd_comics SET updated_at =
+  substr(updated_at, 1, 4) || '-' || substr(updated_at, 5, 2) || '-' || substr(updated_at, 7, 2) || 'T' ||
+  substr(updated_at, 9, 2) || ':' || substr(updated_at, 11, 2) || ':' || substr(updated_at, 13, 2) || 'Z'
+WHERE updated_at GLOB '[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9]';
+
+-- Then everything else, which SQLite's date functions convert to UTC.
+UPDATE xkcd_comics SET
+  last_revision_timestamp = coalesce(strftime('%Y-%m-%dT%H:%M:%SZ', last_revision_timestamp), last_revision_timestamp),
+  scraped_at = coalesce(strftime('%Y-%m-%dT%H:%M:%SZ', scraped_at), scraped_at),
+  updated_at = coalesce(strftime('%Y-%m-%dT%H:%M:%SZ', updated_at), updated_at);
@@ -0,0 +1,5 @@
+-- Record the options the vector index is built with. Every database so far
+-- has the index from 001_schema.sql, which only sets the metric; new
+-- databases overwrite this row if they are created with other options.
+INSERT OR IGNORE INTO metadata (key, value) VALUES
+    ('VECTOR_INDEX_OPTIONS', '{"metric":"cosine"}');
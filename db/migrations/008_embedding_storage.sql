@@ -0,0 +1,5 @@
+-- Record how embeddings are stored for the vector index. Existing databases
+-- index the full-precision vectors; new databases overwrite this row if they
+-- are created with a quantized mode.
+INSERT OR IGNORE INTO metadata (key, value) VALUES
+    ('EMBEDDING_STORAGE', 'full');
@@ -0,0 +1,9 @@
+-- Store publish dates as "YYYY-MM-DD" so the date filters compare them
+-- correctly as text. ISO dates, with or without a time, are rewritten in
+-- that form; anything else, such as "July 28, 2006", can't be read back as
+-- a date and is cleared until the comic is scraped again.
+UPDATE xkcd_comics SET published_at =
+  CASE WHEN published_at GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]*'
+    THEN date(published_at)
+  END
+WHERE published_at IS NOT NULL;
@@ -0,0 +1,415 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use libsql::{Connection, params};
+
+use crate::error::{DatabaseError, Result};
+use crate::{Database, DatabaseOptions, OpenMode, SnapshotCounts};
+
+const BACKUP_PREFIX: &str = "xkcd-";
+const BACKUP_SUFFIX: &str = ".db";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// How many backups [`Database::backup`] keeps in its directory.
+///
+/// The newest backup of each of the last `daily` days that have one is kept,
+/// and likewise the newest of each of the last `weekly` ISO weeks. The backup
+/// just taken is always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupPolicy {
+  pub daily: usize,
+  pub weekly: usize,
+}
+
+impl Default for BackupPolicy {
+  fn default() -> Self {
+    Self {
+      daily: 7,
+      weekly: 4,
+    }
+  }
+}
+
+/// A verified backup written by [`Database::backup`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+  pub path: PathBuf,
+  pub created_at: DateTime<Utc>,
+  /// Rows in the backup, checked after reopening it.
+  pub counts: SnapshotCounts,
+  /// Older backups deleted by the retention policy.
+  pub removed: Vec<PathBuf>,
+}
+
+async fn count(conn: &Connection, table: &str) -> Result<u64> {
+  let mut rows = conn
+    .query(&format!("SELECT COUNT(*) FROM {table}"), ())
+    .await
+    .map_err(DatabaseError::query)?;
+  let row = rows
+    .next()
+    .await
+    .map_err(DatabaseError::query)?
+    .ok_or_else(|| DatabaseError::QueryFailed("COUNT(*) returned no rows".to_string()))?;
+  row
+    .get(0)
+    .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))
+}
+
+async fn table_counts(conn: &Connection) -> Result<SnapshotCounts> {
+  Ok(SnapshotCounts {
+    comics: count(conn, "xkcd_comics").await?,
+    chunks: count(conn, "xkcd_chunks").await?,
+    metadata: count(conn, "metadata").await?,
+  })
+}
+
+/// Current value of an integer pragma.
+async fn pragma_u64(conn: &Connection, pragma: &str) -> Result<u64> {
+  let mut rows = conn
+    .query(&format!("PRAGMA {pragma}"), ())
+    .await
+    .map_err(DatabaseError::query)?;
+  let row = rows
+    .next()
+    .await
+    .map_err(DatabaseError::query)?
+    .ok_or_else(|| DatabaseError::QueryFailed(format!("PRAGMA {pragma} returned no rows")))?;
+  row
+    .get(0)
+    .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))
+}
+
+/// Reopen the backup at `path` the way the bot would and check it.
+///
+/// `expected` is `None` when writes landed while the backup was taken, in
+/// which case the counts can't be compared with the live database.
+async fn verify(path: &Path, expected: Option<SnapshotCounts>) -> Result<SnapshotCounts> {
+  let backup = DatabaseOptions::new(path)
+    .mode(OpenMode::ReadOnly)
+    .readers(1)
+    .open()
+    .await?;
+  let counts = table_counts(&*backup.reader().await?).await?;
+  if let Some(expected) = expected
+    && counts != expected
+  {
+    return Err(DatabaseError::Backup(format!(
+      "{} has {counts:?}, expected {expected:?}",
+      path.display()
+    )));
+  }
+  Ok(counts)
+}
+
+/// Name of the backup taken at `at`.
+fn backup_name(at: DateTime<Utc>) -> String {
+  format!(
+    "{BACKUP_PREFIX}{}{BACKUP_SUFFIX}",
+    at.format(TIMESTAMP_FORMAT)
+  )
+}
+
+/// When the backup called `name` was taken, if it's one of ours.
+fn parse_backup_name(name: &str) -> Option<DateTime<Utc>> {
+  let timestamp = name
+    .strip_prefix(BACKUP_PREFIX)?
+    .strip_suffix(BACKUP_SUFFIX)?;
+  NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+    .ok()
+    .map(|t| t.and_utc())
+}
+
+/// The backups in `backups` that `policy` doesn't keep.
+fn expired(mut backups: Vec<(DateTime<Utc>, PathBuf)>, policy: &BackupPolicy) -> Vec<PathBuf> {
+  backups.sort_by_key(|b| std::cmp::Reverse(b.0));
+  let mut days = HashSet::new();
+  let mut weeks = HashSet::new();
+  let mut expired = Vec::new();
+  for (i, (at, path)) in backups.into_iter().enumerate() {
+    let week = at.iso_week();
+    // insert only counts a day or week the first time, i.e. for its newest
+    // backup, since the list runs newest first
+    let daily = days.len() < policy.daily && days.insert(at.date_naive());
+    let weekly = weeks.len() < policy.weekly && weeks.insert((week.year(), week.week()));
+    if i > 0 && !daily && !weekly {
+      expired.push(path);
+    }
+  }
+  expired
+}
+
+fn prune(dir: &Path, policy: &BackupPolicy) -> Result<Vec<PathBuf>> {
+  let mut backups = Vec::new();
+  for entry in std::fs::read_dir(dir)? {
+    let entry = entry?;
+    if let Some(at) = entry.file_name().to_str().and_then(parse_backup_name) {
+      backups.push((at, entry.path()));
+    }
+  }
+  let expired = expired(backups, policy);
+  for path in &expired {
+    std::fs::remove_file(path)?;
+  }
+  Ok(expired)
+}
+
+impl Database {
+  /// Write a verified backup of the database to `path`.
+  ///
+  /// The copy is made with `VACUUM INTO` on a connection opened for the
+  /// backup, so it is a consistent snapshot and writers aren't blocked while
+  /// it runs. It's written next to `path` first and only moved into place
+  /// once it reopens cleanly with the expected rows.
+  ///
+  /// # Errors
+  /// Returns an error if `path` already exists, the copy fails, or the copy
+  /// doesn't verify; nothing is left at `path` then.
+  pub async fn backup_to(&self, path: impl AsRef<Path>) -> Result<SnapshotCounts> {
+    let path = path.as_ref();
+    if path.exists() {
+      return Err(DatabaseError::Backup(format!(
+        "{} already exists",
+        path.display()
+      )));
+    }
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let partial_str = partial
+      .to_str()
+      .ok_or_else(|| DatabaseError::Backup(format!("{} is not UTF-8", partial.display())))?
+      .to_string();
+
+    let expected = {
+      // pooled readers are query_only, which refuses VACUUM INTO too,
+      // although it only writes the new file
+      let conn = self.unpooled_reader().await?;
+      // data_version changes whenever another connection commits
+      let version = pragma_u64(&conn, "data_version").await?;
+      let counts = table_counts(&conn).await?;
+      conn
+        .execute("VACUUM INTO ?", params![partial_str])
+        .await
+        .map_err(|e| DatabaseError::Backup(e.to_string()))?;
+      (pragma_u64(&conn, "data_version").await? == version).then_some(counts)
+    };
+
+    match verify(&partial, expected).await {
+      Ok(counts) => {
+        std::fs::rename(&partial, path)?;
+        Ok(counts)
+      }
+      Err(e) => {
+        let _ = std::fs::remove_file(&partial);
+        Err(e)
+      }
+    }
+  }
+
+  /// Write a verified, timestamped backup into `dir` and delete older
+  /// backups there that `policy` doesn't keep.
+  ///
+  /// Backups are named `xkcd-YYYYMMDDTHHMMSSZ.db`; other files in `dir` are
+  /// left alone.
+  pub async fn backup(&self, dir: impl AsRef<Path>, policy: &BackupPolicy) -> Result<Backup> {
+    self.backup_at(dir.as_ref(), policy, Utc::now()).await
+  }
+
+  async fn backup_at(
+    &self,
+    dir: &Path,
+    policy: &BackupPolicy,
+    created_at: DateTime<Utc>,
+  ) -> Result<Backup> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(backup_name(created_at));
+    let counts = self.backup_to(&path).await?;
+    let removed = prune(dir, policy)?;
+    Ok(Backup {
+      path,
+      created_at,
+      counts,
+      removed,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+  use crate::test_support::{make_chunk, make_comic};
+  use crate::{EMBEDDING_DIM, EmbeddingModel};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  async fn populate(db: &Database, comics: std::ops::RangeInclusive<u64>) {
+    for n in comics {
+      db.insert_comic(make_comic(n)).await.unwrap();
+      db.insert_chunks_batch(&MODEL, (0..2).map(|i| make_chunk(n, i)).collect())
+        .await
+        .unwrap();
+    }
+  }
+
+  fn at(day: u32, hour: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 3, day, hour, 0, 0).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_backup_reopens_with_same_data() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db = Database::new(temp_dir.path().join("live.db"))
+      .await
+      .unwrap();
+    populate(&db, 1..=3).await;
+
+    let backup = db
+      .backup(temp_dir.path().join("backups"), &BackupPolicy::default())
+      .await
+      .unwrap();
+    assert_eq!(backup.counts.comics, 3);
+    assert_eq!(backup.counts.chunks, 6);
+    assert!(backup.removed.is_empty());
+
+    let restored = Database::new(&backup.path).await.unwrap();
+    assert_eq!(restored.get_chunks_for_comic(2).await.unwrap().len(), 2);
+    let results = restored
+      .vector_search(&MODEL, vec![1.0; EMBEDDING_DIM], 3)
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(
+      std::fs::read_dir(temp_dir.path().join("backups"))
+        .unwrap()
+        .all(|e| e.unwrap().path() == backup.path)
+    );
+  }
+
+  #[tokio::test]
+  async fn test_backup_never_overwrites() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("backup.db");
+    let db = Database::new(":memory:").await.unwrap();
+    populate(&db, 1..=1).await;
+    db.backup_to(&path).await.unwrap();
+
+    populate(&db, 2..=2).await;
+    let result = db.backup_to(&path).await;
+    assert!(matches!(result, Err(DatabaseError::Backup(_))));
+    let backup = Database::new(&path).await.unwrap();
+    assert_eq!(backup.get_max_comic_number().await.unwrap(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_backup_leaves_readers_query_only() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let live = temp_dir.path().join("live.db");
+    populate(&Database::new(&live).await.unwrap(), 1..=2).await;
+    let db = DatabaseOptions::new(&live)
+      .mode(OpenMode::ReadOnly)
+      .readers(1)
+      .open()
+      .await
+      .unwrap();
+
+    let counts = db
+      .backup_to(temp_dir.path().join("backup.db"))
+      .await
+      .unwrap();
+    assert_eq!(counts.comics, 2);
+    assert_eq!(
+      pragma_u64(&db.reader().await.unwrap(), "query_only")
+        .await
+        .unwrap(),
+      1
+    );
+  }
+
+  #[tokio::test]
+  async fn test_backup_while_writing() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db = Database::new(temp_dir.path().join("live.db"))
+      .await
+      .unwrap();
+    populate(&db, 1..=2).await;
+
+    let writer = {
+      let db = db.clone();
+      tokio::spawn(async move { populate(&db, 3..=20).await })
+    };
+    let backup = db
+      .backup(temp_dir.path().join("backups"), &BackupPolicy::default())
+      .await
+      .unwrap();
+    writer.await.unwrap();
+
+    // whatever point the snapshot caught, every comic in it has its chunks
+    assert!(backup.counts.comics >= 2);
+    assert!(backup.counts.chunks >= backup.counts.comics * 2 - 1);
+    assert!(backup.counts.chunks <= backup.counts.comics * 2);
+  }
+
+  #[tokio::test]
+  async fn test_retention_prunes_old_backups() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db = Database::new(":memory:").await.unwrap();
+    populate(&db, 1..=1).await;
+    let policy = BackupPolicy {
+      daily: 2,
+      weekly: 1,
+    };
+    std::fs::write(temp_dir.path().join("notes.txt"), "keep me").unwrap();
+
+    // 2025-03-03 is a Monday
+    for (day, hour) in [(3, 1), (3, 2), (4, 1), (5, 1)] {
+      db.backup_at(temp_dir.path(), &policy, at(day, hour))
+        .await
+        .unwrap();
+    }
+    let mut left: Vec<_> = std::fs::read_dir(temp_dir.path())
+      .unwrap()
+      .map(|e| e.unwrap().file_name().into_string().unwrap())
+      .collect();
+    left.sort();
+    assert_eq!(
+      left,
+      vec![
+        "notes.txt",
+        "xkcd-20250304T010000Z.db",
+        "xkcd-20250305T010000Z.db"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_expired() {
+    let backups: Vec<_> = [(1, 0), (2, 0), (2, 12), (9, 0), (10, 0), (10, 6), (16, 0)]
+      .into_iter()
+      .map(|(day, hour)| (at(day, hour), PathBuf::from(backup_name(at(day, hour)))))
+      .collect();
+    assert_eq!(parse_backup_name(&backup_name(at(2, 12))), Some(at(2, 12)));
+
+    let expired = expired(
+      backups,
+      &BackupPolicy {
+        daily: 2,
+        weekly: 3,
+      },
+    );
+    // days kept: 16th and 10th (06:00), both in the week of March 10th;
+    // weeks kept besides that: 9th and 2nd (12:00)
+    let expired: Vec<_> = expired.iter().map(|p| p.to_str().unwrap()).collect();
+    assert_eq!(
+      expired,
+      vec![
+        "xkcd-20250310T000000Z.db",
+        "xkcd-20250302T000000Z.db",
+        "xkcd-20250301T000000Z.db",
+      ]
+    );
+  }
+}
@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::embedding::EmbeddingModel;
+use crate::error::Result;
+use crate::mmr::{self, Mmr, MmrSimilarity};
+use crate::models::SectionType;
+use crate::search::{ChunkSearchResult, SearchOptions};
+
+/// How many chunk hits to fetch per requested comic on the first pass.
+const CHUNKS_PER_COMIC: usize = 8;
+
+/// How chunk similarities are combined into a single comic score.
+#[derive(Debug, Clone, Default)]
+pub enum ComicAggregation {
+  /// The similarity of the comic's best chunk.
+  #[default]
+  Max,
+  /// The mean similarity of the comic's best `n` chunks. Chunks that weren't
+  /// among the hits count as zero, so a comic needs several good chunks to
+  /// score well.
+  MeanTopN(usize),
+  /// The sum of every hit's similarity multiplied by the weight of its
+  /// section. Sections missing from the map, and chunks without a section,
+  /// get a weight of `1.0`.
+  SectionWeighted(HashMap<SectionType, f64>),
+}
+
+impl ComicAggregation {
+  /// Score a comic from its hits, which must be sorted best first.
+  fn score(&self, hits: &[ChunkSearchResult]) -> f64 {
+    match self {
+      Self::Max => hits.first().map_or(0.0, ChunkSearchResult::similarity),
+      Self::MeanTopN(n) => {
+        let n = (*n).max(1);
+        hits
+          .iter()
+          .take(n)
+          .map(ChunkSearchResult::similarity)
+          .sum::<f64>()
+          / n as f64
+      }
+      Self::SectionWeighted(weights) => hits
+        .iter()
+        .map(|hit| {
+          let weight = hit
+            .section_type
+            .as_deref()
+            .and_then(|s| s.parse::<SectionType>().ok())
+            .and_then(|s| weights.get(&s).copied())
+            .unwrap_or(1.0);
+          weight * hit.similarity()
+        })
+        .sum(),
+    }
+  }
+}
+
+/// Options for [`Database::search_comics`].
+///
+/// `search.top_k` is the number of distinct comics to return; the filters in
+/// `search` apply to the underlying chunk search.
+#[derive(Debug, Clone)]
+pub struct ComicSearchOptions {
+  pub search: SearchOptions,
+  pub aggregation: ComicAggregation,
+  /// Maximum number of supporting chunks returned per comic. The best
+  /// chunk is always returned, so `0` counts as `1`.
+  pub evidence_per_comic: usize,
+  /// Re-rank the comics with maximal marginal relevance so near-duplicates
+  /// don't crowd out different ones. Relevance is the score divided by the
+  /// best score among the candidates.
+  pub diversity: Option<Mmr>,
+}
+
+impl ComicSearchOptions {
+  /// Return the `top_k` best comics by their best chunk, with up to three
+  /// chunks of evidence each.
+  #[must_use]
+  pub fn new(top_k: usize) -> Self {
+    Self {
+      search: SearchOptions::new(top_k),
+      aggregation: ComicAggregation::default(),
+      evidence_per_comic: 3,
+      diversity: None,
+    }
+  }
+}
+
+/// A comic found by [`Database::search_comics`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComicSearchResult {
+  pub comic_number: u64,
+  pub comic_title: String,
+  pub xkcd_url: String,
+  pub hover_text: Option<String>,
+  /// Aggregated score, higher is better. Its range depends on the
+  /// [`ComicAggregation`] used.
+  pub score: f64,
+  /// The comic's best matching chunks, most similar first.
+  pub evidence: Vec<ChunkSearchResult>,
+}
+
+/// Group chunk hits (sorted best first) by comic and keep the best `limit`
+/// comics.
+fn aggregate(
+  hits: Vec<ChunkSearchResult>,
+  options: &ComicSearchOptions,
+  limit: usize,
+) -> Vec<ComicSearchResult> {
+  let mut by_comic: HashMap<u64, Vec<ChunkSearchResult>> = HashMap::new();
+  for hit in hits {
+    by_comic.entry(hit.comic_number).or_default().push(hit);
+  }
+
+  let mut comics: Vec<ComicSearchResult> = by_comic
+    .into_values()
+    .map(|mut hits| {
+      let score = options.aggregation.score(&hits);
+      hits.truncate(options.evidence_per_comic.max(1));
+      let best = &hits[0];
+      ComicSearchResult {
+        comic_number: best.comic_number,
+        comic_title: best.comic_title.clone(),
+        xkcd_url: best.xkcd_url.clone(),
+        hover_text: best.hover_text.clone(),
+        score,
+        evidence: hits,
+      }
+    })
+    .collect();
+  comics.sort_by(|a, b| {
+    b.score
+      .total_cmp(&a.score)
+      .then(a.comic_number.cmp(&b.comic_number))
+  });
+  comics.truncate(limit);
+  comics
+}
+
+impl Database {
+  /// Find the comics most relevant to `query_embedding`.
+  ///
+  /// Chunk hits are grouped by comic and scored with `options.aggregation`,
+  /// so one comic with many matching chunks takes a single slot. The chunk
+  /// search is widened until `top_k` distinct comics are found or every chunk
+  /// has been considered. With `options.diversity` set, the search gathers
+  /// a larger pool of comics and picks `top_k` of them by MMR.
+  ///
+  /// # Errors
+  /// Returns an error if `model` isn't the model recorded in the database, the
+  /// embedding dimension doesn't match `EMBEDDING_DIM`, or the query fails.
+  pub async fn search_comics(
+    &self,
+    model: &EmbeddingModel,
+    query_embedding: Vec<f32>,
+    options: &ComicSearchOptions,
+  ) -> Result<Vec<ComicSearchResult>> {
+    let wanted = options.search.top_k;
+    if wanted == 0 {
+      return Ok(Vec::new());
+    }
+
+    let pool = options
+      .diversity
+      .as_ref()
+      .map_or(wanted, |mmr| mmr.pool_size(wanted));
+
+    let mut chunk_options = SearchOptions {
+      top_k: pool.saturating_mul(CHUNKS_PER_COMIC),
+      ..options.search.clone()
+    };
+    loop {
+      let hits = self
+        .vector_search_with_options(model, query_embedding.clone(), &chunk_options)
+        .await?;
+      let exhausted = hits.len() < chunk_options.top_k;
+      let comics = aggregate(hits, options, pool);
+      if comics.len() >= pool || exhausted {
+        return match &options.diversity {
+          Some(mmr) => self.diversify_comics(comics, mmr, wanted).await,
+          None => Ok(comics),
+        };
+      }
+      chunk_options.top_k = chunk_options.top_k.saturating_mul(2);
+    }
+  }
+
+  /// Pick `wanted` of `comics` (ranked best first) by MMR.
+  async fn diversify_comics(
+    &self,
+    comics: Vec<ComicSearchResult>,
+    mmr: &Mmr,
+    wanted: usize,
+  ) -> Result<Vec<ComicSearchResult>> {
+    if comics.len() <= 1 {
+      return Ok(comics);
+    }
+
+    let conn = self.reader().await?;
+    let vectors = match mmr.similarity {
+      MmrSimilarity::ComicCentroid => {
+        mmr::comic_centroids(&conn, comics.iter().map(|c| c.comic_number).collect()).await?
+      }
+      MmrSimilarity::Chunk => {
+        // compare comics by their best chunk, keyed by comic
+        let best: HashMap<u64, u64> = comics
+          .iter()
+          .map(|c| (c.evidence[0].chunk_id, c.comic_number))
+          .collect();
+        mmr::chunk_embeddings(&conn, best.keys().copied().collect())
+          .await?
+          .into_iter()
+          .map(|(chunk_id, embedding)| (best[&chunk_id], embedding))
+          .collect()
+      }
+    };
+    drop(conn);
+
+    let comics: Vec<ComicSearchResult> = comics
+      .into_iter()
+      .filter(|c| vectors.contains_key(&c.comic_number))
+      .collect();
+    let top = comics.first().map_or(0.0, |c| c.score);
+    let relevance: Vec<f64> = comics
+      .iter()
+      .map(|c| if top > 0.0 { c.score / top } else { 0.0 })
+      .collect();
+    let comic_vectors: Vec<&[f32]> = comics
+      .iter()
+      .map(|c| vectors[&c.comic_number].as_slice())
+      .collect();
+    let picked = mmr.select(&relevance, &comic_vectors, wanted);
+
+    let mut comics: Vec<Option<ComicSearchResult>> = comics.into_iter().map(Some).collect();
+    Ok(
+      picked
+        .into_iter()
+        .filter_map(|i| comics[i].take())
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::EMBEDDING_DIM;
+  use crate::models::Chunks;
+  use crate::test_support::{make_chunk, make_comic, setup_db};
+
+  /// Embedding along the all-ones direction, tilted further away by `tilt`.
+  fn tilted(tilt: f32) -> Vec<f32> {
+    (0..EMBEDDING_DIM)
+      .map(|i| if i % 2 == 0 { 1.0 } else { 1.0 - tilt })
+      .collect()
+  }
+
+  fn section_chunk(comic: u64, idx: u64, section: SectionType, tilt: f32) -> Chunks {
+    Chunks {
+      section_type: Some(section),
+      embedding: tilted(tilt),
+      ..make_chunk(comic, idx)
+    }
+  }
+
+  /// Comic 1 has the single best chunk, comic 2 has many good explanation
+  /// chunks, comic 3 has one good trivia chunk.
+  async fn setup_corpus() -> Database {
+    let db = setup_db().await;
+    for n in 1..=3 {
+      db.insert_comic(make_comic(n)).await.unwrap();
+    }
+    let mut chunks = vec![
+      section_chunk(1, 0, SectionType::Transcript, 0.0),
+      section_chunk(1, 1, SectionType::Transcript, 1.5),
+      section_chunk(3, 0, SectionType::Trivia, 0.2),
+    ];
+    for i in 0..6 {
+      chunks.push(section_chunk(2, i, SectionType::Explanation, 0.1));
+    }
+    db.insert_chunks_batch(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, chunks)
+      .await
+      .unwrap();
+    db
+  }
+
+  #[tokio::test]
+  async fn test_returns_distinct_comics() {
+    let db = setup_corpus().await;
+    let results = db
+      .search_comics(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &ComicSearchOptions::new(3),
+      )
+      .await
+      .unwrap();
+    let comics: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+    assert_eq!(comics, vec![1, 2, 3]);
+    assert_eq!(results[1].evidence.len(), 3);
+    assert!(results[0].evidence[0].distance <= results[0].evidence[1].distance);
+  }
+
+  #[tokio::test]
+  async fn test_zero_evidence_keeps_best_chunk() {
+    let db = setup_corpus().await;
+    let options = ComicSearchOptions {
+      evidence_per_comic: 0,
+      diversity: Some(Mmr {
+        similarity: MmrSimilarity::Chunk,
+        ..Mmr::new(0.5)
+      }),
+      ..ComicSearchOptions::new(3)
+    };
+    let results = db
+      .search_comics(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|r| r.evidence.len() == 1));
+  }
+
+  #[tokio::test]
+  async fn test_widens_until_enough_comics() {
+    let db = setup_db().await;
+    db.insert_comic(make_comic(1)).await.unwrap();
+    db.insert_comic(make_comic(2)).await.unwrap();
+    // more close chunks of comic 2 than the first pass fetches
+    let mut chunks: Vec<Chunks> = (0..(CHUNKS_PER_COMIC as u64 * 3))
+      .map(|i| section_chunk(2, i, SectionType::Explanation, 0.1))
+      .collect();
+    chunks.push(section_chunk(1, 0, SectionType::Explanation, 1.5));
+    db.insert_chunks_batch(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, chunks)
+      .await
+      .unwrap();
+
+    let results = db
+      .search_comics(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &ComicSearchOptions::new(2),
+      )
+      .await
+      .unwrap();
+    let comics: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+    assert_eq!(comics, vec![2, 1]);
+  }
+
+  #[tokio::test]
+  async fn test_mean_top_n_prefers_consistent_comic() {
+    let db = setup_corpus().await;
+    let options = ComicSearchOptions {
+      aggregation: ComicAggregation::MeanTopN(2),
+      ..ComicSearchOptions::new(1)
+    };
+    let results = db
+      .search_comics(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert_eq!(results[0].comic_number, 2);
+  }
+
+  #[tokio::test]
+  async fn test_section_weighted_sum() {
+    let db = setup_corpus().await;
+    let weights = HashMap::from([
+      (SectionType::Trivia, 10.0),
+      (SectionType::Explanation, 0.1),
+      (SectionType::Transcript, 0.1),
+    ]);
+    let options = ComicSearchOptions {
+      aggregation: ComicAggregation::SectionWeighted(weights),
+      ..ComicSearchOptions::new(3)
+    };
+    let results = db
+      .search_comics(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert_eq!(results[0].comic_number, 3);
+  }
+
+  #[tokio::test]
+  async fn test_filters_apply_to_chunks() {
+    let db = setup_corpus().await;
+    let options = ComicSearchOptions {
+      search: SearchOptions {
+        exclude_comics: vec![1],
+        ..SearchOptions::new(5)
+      },
+      ..ComicSearchOptions::new(5)
+    };
+    let results = db
+      .search_comics(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    let comics: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+    assert_eq!(comics, vec![2, 3]);
+  }
+
+  #[tokio::test]
+  async fn test_diversity_skips_near_duplicate_comics() {
+    let db = setup_db().await;
+    for n in 1..=3 {
+      db.insert_comic(make_comic(n)).await.unwrap();
+    }
+    // comics 1 and 2 point almost the same way, comic 3 elsewhere
+    let along = |axis: usize, amount: f32| {
+      let mut v = vec![0.0; EMBEDDING_DIM];
+      v[0] = 1.0;
+      v[axis] = amount;
+      v
+    };
+    let chunks = vec![
+      Chunks {
+        embedding: along(1, 0.1),
+        ..section_chunk(1, 0, SectionType::Explanation, 0.0)
+      },
+      Chunks {
+        embedding: along(1, 0.12),
+        ..section_chunk(2, 0, SectionType::Explanation, 0.0)
+      },
+      Chunks {
+        embedding: along(2, 0.8),
+        ..section_chunk(3, 0, SectionType::Explanation, 0.0)
+      },
+    ];
+    db.insert_chunks_batch(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, chunks)
+      .await
+      .unwrap();
+
+    let search = |options: ComicSearchOptions| {
+      let db = db.clone();
+      async move {
+        let results = db
+          .search_comics(
+            &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+            along(1, 0.0),
+            &options,
+          )
+          .await
+          .unwrap();
+        results.iter().map(|r| r.comic_number).collect::<Vec<u64>>()
+      }
+    };
+    assert_eq!(search(ComicSearchOptions::new(2)).await, vec![1, 2]);
+    for similarity in [MmrSimilarity::Chunk, MmrSimilarity::ComicCentroid] {
+      let options = ComicSearchOptions {
+        diversity: Some(Mmr {
+          similarity,
+          ..Mmr::new(0.3)
+        }),
+        ..ComicSearchOptions::new(2)
+      };
+      assert_eq!(search(options).await, vec![1, 3], "{similarity:?}");
+    }
+  }
+}
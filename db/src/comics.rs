@@ -1,97 +1,130 @@
 use chrono::{DateTime, Utc};
-use futures::{StreamExt, TryFutureExt, TryStreamExt};
-use libsql::{Rows, de, params};
+use futures::{StreamExt, TryStreamExt};
+use libsql::{Connection, Rows, TransactionBehavior, de, params};
 
-use crate::error::Result;
-use crate::models::Comics;
-use crate::{Database, DatabaseError, chunks};
+use crate::chunks::validate_chunk;
+use crate::embedding::{EmbeddingModel, check_model};
+use crate::error::{Constraint, Result};
+use crate::models::{Chunks, Comics};
+use crate::{Database, DatabaseError, chunks, timestamp};
 
 async fn into_comic_vec(rows: Rows) -> Result<Vec<Comics>> {
   rows
     .into_stream()
-    .map(|res| res.map_err(|e| DatabaseError::QueryFailed(e.to_string())))
+    .map(|res| res.map_err(DatabaseError::query))
     .and_then(|row| async move {
       de::from_row::<Comics>(&row).map_err(|e| DatabaseError::Serialization(e.to_string()))
     })
     .try_collect()
-    .map_err(|e| DatabaseError::Serialization(e.to_string()))
     .await
 }
 
+/// Check a comic before it is written.
+pub(crate) fn validate_comic(comic: &Comics) -> Result<()> {
+  for (field, value) in [
+    ("title", &comic.title),
+    ("url", &comic.url),
+    ("xkcd_url", &comic.xkcd_url),
+  ] {
+    if value.trim().is_empty() {
+      return Err(DatabaseError::InvalidContent(format!(
+        "comic {} has an empty {field}",
+        comic.comic_number
+      )));
+    }
+  }
+  Ok(())
+}
+
+/// Insert `comic` on `conn`, which may be an open transaction.
+pub(crate) async fn insert_comic_in(conn: &Connection, comic: &Comics) -> Result<()> {
+  validate_comic(comic)?;
+  let stmt = conn
+    .prepare(
+      "INSERT INTO xkcd_comics (
+        comic_number,
+        title,
+        url,
+        xkcd_url,
+        hover_text,
+        published_at,
+        last_revision_id,
+        last_revision_timestamp,
+        scraped_at,
+        updated_at
+        ) VALUES (
+        ?,
+        ?,
+        ?,
+        ?,
+        ?,
+        ?,
+        ?,
+        ?,
+        ?,
+        ?
+        )",
+    )
+    .await
+    .map_err(DatabaseError::prepare)?;
+
+  stmt
+    .execute(params![
+      comic.comic_number,
+      comic.title.as_str(),
+      comic.url.as_str(),
+      comic.xkcd_url.as_str(),
+      comic.hover_text.as_deref(),
+      comic.published_at.map(|d| d.to_string()),
+      comic.last_revision_id,
+      timestamp::to_sql(&comic.last_revision_timestamp),
+      timestamp::to_sql(&comic.scraped_at),
+      timestamp::to_sql(&comic.updated_at),
+    ])
+    .await
+    .map_err(|e| match DatabaseError::query(e) {
+      // the primary key is the only unique constraint on the table
+      DatabaseError::ConstraintViolation {
+        constraint: Constraint::Unique,
+        source,
+      } => DatabaseError::ComicAlreadyExists {
+        comic_number: comic.comic_number.get(),
+        source,
+      },
+      e => e,
+    })?;
+
+  Ok(())
+}
+
 impl Database {
   /// Insert a new comic into the database.
   ///
   /// # Errors
   /// Returns an error if:
+  /// - `title`, `url` or `xkcd_url` is empty ([`DatabaseError::InvalidContent`])
   /// - A comic with the same `comic_number` already exists in the database
+  ///   ([`DatabaseError::ComicAlreadyExists`])
   /// - The database connection fails
-  ///
-  /// # Required fields
-  /// All fields in the `Comics` struct are required. Ensure that:
-  /// - `comic_number` is unique
-  /// - `title` is non-empty
-  /// - Timestamps are in the correct format
   pub async fn insert_comic(&self, comic: Comics) -> Result<()> {
-    let stmt = self
-      .conn
-      .prepare(
-        "INSERT INTO xkcd_comics (
-          comic_number,
-          title,
-          url,
-          xkcd_url,
-          hover_text,
-          last_revision_id,
-          last_revision_timestamp,
-          scraped_at,
-          updated_at
-          ) VALUES (
-          ?,
-          ?,
-          ?,
-          ?,
-          ?,
-          ?,
-          ?,
-          ?,
-          ?
-          )",
-      )
-      .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
-
-    stmt
-      .execute(params![
-        comic.comic_number,
-        comic.title,
-        comic.url,
-        comic.xkcd_url,
-        comic.hover_text,
-        comic.last_revision_id,
-        comic.last_revision_timestamp,
-        comic.scraped_at,
-        comic.updated_at,
-      ])
-      .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
-
-    Ok(())
+    let conn = self.writer().await?;
+    insert_comic_in(&conn, &comic).await
   }
 
   /// Get a comic by its number
   pub async fn get_comic_by_number(&self, comic_number: u64) -> Result<Option<Comics>> {
-    let mut stmt = self
-      .conn
+    let conn = self.reader().await?;
+    let mut stmt = conn
       .prepare("SELECT * FROM xkcd_comics WHERE comic_number = ?")
       .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
+      .map_err(DatabaseError::prepare)?;
 
     match stmt.query_row(params![comic_number]).await {
       Ok(row) => Ok(Some(
         de::from_row::<Comics>(&row).map_err(|e| DatabaseError::Serialization(e.to_string()))?,
       )),
       Err(libsql::Error::QueryReturnedNoRows) => Ok(None),
-      Err(e) => Err(DatabaseError::QueryFailed(e.to_string())),
+      Err(e) => Err(DatabaseError::query(e)),
     }
   }
 
@@ -109,26 +142,26 @@ impl Database {
     &self,
     comic_number: u64,
     last_revision_id: u64,
-    last_revision_timestamp: String,
-    updated_at: String,
+    last_revision_timestamp: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
   ) -> Result<()> {
-    let stmt = self
-      .conn
+    let conn = self.writer().await?;
+    let stmt = conn
       .prepare(
         "UPDATE xkcd_comics SET last_revision_id = ?, last_revision_timestamp = ?, updated_at = ? WHERE comic_number = ?"
       )
       .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
+      .map_err(DatabaseError::prepare)?;
 
     let rows_affected = stmt
       .execute(params![
         last_revision_id,
-        last_revision_timestamp,
-        updated_at,
+        timestamp::to_sql(&last_revision_timestamp),
+        timestamp::to_sql(&updated_at),
         comic_number
       ])
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::query)?;
 
     if rows_affected == 0 {
       Err(DatabaseError::InvalidComicNumber(comic_number))
@@ -141,15 +174,15 @@ impl Database {
   ///
   /// Returns an error if the comic doesn't exist.
   pub async fn delete_comic(&self, comic_number: u64) -> Result<()> {
-    let stmt = self
-      .conn
+    let conn = self.writer().await?;
+    let stmt = conn
       .prepare("DELETE FROM xkcd_comics WHERE comic_number = ?")
       .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
+      .map_err(DatabaseError::prepare)?;
     let rows_affected = stmt
       .execute(params![comic_number])
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::query)?;
 
     if rows_affected == 0 {
       Err(DatabaseError::InvalidComicNumber(comic_number))
@@ -160,15 +193,15 @@ impl Database {
 
   /// Get the highest comic number in database
   pub async fn get_max_comic_number(&self) -> Result<u64> {
-    let mut stmt = self
-      .conn
+    let conn = self.reader().await?;
+    let mut stmt = conn
       .prepare("SELECT MAX(comic_number) FROM xkcd_comics")
       .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
+      .map_err(DatabaseError::prepare)?;
     let row = stmt
       .query_row(params![])
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::query)?;
     match row.get(0) {
       Ok(Some(max_comic_number)) => Ok(max_comic_number),
       Ok(None) => Err(DatabaseError::NoComicsFound),
@@ -177,21 +210,137 @@ impl Database {
   }
 
   /// Get comics that haven't been updated recently (for update checks)
+  ///
+  /// Compares `updated_at` as stored text, which orders correctly because
+  /// every timestamp is written in the same fixed-width UTC form.
   pub async fn get_comics_needing_update(&self, older_than: DateTime<Utc>) -> Result<Vec<Comics>> {
-    let stmt = self
-      .conn
+    let conn = self.reader().await?;
+    let stmt = conn
       .prepare("SELECT * FROM xkcd_comics WHERE updated_at < ?")
       .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
+      .map_err(DatabaseError::prepare)?;
 
     let rows = stmt
-      .query(params![older_than.to_rfc3339()])
+      .query(params![timestamp::to_sql(&older_than)])
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::query)?;
 
     into_comic_vec(rows).await
   }
 
+  /// Write a new revision of a comic and its whole chunk set atomically.
+  ///
+  /// Inserts the comic if it is new, otherwise updates every column except
+  /// `scraped_at`, then deletes the comic's old chunks and inserts `chunks`,
+  /// all in one transaction. A crash part way through leaves the previous
+  /// revision intact. Writing the same revision twice is harmless, which makes
+  /// this the scraper's retry-safe write path.
+  ///
+  /// # Errors
+  /// Returns an error if:
+  /// - `model` isn't the model recorded in the database
+  /// - The stored `last_revision_id` is newer than `comic.last_revision_id`
+  ///   ([`DatabaseError::StaleRevision`]); nothing is written
+  /// - The comic or any chunk has empty text ([`DatabaseError::InvalidContent`])
+  /// - Any chunk belongs to a different comic or has the wrong embedding dimension
+  /// - The database operation fails
+  pub async fn replace_comic_content(
+    &self,
+    model: &EmbeddingModel,
+    comic: Comics,
+    chunks: Vec<Chunks>,
+  ) -> Result<()> {
+    validate_comic(&comic)?;
+    for chunk in &chunks {
+      if chunk.comic_number != comic.comic_number {
+        return Err(DatabaseError::InvalidContent(format!(
+          "chunk {} belongs to comic {}, not {}",
+          chunk.chunk_index, chunk.comic_number, comic.comic_number
+        )));
+      }
+      validate_chunk(chunk)?;
+    }
+
+    // take the write lock up front so the revision check can't go stale
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    check_model(&tx, model).await?;
+
+    let mut rows = tx
+      .query(
+        "SELECT last_revision_id FROM xkcd_comics WHERE comic_number = ?",
+        params![comic.comic_number],
+      )
+      .await
+      .map_err(DatabaseError::query)?;
+    if let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+      let stored: u64 = row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+      if stored > comic.last_revision_id {
+        return Err(DatabaseError::StaleRevision {
+          comic_number: comic.comic_number.get(),
+          stored,
+          attempted: comic.last_revision_id,
+        });
+      }
+    }
+    drop(rows);
+
+    tx.execute(
+      "INSERT INTO xkcd_comics (
+        comic_number,
+        title,
+        url,
+        xkcd_url,
+        hover_text,
+        published_at,
+        last_revision_id,
+        last_revision_timestamp,
+        scraped_at,
+        updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (comic_number) DO UPDATE SET
+          title = excluded.title,
+          url = excluded.url,
+          xkcd_url = excluded.xkcd_url,
+          hover_text = excluded.hover_text,
+          published_at = excluded.published_at,
+          last_revision_id = excluded.last_revision_id,
+          last_revision_timestamp = excluded.last_revision_timestamp,
+          updated_at = excluded.updated_at",
+      params![
+        comic.comic_number,
+        comic.title,
+        comic.url,
+        comic.xkcd_url,
+        comic.hover_text,
+        comic.published_at.map(|d| d.to_string()),
+        comic.last_revision_id,
+        timestamp::to_sql(&comic.last_revision_timestamp),
+        timestamp::to_sql(&comic.scraped_at),
+        timestamp::to_sql(&comic.updated_at),
+      ],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+
+    tx.execute(
+      "DELETE FROM xkcd_chunks WHERE comic_number = ?",
+      params![comic.comic_number],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+
+    chunks::insert_chunks_in(&tx, chunks).await?;
+
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(())
+  }
+
   /// Get a batch of comics by their numbers.
   ///
   /// Returns only the comics that exist in the database. Non-existent comic
@@ -208,45 +357,38 @@ impl Database {
   /// A vector of comics that were found. May be shorter than the input slice
   /// if some comics don't exist. Returns an empty vector if no comics are found.
   pub async fn get_comics_batch(&self, comic_numbers: Vec<u64>) -> Result<Vec<Comics>> {
-    let stmt = self
-      .conn
+    let conn = self.reader().await?;
+    let stmt = conn
       .prepare("SELECT * FROM xkcd_comics WHERE comic_number IN (SELECT value FROM json_each(?))")
-      .await?;
+      .await
+      .map_err(DatabaseError::prepare)?;
 
     let rows = stmt
       .query(params![chunks::vec_to_json_string(comic_numbers)])
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::query)?;
     into_comic_vec(rows).await
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use chrono::NaiveDate;
+
   use super::*;
-  use crate::models::Comics;
-
-  async fn setup() -> Database {
-    Database::new(":memory:").await.unwrap()
-  }
-
-  fn make_comic(number: u64) -> Comics {
-    Comics {
-      comic_number: number,
-      title: format!("Comic {}", number),
-      url: format!("https://explainxkcd.com/{}", number),
-      xkcd_url: format!("https://xkcd.com/{}", number),
-      hover_text: Some(format!("Hover text {}", number)),
-      last_revision_id: 12345,
-      last_revision_timestamp: "20250127000000".to_string(),
-      scraped_at: "2025-01-27T00:00:00Z".to_string(),
-      updated_at: "2025-01-27T00:00:00Z".to_string(),
+  use crate::test_support::{make_chunk, make_comic, setup_db};
+  use crate::timestamp;
+
+  fn text_chunk(comic: u64, idx: u64, text: &str) -> Chunks {
+    Chunks {
+      chunk_text: text.to_string(),
+      ..make_chunk(comic, idx)
     }
   }
 
   #[tokio::test]
   async fn test_insert_comic() {
-    let db = setup().await;
+    let db = setup_db().await;
     let comic = make_comic(1);
     let result = db.insert_comic(comic.clone()).await;
     assert!(result.is_ok());
@@ -254,16 +396,23 @@ mod tests {
 
   #[tokio::test]
   async fn test_insert_duplicate_comic_fails() {
-    let db = setup().await;
+    let db = setup_db().await;
     let comic = make_comic(1);
     db.insert_comic(comic.clone()).await.unwrap();
     let result = db.insert_comic(comic).await;
-    assert!(result.is_err());
+    assert!(matches!(
+      result,
+      Err(DatabaseError::ComicAlreadyExists {
+        comic_number: 1,
+        ..
+      })
+    ));
+    assert!(!result.unwrap_err().is_retryable());
   }
 
   #[tokio::test]
   async fn test_get_comic_exists() {
-    let db = setup().await;
+    let db = setup_db().await;
     let comic = make_comic(42);
     db.insert_comic(comic.clone()).await.unwrap();
     let result = db.get_comic_by_number(42).await.unwrap();
@@ -273,14 +422,14 @@ mod tests {
 
   #[tokio::test]
   async fn test_get_comic_not_found() {
-    let db = setup().await;
+    let db = setup_db().await;
     let result = db.get_comic_by_number(999).await.unwrap();
     assert!(result.is_none());
   }
 
   #[tokio::test]
   async fn test_comic_exists() {
-    let db = setup().await;
+    let db = setup_db().await;
     db.insert_comic(make_comic(100)).await.unwrap();
     assert!(db.comic_exists(100).await.unwrap());
     assert!(!db.comic_exists(101).await.unwrap());
@@ -288,30 +437,34 @@ mod tests {
 
   #[tokio::test]
   async fn test_update_comic() {
-    let db = setup().await;
+    let db = setup_db().await;
     db.insert_comic(make_comic(50)).await.unwrap();
     let result = db
       .update_comic(
         50,
         99999,
-        "20250128000000".to_string(),
-        "2025-01-28T00:00:00Z".to_string(),
+        timestamp::parse("20250128000000").unwrap(),
+        timestamp::parse("2025-01-28T00:00:00Z").unwrap(),
       )
       .await;
     assert!(result.is_ok());
     let updated = db.get_comic_by_number(50).await.unwrap().unwrap();
     assert_eq!(updated.last_revision_id, 99999);
+    assert_eq!(
+      updated.last_revision_timestamp,
+      timestamp::parse("2025-01-28T00:00:00Z").unwrap()
+    );
   }
 
   #[tokio::test]
   async fn test_update_nonexistent_comic_fails() {
-    let db = setup().await;
+    let db = setup_db().await;
     let result = db
       .update_comic(
         999,
         12345,
-        "20250127000000".to_string(),
-        "2025-01-27T00:00:00Z".to_string(),
+        timestamp::parse("20250127000000").unwrap(),
+        timestamp::parse("2025-01-27T00:00:00Z").unwrap(),
       )
       .await;
     assert!(result.is_err());
@@ -319,7 +472,7 @@ mod tests {
 
   #[tokio::test]
   async fn test_delete_comic() {
-    let db = setup().await;
+    let db = setup_db().await;
     db.insert_comic(make_comic(10)).await.unwrap();
     assert!(db.comic_exists(10).await.unwrap());
     db.delete_comic(10).await.unwrap();
@@ -328,14 +481,14 @@ mod tests {
 
   #[tokio::test]
   async fn test_delete_nonexistent_comic_fails() {
-    let db = setup().await;
+    let db = setup_db().await;
     let result = db.delete_comic(999).await;
     assert!(result.is_err());
   }
 
   #[tokio::test]
   async fn test_get_max_comic_number_empty_db() {
-    let db = setup().await;
+    let db = setup_db().await;
     assert!(
       db.get_max_comic_number()
         .await
@@ -345,7 +498,7 @@ mod tests {
 
   #[tokio::test]
   async fn test_get_max_comic_number() {
-    let db = setup().await;
+    let db = setup_db().await;
     db.insert_comic(make_comic(5)).await.unwrap();
     db.insert_comic(make_comic(100)).await.unwrap();
     db.insert_comic(make_comic(42)).await.unwrap();
@@ -354,9 +507,9 @@ mod tests {
 
   #[tokio::test]
   async fn test_get_comics_needing_update() {
-    let db = setup().await;
+    let db = setup_db().await;
     let mut old = make_comic(1);
-    old.updated_at = "2020-01-01T00:00:00Z".to_string();
+    old.updated_at = timestamp::parse("2020-01-01T00:00:00Z").unwrap();
     db.insert_comic(old).await.unwrap();
     db.insert_comic(make_comic(2)).await.unwrap();
     let cutoff = "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
@@ -365,9 +518,95 @@ mod tests {
     assert_eq!(old_comics[0].comic_number, 1);
   }
 
+  #[tokio::test]
+  async fn test_legacy_timestamps_normalized() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let raw = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = raw.connect().unwrap();
+    conn
+      .execute_batch(crate::migrations::MIGRATIONS[0].sql)
+      .await
+      .unwrap();
+    // compared as text against the cutoff, 1's MediaWiki-style updated_at
+    // looks newer than it is and 2's offset makes it look older
+    conn
+      .execute_batch(
+        "INSERT INTO xkcd_comics VALUES
+           (1, 'A', 'u', 'x', NULL, 1, '20250115000000', '20250115000000', '20250115000000'),
+           (2, 'B', 'u', 'x', NULL, 1, '20250201010000',
+            '2025-01-31T23:00:00.5-02:00', '2025-01-31T23:00:00.5-02:00');",
+      )
+      .await
+      .unwrap();
+    drop(conn);
+    drop(raw);
+
+    let db = Database::new(&path).await.unwrap();
+    let comic = db.get_comic_by_number(2).await.unwrap().unwrap();
+    assert_eq!(
+      comic.updated_at,
+      timestamp::parse("2025-02-01T01:00:00Z").unwrap()
+    );
+    let cutoff = timestamp::parse("2025-02-01T00:00:00Z").unwrap();
+    let stale = db.get_comics_needing_update(cutoff).await.unwrap();
+    assert_eq!(stale.len(), 1);
+    assert_eq!(stale[0].comic_number, 1);
+  }
+
+  #[tokio::test]
+  async fn test_legacy_publish_dates_normalized() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let raw = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = raw.connect().unwrap();
+    crate::migrations::apply_migrations(&conn, &crate::migrations::MIGRATIONS[..8])
+      .await
+      .unwrap();
+    conn
+      .execute_batch(
+        "INSERT INTO xkcd_comics VALUES
+           (1, 'A', 'u', 'x', NULL, 1, '2025-01-15T00:00:00Z', '2025-01-15T00:00:00Z',
+            '2025-01-15T00:00:00Z', '2006-07-28T12:00:00'),
+           (2, 'B', 'u', 'x', NULL, 1, '2025-01-15T00:00:00Z', '2025-01-15T00:00:00Z',
+            '2025-01-15T00:00:00Z', 'July 28, 2006');",
+      )
+      .await
+      .unwrap();
+    drop(conn);
+    drop(raw);
+
+    let db = Database::new(&path).await.unwrap();
+    let comics = db.get_comics_batch(vec![1, 2]).await.unwrap();
+    assert_eq!(comics[0].published_at, NaiveDate::from_ymd_opt(2006, 7, 28));
+    assert_eq!(comics[1].published_at, None);
+  }
+
+  #[tokio::test]
+  async fn test_insert_rejects_empty_content() {
+    let db = setup_db().await;
+    let mut untitled = make_comic(1);
+    untitled.title = "  ".to_string();
+    assert!(matches!(
+      db.insert_comic(untitled).await,
+      Err(DatabaseError::InvalidContent(_))
+    ));
+    assert!(!db.comic_exists(1).await.unwrap());
+
+    db.insert_comic(make_comic(1)).await.unwrap();
+    let result = db
+      .replace_comic_content(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        make_comic(1),
+        vec![text_chunk(1, 0, "")],
+      )
+      .await;
+    assert!(matches!(result, Err(DatabaseError::InvalidContent(_))));
+  }
+
   #[tokio::test]
   async fn test_get_comics_batch() {
-    let db = setup().await;
+    let db = setup_db().await;
     for i in 1..=5 {
       db.insert_comic(make_comic(i)).await.unwrap();
     }
@@ -377,16 +616,157 @@ mod tests {
 
   #[tokio::test]
   async fn test_get_comics_batch_empty() {
-    let db = setup().await;
+    let db = setup_db().await;
     assert_eq!(db.get_comics_batch(Vec::new()).await.unwrap().len(), 0);
   }
 
   #[tokio::test]
   async fn test_get_comics_batch_some_missing() {
-    let db = setup().await;
+    let db = setup_db().await;
     db.insert_comic(make_comic(1)).await.unwrap();
     db.insert_comic(make_comic(3)).await.unwrap();
     let batch = db.get_comics_batch([1, 2, 3].to_vec()).await.unwrap();
     assert_eq!(batch.len(), 2);
   }
+
+  #[tokio::test]
+  async fn test_replace_comic_content_inserts_new_comic() {
+    let db = setup_db().await;
+    db.replace_comic_content(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      make_comic(7),
+      vec![text_chunk(7, 0, "a"), text_chunk(7, 1, "b")],
+    )
+    .await
+    .unwrap();
+    assert!(db.comic_exists(7).await.unwrap());
+    assert_eq!(db.get_chunks_for_comic(7).await.unwrap().len(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_replace_comic_content_swaps_chunks() {
+    let db = setup_db().await;
+    db.replace_comic_content(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      make_comic(7),
+      vec![text_chunk(7, 0, "old"), text_chunk(7, 1, "old")],
+    )
+    .await
+    .unwrap();
+
+    let mut newer = make_comic(7);
+    newer.last_revision_id += 1;
+    newer.title = "Renamed".to_string();
+    newer.scraped_at = timestamp::parse("2030-01-01T00:00:00Z").unwrap();
+    db.replace_comic_content(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      newer,
+      vec![text_chunk(7, 0, "new")],
+    )
+    .await
+    .unwrap();
+
+    let stored = db.get_comic_by_number(7).await.unwrap().unwrap();
+    assert_eq!(stored.title, "Renamed");
+    // first-scraped time is kept
+    assert_eq!(
+      stored.scraped_at,
+      timestamp::parse("2025-01-27T00:00:00Z").unwrap()
+    );
+    let chunks = db.get_chunks_for_comic(7).await.unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].chunk_text, "new");
+  }
+
+  #[tokio::test]
+  async fn test_replace_comic_content_is_idempotent() {
+    let db = setup_db().await;
+    for _ in 0..2 {
+      db.replace_comic_content(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        make_comic(7),
+        vec![text_chunk(7, 0, "a")],
+      )
+      .await
+      .unwrap();
+    }
+    assert_eq!(db.get_chunks_for_comic(7).await.unwrap().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_replace_comic_content_rejects_stale_revision() {
+    let db = setup_db().await;
+    db.replace_comic_content(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      make_comic(7),
+      vec![text_chunk(7, 0, "current")],
+    )
+    .await
+    .unwrap();
+
+    let mut stale = make_comic(7);
+    stale.last_revision_id -= 1;
+    let result = db
+      .replace_comic_content(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        stale,
+        vec![text_chunk(7, 0, "stale")],
+      )
+      .await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::StaleRevision {
+        stored: 12345,
+        attempted: 12344,
+        ..
+      })
+    ));
+    assert_eq!(
+      db.get_chunks_for_comic(7).await.unwrap()[0].chunk_text,
+      "current"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_replace_comic_content_rolls_back_on_bad_chunk() {
+    let db = setup_db().await;
+    db.replace_comic_content(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      make_comic(7),
+      vec![text_chunk(7, 0, "current")],
+    )
+    .await
+    .unwrap();
+
+    let mut newer = make_comic(7);
+    newer.last_revision_id += 1;
+    let mut bad = text_chunk(7, 1, "bad");
+    bad.embedding = vec![0.0; 10];
+    assert!(
+      db.replace_comic_content(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        newer.clone(),
+        vec![text_chunk(7, 0, "new"), bad]
+      )
+      .await
+      .is_err()
+    );
+    let wrong_comic = text_chunk(8, 0, "elsewhere");
+    assert!(matches!(
+      db.replace_comic_content(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        newer,
+        vec![wrong_comic]
+      )
+      .await,
+      Err(DatabaseError::InvalidContent(_))
+    ));
+
+    let stored = db.get_comic_by_number(7).await.unwrap().unwrap();
+    assert_eq!(stored.last_revision_id, 12345);
+    assert_eq!(
+      db.get_chunks_for_comic(7).await.unwrap()[0].chunk_text,
+      "current"
+    );
+  }
 }
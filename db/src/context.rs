@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+
+use libsql::{Connection, de, params};
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::error::{DatabaseError, Result};
+use crate::models::{Comics, SectionType};
+use crate::search::ChunkSearchResult;
+
+/// A chunk's text and position, without its embedding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextChunk {
+  pub chunk_id: u64,
+  pub chunk_index: u64,
+  pub chunk_text: String,
+}
+
+/// A run of consecutive chunks from one section of one comic, built around
+/// one or more search hits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkContext {
+  /// The hits inside this window, in the order they were passed in.
+  pub hits: Vec<ChunkSearchResult>,
+  pub section_type: Option<SectionType>,
+  /// The window's chunks in `chunk_index` order, hits included.
+  pub chunks: Vec<ContextChunk>,
+}
+
+impl ChunkContext {
+  pub fn comic_number(&self) -> u64 {
+    self.hits[0].comic_number
+  }
+
+  /// The window's text, one chunk per paragraph.
+  pub fn text(&self) -> String {
+    join_chunks(&self.chunks)
+  }
+}
+
+/// All chunks of one section type, in `chunk_index` order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentSection {
+  pub section_type: Option<SectionType>,
+  pub chunks: Vec<ContextChunk>,
+}
+
+impl DocumentSection {
+  /// The section's text, one chunk per paragraph.
+  pub fn text(&self) -> String {
+    join_chunks(&self.chunks)
+  }
+}
+
+/// A comic's stored text put back together from its chunks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComicDocument {
+  pub comic: Comics,
+  /// One entry per section type, ordered by where the section first appears.
+  pub sections: Vec<DocumentSection>,
+}
+
+impl ComicDocument {
+  /// The section of type `section_type`, if the comic has one.
+  pub fn section(&self, section_type: SectionType) -> Option<&DocumentSection> {
+    self
+      .sections
+      .iter()
+      .find(|s| s.section_type == Some(section_type))
+  }
+}
+
+fn join_chunks(chunks: &[ContextChunk]) -> String {
+  chunks
+    .iter()
+    .map(|c| c.chunk_text.as_str())
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+fn parse_section(section: Option<&str>) -> Result<Option<SectionType>> {
+  section
+    .map(str::parse::<SectionType>)
+    .transpose()
+    .map_err(|e| DatabaseError::InvalidSectionType(e.to_string()))
+}
+
+/// Chunks of `comic_number` in `section`, limited to `chunk_index` in
+/// `first..=last`, in order.
+async fn chunk_range(
+  conn: &Connection,
+  comic_number: u64,
+  section: Option<&str>,
+  first: u64,
+  last: u64,
+) -> Result<Vec<ContextChunk>> {
+  // `IS` so chunks without a section only match each other
+  let mut rows = conn
+    .query(
+      "SELECT id, chunk_index, chunk_text FROM xkcd_chunks
+       WHERE comic_number = ? AND chunk_index BETWEEN ? AND ? AND section_type IS ?
+       ORDER BY chunk_index, id",
+      params![comic_number, first, last, section],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let mut chunks = Vec::new();
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    chunks.push(ContextChunk {
+      chunk_id: row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+      chunk_index: row
+        .get(1)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+      chunk_text: row
+        .get(2)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+    });
+  }
+  Ok(chunks)
+}
+
+async fn chunk_index(conn: &Connection, chunk_id: u64) -> Result<Option<u64>> {
+  let mut rows = conn
+    .query(
+      "SELECT chunk_index FROM xkcd_chunks WHERE id = ?",
+      params![chunk_id],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  match rows.next().await.map_err(DatabaseError::query)? {
+    Some(row) => Ok(Some(
+      row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+    )),
+    None => Ok(None),
+  }
+}
+
+/// A window being built: the hits in it and its `chunk_index` bounds.
+struct Window {
+  hits: Vec<(usize, ChunkSearchResult)>,
+  first: u64,
+  last: u64,
+}
+
+impl Database {
+  /// Surround each hit with up to `radius` chunks either side of it from the
+  /// same comic and section.
+  ///
+  /// Windows that overlap or touch are merged, so every chunk appears at most
+  /// once. The result is ordered by each window's earliest hit in `hits`,
+  /// which keeps a relevance ranking intact. Hits whose chunk has since been
+  /// deleted are skipped.
+  pub async fn expand_context(
+    &self,
+    hits: &[ChunkSearchResult],
+    radius: u64,
+  ) -> Result<Vec<ChunkContext>> {
+    let reader = self.reader().await?;
+    // one snapshot, so a rescrape can't land between the windows
+    let conn = reader
+      .transaction()
+      .await
+      .map_err(DatabaseError::transaction)?;
+
+    let mut groups: HashMap<(u64, Option<String>), Vec<Window>> = HashMap::new();
+    for (rank, hit) in hits.iter().enumerate() {
+      let Some(index) = chunk_index(&conn, hit.chunk_id).await? else {
+        continue;
+      };
+      groups
+        .entry((hit.comic_number, hit.section_type.clone()))
+        .or_default()
+        .push(Window {
+          hits: vec![(rank, hit.clone())],
+          first: index.saturating_sub(radius),
+          last: index.saturating_add(radius),
+        });
+    }
+
+    let mut contexts = Vec::new();
+    for ((comic_number, section), mut windows) in groups {
+      windows.sort_by_key(|w| w.first);
+      let mut merged: Vec<Window> = Vec::new();
+      for window in windows {
+        match merged.last_mut() {
+          Some(prev) if window.first <= prev.last.saturating_add(1) => {
+            prev.last = prev.last.max(window.last);
+            prev.hits.extend(window.hits);
+          }
+          _ => merged.push(window),
+        }
+      }
+
+      let section_type = parse_section(section.as_deref())?;
+      for mut window in merged {
+        window.hits.sort_by_key(|(rank, _)| *rank);
+        let chunks = chunk_range(
+          &conn,
+          comic_number,
+          section.as_deref(),
+          window.first,
+          window.last,
+        )
+        .await?;
+        contexts.push((
+          window.hits[0].0,
+          ChunkContext {
+            hits: window.hits.into_iter().map(|(_, hit)| hit).collect(),
+            section_type,
+            chunks,
+          },
+        ));
+      }
+    }
+
+    contexts.sort_by_key(|(rank, _)| *rank);
+    Ok(contexts.into_iter().map(|(_, context)| context).collect())
+  }
+
+  /// Put a comic's text back together from its chunks, one section per
+  /// section type.
+  ///
+  /// Returns `None` if the comic doesn't exist.
+  pub async fn comic_document(&self, comic_number: u64) -> Result<Option<ComicDocument>> {
+    let reader = self.reader().await?;
+    // one snapshot, so the comic and its chunks are from the same revision
+    let conn = reader
+      .transaction()
+      .await
+      .map_err(DatabaseError::transaction)?;
+    let mut rows = conn
+      .query(
+        "SELECT * FROM xkcd_comics WHERE comic_number = ?",
+        params![comic_number],
+      )
+      .await
+      .map_err(DatabaseError::query)?;
+    let Some(row) = rows.next().await.map_err(DatabaseError::query)? else {
+      return Ok(None);
+    };
+    let comic =
+      de::from_row::<Comics>(&row).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+    drop(rows);
+
+    let mut rows = conn
+      .query(
+        "SELECT id, chunk_index, chunk_text, section_type FROM xkcd_chunks
+         WHERE comic_number = ?
+         ORDER BY chunk_index, id",
+        params![comic_number],
+      )
+      .await
+      .map_err(DatabaseError::query)?;
+    let mut sections: Vec<DocumentSection> = Vec::new();
+    while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+      let section: Option<String> = row
+        .get(3)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+      let section_type = parse_section(section.as_deref())?;
+      let chunk = ContextChunk {
+        chunk_id: row
+          .get(0)
+          .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+        chunk_index: row
+          .get(1)
+          .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+        chunk_text: row
+          .get(2)
+          .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+      };
+      match sections.iter_mut().find(|s| s.section_type == section_type) {
+        Some(existing) => existing.chunks.push(chunk),
+        None => sections.push(DocumentSection {
+          section_type,
+          chunks: vec![chunk],
+        }),
+      }
+    }
+
+    Ok(Some(ComicDocument { comic, sections }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::EmbeddingModel;
+  use crate::models::Chunks;
+  use crate::test_support::{make_chunk, make_comic};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  fn section_chunk(comic: u64, idx: u64, section: SectionType) -> Chunks {
+    Chunks {
+      chunk_text: format!("{section} {idx}"),
+      section_type: Some(section),
+      ..make_chunk(comic, idx)
+    }
+  }
+
+  /// Comic 1 has a title/hover chunk, explanation chunks 1-8 and transcript
+  /// chunks 9-10; comic 2 has explanation chunks 1-3.
+  async fn setup() -> Database {
+    let db = Database::new(":memory:").await.unwrap();
+    db.insert_comic(make_comic(1)).await.unwrap();
+    db.insert_comic(make_comic(2)).await.unwrap();
+    let mut chunks = vec![section_chunk(1, 0, SectionType::TitleHover)];
+    chunks.extend((1..=8).map(|i| section_chunk(1, i, SectionType::Explanation)));
+    // inserted out of order on purpose
+    chunks.extend(
+      (9..=10)
+        .rev()
+        .map(|i| section_chunk(1, i, SectionType::Transcript)),
+    );
+    chunks.extend((1..=3).map(|i| section_chunk(2, i, SectionType::Explanation)));
+    db.insert_chunks_batch(&MODEL, chunks).await.unwrap();
+    db
+  }
+
+  /// A search hit on chunk `idx` of `comic`.
+  async fn hit(db: &Database, comic: u64, idx: u64) -> ChunkSearchResult {
+    let chunk = db
+      .get_chunks_for_comic(comic)
+      .await
+      .unwrap()
+      .into_iter()
+      .find(|c| c.chunk_index == idx)
+      .unwrap();
+    ChunkSearchResult {
+      chunk_id: chunk.id.unwrap(),
+      comic_number: comic,
+      chunk_text: chunk.chunk_text,
+      section_type: chunk.section_type.map(|s| s.to_string()),
+      comic_title: format!("C{}", comic),
+      xkcd_url: format!("https://xkcd.com/{}", comic),
+      hover_text: None,
+      distance: 0.1,
+    }
+  }
+
+  fn indexes(context: &ChunkContext) -> Vec<u64> {
+    context.chunks.iter().map(|c| c.chunk_index).collect()
+  }
+
+  #[tokio::test]
+  async fn test_window_stays_in_section() {
+    let db = setup().await;
+    let hits = vec![hit(&db, 1, 1).await, hit(&db, 1, 8).await];
+    let contexts = db.expand_context(&hits, 2).await.unwrap();
+
+    assert_eq!(contexts.len(), 2);
+    // neither the title/hover chunk 0 nor the transcript after 8 is pulled in
+    assert_eq!(indexes(&contexts[0]), vec![1, 2, 3]);
+    assert_eq!(indexes(&contexts[1]), vec![6, 7, 8]);
+    assert_eq!(contexts[0].section_type, Some(SectionType::Explanation));
+    assert_eq!(
+      contexts[0].text(),
+      "explanation 1\n\nexplanation 2\n\nexplanation 3"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_overlapping_windows_merge() {
+    let db = setup().await;
+    let hits = vec![
+      hit(&db, 2, 2).await,
+      hit(&db, 1, 6).await,
+      hit(&db, 1, 3).await,
+      hit(&db, 1, 10).await,
+    ];
+    let contexts = db.expand_context(&hits, 1).await.unwrap();
+
+    // 2..=4 and 5..=7 touch, so comic 1's explanation hits share a window
+    // ranked where its best hit was
+    assert_eq!(contexts.len(), 3);
+    assert_eq!(contexts[0].comic_number(), 2);
+    assert_eq!(indexes(&contexts[0]), vec![1, 2, 3]);
+    assert_eq!(indexes(&contexts[1]), vec![2, 3, 4, 5, 6, 7]);
+    let merged: Vec<u64> = contexts[1].hits.iter().map(|h| h.chunk_id).collect();
+    assert_eq!(merged, vec![hits[1].chunk_id, hits[2].chunk_id]);
+    assert_eq!(indexes(&contexts[2]), vec![9, 10]);
+
+    assert!(db.expand_context(&[], 1).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_comic_document_grouped_by_section() {
+    let db = setup().await;
+    let document = db.comic_document(1).await.unwrap().unwrap();
+    assert_eq!(document.comic.title, "C1");
+
+    let sections: Vec<_> = document.sections.iter().map(|s| s.section_type).collect();
+    assert_eq!(
+      sections,
+      vec![
+        Some(SectionType::TitleHover),
+        Some(SectionType::Explanation),
+        Some(SectionType::Transcript)
+      ]
+    );
+    assert_eq!(
+      document.section(SectionType::Transcript).unwrap().text(),
+      "transcript 9\n\ntranscript 10"
+    );
+    assert_eq!(
+      document
+        .section(SectionType::Explanation)
+        .unwrap()
+        .chunks
+        .len(),
+      8
+    );
+
+    assert!(db.comic_document(99).await.unwrap().is_none());
+  }
+}
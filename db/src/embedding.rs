@@ -0,0 +1,426 @@
+use std::borrow::Cow;
+
+use libsql::{Connection, params};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::error::{DatabaseError, Result};
+use crate::{Database, EMBEDDING_DIM};
+
+pub(crate) const MODEL_ID_KEY: &str = "EMBEDDING_MODEL_ID";
+pub(crate) const DIMENSION_KEY: &str = "EMBEDDING_DIMENSION";
+pub(crate) const NORMALIZED_KEY: &str = "EMBEDDING_NORMALIZED";
+pub(crate) const METRIC_KEY: &str = "EMBEDDING_METRIC";
+
+/// Distance metric the vector index ranks embeddings by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DistanceMetric {
+  Cosine,
+}
+
+/// Describes the model that produced a set of embeddings.
+///
+/// The database records the model it was created for, and every call that
+/// writes or searches embeddings takes the caller's descriptor and refuses to
+/// run if the two disagree. Vectors from different models live in unrelated
+/// spaces, so mixing them would make search results meaningless without any
+/// error.
+///
+/// # Example
+/// ```
+/// use db::{DistanceMetric, EmbeddingModel};
+/// let model = EmbeddingModel {
+///    id: "Qwen/Qwen3-Embedding-0.6B".into(),
+///    dimension: 1024,
+///    normalized: true,
+///    metric: DistanceMetric::Cosine,
+///};
+/// assert_eq!(model, EmbeddingModel::QWEN3_EMBEDDING_0_6B);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingModel {
+  /// Model name, e.g. the Hugging Face repository id.
+  pub id: Cow<'static, str>,
+  pub dimension: usize,
+  /// Whether the model's vectors are L2-normalised.
+  pub normalized: bool,
+  pub metric: DistanceMetric,
+}
+
+impl EmbeddingModel {
+  /// The model the schema is sized for, and the one new databases record
+  /// unless told otherwise.
+  pub const QWEN3_EMBEDDING_0_6B: Self = Self {
+    id: Cow::Borrowed("Qwen/Qwen3-Embedding-0.6B"),
+    dimension: EMBEDDING_DIM,
+    normalized: true,
+    metric: DistanceMetric::Cosine,
+  };
+
+  /// Check that embeddings from `self` can be mixed with ones from `stored`.
+  ///
+  /// # Errors
+  /// Returns [`DatabaseError::EmbeddingModelMismatch`] naming the first field
+  /// that differs.
+  pub fn ensure_matches(&self, stored: &Self) -> Result<()> {
+    let fields = [
+      ("model id", stored.id.to_string(), self.id.to_string()),
+      (
+        "dimension",
+        stored.dimension.to_string(),
+        self.dimension.to_string(),
+      ),
+      (
+        "normalized",
+        stored.normalized.to_string(),
+        self.normalized.to_string(),
+      ),
+      ("metric", stored.metric.to_string(), self.metric.to_string()),
+    ];
+    for (field, stored, given) in fields {
+      if stored != given {
+        return Err(DatabaseError::EmbeddingModelMismatch {
+          field,
+          stored,
+          given,
+        });
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Default for EmbeddingModel {
+  fn default() -> Self {
+    Self::QWEN3_EMBEDDING_0_6B
+  }
+}
+
+/// Instruction prefix Qwen3-Embedding expects before retrieval queries.
+/// Documents are embedded without one.
+pub const QWEN3_QUERY_PREFIX: &str = "Instruct: Given a chat message, retrieve explanations of \
+  xkcd comics relevant to it\nQuery:";
+
+/// Turns text into embedding vectors, e.g. a local model or an HTTP
+/// embedding service.
+///
+/// Some models are trained to embed queries and documents differently and
+/// expect an instruction before the text. [`Embedder::embed_queries`] and
+/// [`Embedder::embed_documents`] add the prefixes an embedder asks for, so
+/// callers should use those rather than [`Embedder::embed`].
+pub trait Embedder: Send + Sync {
+  type Error: std::error::Error + Send + Sync + 'static;
+
+  /// The model behind this embedder.
+  fn model(&self) -> &EmbeddingModel;
+
+  /// Embed `texts` as given, returning one vector per text in the same
+  /// order.
+  fn embed(
+    &self,
+    texts: &[String],
+  ) -> impl Future<Output = std::result::Result<Vec<Vec<f32>>, Self::Error>> + Send;
+
+  fn model_id(&self) -> &str {
+    &self.model().id
+  }
+
+  fn dimension(&self) -> usize {
+    self.model().dimension
+  }
+
+  /// Text put before each search query, if the model wants one.
+  fn query_prefix(&self) -> Option<&str> {
+    None
+  }
+
+  /// Text put before each document, if the model wants one.
+  fn document_prefix(&self) -> Option<&str> {
+    None
+  }
+
+  /// Embed search queries, with the [`Embedder::query_prefix`].
+  fn embed_queries(
+    &self,
+    queries: &[String],
+  ) -> impl Future<Output = std::result::Result<Vec<Vec<f32>>, Self::Error>> + Send {
+    async move {
+      match self.query_prefix() {
+        Some(prefix) => self.embed(&with_prefix(prefix, queries)).await,
+        None => self.embed(queries).await,
+      }
+    }
+  }
+
+  /// Embed documents such as chunk texts, with the
+  /// [`Embedder::document_prefix`].
+  fn embed_documents(
+    &self,
+    documents: &[String],
+  ) -> impl Future<Output = std::result::Result<Vec<Vec<f32>>, Self::Error>> + Send {
+    async move {
+      match self.document_prefix() {
+        Some(prefix) => self.embed(&with_prefix(prefix, documents)).await,
+        None => self.embed(documents).await,
+      }
+    }
+  }
+}
+
+fn with_prefix(prefix: &str, texts: &[String]) -> Vec<String> {
+  texts.iter().map(|text| format!("{prefix}{text}")).collect()
+}
+
+/// Read the model recorded in the database.
+pub(crate) async fn read_model(conn: &Connection) -> Result<EmbeddingModel> {
+  let mut rows = conn
+    .query(
+      "SELECT key, value FROM metadata WHERE key IN (?1, ?2, ?3, ?4)",
+      params![MODEL_ID_KEY, DIMENSION_KEY, NORMALIZED_KEY, METRIC_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+
+  let (mut id, mut dimension, mut normalized, mut metric) = (None, None, None, None);
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let key: String = row
+      .get(0)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    let value: String = row
+      .get(1)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    let parse_failed = || DatabaseError::MetaParseFailed(format!("{key}={value}"));
+    match key.as_str() {
+      MODEL_ID_KEY => id = Some(value),
+      DIMENSION_KEY => dimension = Some(value.parse().map_err(|_| parse_failed())?),
+      NORMALIZED_KEY => normalized = Some(value.parse().map_err(|_| parse_failed())?),
+      METRIC_KEY => metric = Some(value.parse().map_err(|_| parse_failed())?),
+      _ => {}
+    }
+  }
+
+  let missing = |key: &str| DatabaseError::MetadataNotFound(key.to_string());
+  Ok(EmbeddingModel {
+    id: Cow::Owned(id.ok_or_else(|| missing(MODEL_ID_KEY))?),
+    dimension: dimension.ok_or_else(|| missing(DIMENSION_KEY))?,
+    normalized: normalized.ok_or_else(|| missing(NORMALIZED_KEY))?,
+    metric: metric.ok_or_else(|| missing(METRIC_KEY))?,
+  })
+}
+
+/// Fail unless `model` matches the model recorded in the database.
+pub(crate) async fn check_model(conn: &Connection, model: &EmbeddingModel) -> Result<()> {
+  model.ensure_matches(&read_model(conn).await?)
+}
+
+/// Fail unless the schema can store `model`'s vectors.
+pub(crate) fn validate_model(model: &EmbeddingModel) -> Result<()> {
+  if model.dimension != EMBEDDING_DIM {
+    return Err(DatabaseError::InvalidEmbeddingDimension(format!(
+      "Model {} has {} dimensions, the schema stores {}",
+      model.id, model.dimension, EMBEDDING_DIM
+    )));
+  }
+  Ok(())
+}
+
+/// Record `model` as the database's embedding model. Run it inside a
+/// transaction so readers never see half of the keys updated.
+pub(crate) async fn write_model(conn: &Connection, model: &EmbeddingModel) -> Result<()> {
+  validate_model(model)?;
+
+  let stmt = conn
+    .prepare("INSERT INTO metadata (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = ?2")
+    .await
+    .map_err(DatabaseError::prepare)?;
+  for (key, value) in [
+    (MODEL_ID_KEY, model.id.to_string()),
+    (DIMENSION_KEY, model.dimension.to_string()),
+    (NORMALIZED_KEY, model.normalized.to_string()),
+    (METRIC_KEY, model.metric.to_string()),
+  ] {
+    stmt
+      .execute(params![key, value])
+      .await
+      .map_err(DatabaseError::query)?;
+    stmt.reset();
+  }
+  Ok(())
+}
+
+impl Database {
+  /// The embedding model the stored vectors were produced by.
+  pub async fn embedding_model(&self) -> Result<EmbeddingModel> {
+    read_model(&*self.reader().await?).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::{make_chunk, make_comic};
+  use crate::{DatabaseOptions, SearchOptions};
+
+  fn other_model() -> EmbeddingModel {
+    EmbeddingModel {
+      id: "intfloat/multilingual-e5-large".into(),
+      ..EmbeddingModel::QWEN3_EMBEDDING_0_6B
+    }
+  }
+
+  /// Echoes each text back as its length, to show what was embedded.
+  struct LengthEmbedder {
+    prefix: Option<&'static str>,
+  }
+
+  impl Embedder for LengthEmbedder {
+    type Error = std::io::Error;
+
+    fn model(&self) -> &EmbeddingModel {
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B
+    }
+
+    async fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, Self::Error> {
+      Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+    }
+
+    fn query_prefix(&self) -> Option<&str> {
+      self.prefix
+    }
+  }
+
+  #[tokio::test]
+  async fn test_prefixes() {
+    let texts = vec!["abc".to_string()];
+    let plain = LengthEmbedder { prefix: None };
+    assert_eq!(plain.embed_queries(&texts).await.unwrap(), [[3.0]]);
+    assert_eq!(plain.dimension(), EMBEDDING_DIM);
+    assert_eq!(plain.model_id(), "Qwen/Qwen3-Embedding-0.6B");
+
+    let qwen = LengthEmbedder {
+      prefix: Some(QWEN3_QUERY_PREFIX),
+    };
+    let expected = (QWEN3_QUERY_PREFIX.len() + 3) as f32;
+    assert_eq!(qwen.embed_queries(&texts).await.unwrap(), [[expected]]);
+    // documents go in as they are
+    assert_eq!(qwen.embed_documents(&texts).await.unwrap(), [[3.0]]);
+    assert!(QWEN3_QUERY_PREFIX.ends_with("\nQuery:"));
+  }
+
+  #[tokio::test]
+  async fn test_new_database_records_model() {
+    let db = Database::new(":memory:").await.unwrap();
+    assert_eq!(
+      db.embedding_model().await.unwrap(),
+      EmbeddingModel::QWEN3_EMBEDDING_0_6B
+    );
+
+    let db = DatabaseOptions::in_memory()
+      .embedding_model(other_model())
+      .open()
+      .await
+      .unwrap();
+    assert_eq!(db.embedding_model().await.unwrap(), other_model());
+  }
+
+  #[tokio::test]
+  async fn test_wrong_dimension_refused_at_creation() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let result = DatabaseOptions::new(&path)
+      .embedding_model(EmbeddingModel {
+        dimension: 768,
+        ..other_model()
+      })
+      .open()
+      .await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::InvalidEmbeddingDimension(_))
+    ));
+    assert!(!path.exists());
+  }
+
+  #[tokio::test]
+  async fn test_mismatched_model_refused() {
+    let db = Database::new(":memory:").await.unwrap();
+    db.insert_comic(make_comic(1)).await.unwrap();
+    db.insert_chunk(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, make_chunk(1, 0))
+      .await
+      .unwrap();
+
+    let result = db.insert_chunk(&other_model(), make_chunk(1, 0)).await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::EmbeddingModelMismatch {
+        field: "model id",
+        ..
+      })
+    ));
+    let result = db
+      .vector_search(&other_model(), vec![1.0; EMBEDDING_DIM], 5)
+      .await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::EmbeddingModelMismatch { .. })
+    ));
+    let result = db
+      .hybrid_search(
+        &other_model(),
+        "chunk",
+        vec![1.0; EMBEDDING_DIM],
+        &SearchOptions::new(5),
+      )
+      .await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::EmbeddingModelMismatch { .. })
+    ));
+    assert_eq!(db.get_chunks_for_comic(1).await.unwrap().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_existing_file_checked_against_options() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    Database::new(&path).await.unwrap();
+
+    let result = DatabaseOptions::new(&path)
+      .embedding_model(other_model())
+      .open()
+      .await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::EmbeddingModelMismatch { .. })
+    ));
+    assert!(
+      DatabaseOptions::new(&path)
+        .embedding_model(EmbeddingModel::QWEN3_EMBEDDING_0_6B)
+        .open()
+        .await
+        .is_ok()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_legacy_file_records_original_model() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let raw = libsql::Builder::new_local(&path).build().await.unwrap();
+    raw
+      .connect()
+      .unwrap()
+      .execute_batch(crate::migrations::MIGRATIONS[0].sql)
+      .await
+      .unwrap();
+    drop(raw);
+
+    let db = Database::new(&path).await.unwrap();
+    assert_eq!(
+      db.embedding_model().await.unwrap(),
+      EmbeddingModel::QWEN3_EMBEDDING_0_6B
+    );
+  }
+}
@@ -1,3 +1,5 @@
+use libsql::ffi;
+use strum::Display;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, DatabaseError>;
@@ -18,6 +20,23 @@ pub enum DatabaseError {
   #[error("Database not initialized")]
   NotInitialized,
 
+  /// Database was written by a newer binary than this one
+  #[error("Database schema version {found} is newer than supported version {supported}")]
+  SchemaTooNew { found: u32, supported: u32 },
+
+  /// Database is older than this binary and can't be migrated because it
+  /// was opened read-only
+  #[error("Database schema version {found} is older than required version {required}")]
+  SchemaTooOld { found: u32, required: u32 },
+
+  /// A write was attempted on a database opened read-only
+  #[error("Database is opened read-only")]
+  ReadOnly,
+
+  /// A schema migration failed and was rolled back
+  #[error("Migration {version} failed: {reason}")]
+  MigrationFailed { version: u32, reason: String },
+
   // ========================================================================
   // Data Validation Errors
   // ========================================================================
@@ -33,10 +52,30 @@ pub enum DatabaseError {
   #[error("Invalid chunk index: {0}")]
   InvalidChunkIndex(u64),
 
+  /// Embeddings from a different model than the one the database holds
+  #[error("Embedding model mismatch: database has {field} {stored}, got {given}")]
+  EmbeddingModelMismatch {
+    field: &'static str,
+    stored: String,
+    given: String,
+  },
+
+  /// An [`Embedder`](crate::Embedder) failed or returned unusable vectors
+  #[error("Embedding failed: {0}")]
+  Embedding(String),
+
+  /// A re-embedding job can't start or continue
+  #[error("Re-embedding conflict: {0}")]
+  ReembedConflict(String),
+
   /// Empty or invalid content
   #[error("Invalid content: {0}")]
   InvalidContent(String),
 
+  /// Vector index options out of range or unsuited to the embedding model
+  #[error("Invalid vector index options: {0}")]
+  InvalidIndexOptions(String),
+
   // ========================================================================
   // Not Found Errors
   // ========================================================================
@@ -52,12 +91,42 @@ pub enum DatabaseError {
   // Conflict Errors
   // ========================================================================
   /// Comic already exists (duplicate primary key)
-  #[error("Comic already exists: {0}")]
-  ComicAlreadyExists(u64),
+  #[error("Comic already exists: {comic_number}")]
+  ComicAlreadyExists {
+    comic_number: u64,
+    #[source]
+    source: libsql::Error,
+  },
+
+  /// A write carried an older wiki revision than the one already stored
+  #[error(
+    "Comic {comic_number} is at revision {stored}, refusing to write older revision {attempted}"
+  )]
+  StaleRevision {
+    comic_number: u64,
+    stored: u64,
+    attempted: u64,
+  },
 
   /// Constraint violation (e.g., foreign key)
-  #[error("Constraint violation: {0}")]
-  ConstraintViolation(String),
+  #[error("{constraint} constraint violated: {source}")]
+  ConstraintViolation {
+    constraint: Constraint,
+    #[source]
+    source: libsql::Error,
+  },
+
+  /// Another connection holds a lock and the busy timeout ran out
+  #[error("Database is busy: {0}")]
+  Busy(#[source] libsql::Error),
+
+  /// A table is locked by another statement on the same connection
+  #[error("Database table is locked: {0}")]
+  Locked(#[source] libsql::Error),
+
+  /// The database file is damaged or isn't a database
+  #[error("Database is corrupt: {0}")]
+  Corrupt(#[source] libsql::Error),
 
   // ========================================================================
   // Query Errors
@@ -94,6 +163,14 @@ pub enum DatabaseError {
   #[error("Failed to serialize/deserialize data: {0}")]
   Serialization(String),
 
+  /// A snapshot file is malformed or doesn't match its manifest
+  #[error("Invalid snapshot: {0}")]
+  Snapshot(String),
+
+  /// A backup couldn't be written or failed verification
+  #[error("Backup failed: {0}")]
+  Backup(String),
+
   /// Failed to convert section type
   #[error("Failed to convert section type: {0}")]
   InvalidSectionType(String),
@@ -115,3 +192,156 @@ pub enum DatabaseError {
   #[error("libSQL error: {0}")]
   LibSql(#[from] libsql::Error),
 }
+
+/// The kind of constraint behind a [`DatabaseError::ConstraintViolation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Constraint {
+  /// `UNIQUE` or `PRIMARY KEY`
+  #[strum(serialize = "UNIQUE")]
+  Unique,
+  #[strum(serialize = "FOREIGN KEY")]
+  ForeignKey,
+  #[strum(serialize = "NOT NULL")]
+  NotNull,
+  #[strum(serialize = "CHECK")]
+  Check,
+  /// Any other constraint, e.g. a trigger calling `RAISE`
+  #[strum(serialize = "other")]
+  Other,
+}
+
+impl DatabaseError {
+  /// Whether the same operation may succeed if tried again later, because it
+  /// failed on a lock rather than on the data.
+  pub fn is_retryable(&self) -> bool {
+    matches!(self, Self::Busy(_) | Self::Locked(_))
+  }
+
+  /// Classify a libSQL error by its SQLite result code, falling back to
+  /// `fallback` with the message for codes without a variant of their own.
+  fn classify(e: libsql::Error, fallback: fn(String) -> Self) -> Self {
+    let libsql::Error::SqliteFailure(code, _) = &e else {
+      return fallback(e.to_string());
+    };
+    let code = *code;
+    // the low byte is the primary result code, the rest says which kind
+    match code & 0xff {
+      ffi::SQLITE_BUSY => Self::Busy(e),
+      ffi::SQLITE_LOCKED => Self::Locked(e),
+      ffi::SQLITE_CORRUPT | ffi::SQLITE_NOTADB => Self::Corrupt(e),
+      ffi::SQLITE_CONSTRAINT => Self::ConstraintViolation {
+        constraint: match code {
+          ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY => Constraint::Unique,
+          ffi::SQLITE_CONSTRAINT_FOREIGNKEY => Constraint::ForeignKey,
+          ffi::SQLITE_CONSTRAINT_NOTNULL => Constraint::NotNull,
+          ffi::SQLITE_CONSTRAINT_CHECK => Constraint::Check,
+          _ => Constraint::Other,
+        },
+        source: e,
+      },
+      _ => fallback(e.to_string()),
+    }
+  }
+
+  /// Map a failed query, falling back to [`DatabaseError::QueryFailed`].
+  pub(crate) fn query(e: libsql::Error) -> Self {
+    Self::classify(e, Self::QueryFailed)
+  }
+
+  /// Map a failed prepare, falling back to [`DatabaseError::PreparedFailed`].
+  pub(crate) fn prepare(e: libsql::Error) -> Self {
+    Self::classify(e, Self::PreparedFailed)
+  }
+
+  /// Map a failed begin or commit, falling back to
+  /// [`DatabaseError::TransactionFailed`].
+  pub(crate) fn transaction(e: libsql::Error) -> Self {
+    Self::classify(e, Self::TransactionFailed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+  use crate::test_support::make_comic;
+  use crate::{Database, DatabaseOptions};
+
+  fn failure(code: i32) -> libsql::Error {
+    libsql::Error::SqliteFailure(code, "failed".to_string())
+  }
+
+  #[test]
+  fn test_classify_result_codes() {
+    assert!(matches!(
+      DatabaseError::query(failure(ffi::SQLITE_CONSTRAINT_PRIMARYKEY)),
+      DatabaseError::ConstraintViolation {
+        constraint: Constraint::Unique,
+        ..
+      }
+    ));
+    assert!(matches!(
+      DatabaseError::transaction(failure(ffi::SQLITE_CONSTRAINT_FOREIGNKEY)),
+      DatabaseError::ConstraintViolation {
+        constraint: Constraint::ForeignKey,
+        ..
+      }
+    ));
+    assert!(matches!(
+      DatabaseError::query(failure(ffi::SQLITE_CONSTRAINT_TRIGGER)),
+      DatabaseError::ConstraintViolation {
+        constraint: Constraint::Other,
+        ..
+      }
+    ));
+    assert!(DatabaseError::query(failure(ffi::SQLITE_BUSY_SNAPSHOT)).is_retryable());
+    assert!(DatabaseError::prepare(failure(ffi::SQLITE_LOCKED)).is_retryable());
+    assert!(matches!(
+      DatabaseError::query(failure(ffi::SQLITE_NOTADB)),
+      DatabaseError::Corrupt(_)
+    ));
+    assert!(matches!(
+      DatabaseError::prepare(failure(ffi::SQLITE_ERROR)),
+      DatabaseError::PreparedFailed(_)
+    ));
+    assert!(matches!(
+      DatabaseError::query(libsql::Error::NullValue),
+      DatabaseError::QueryFailed(_)
+    ));
+  }
+
+  #[test]
+  fn test_source_is_kept() {
+    let e = DatabaseError::query(failure(ffi::SQLITE_BUSY));
+    let source = std::error::Error::source(&e).unwrap();
+    assert!(matches!(
+      source.downcast_ref::<libsql::Error>(),
+      Some(libsql::Error::SqliteFailure(ffi::SQLITE_BUSY, _))
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_locked_database_is_busy_and_retryable() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    Database::new(&path).await.unwrap();
+    let db = DatabaseOptions::new(&path)
+      .busy_timeout(Duration::from_millis(50))
+      .open()
+      .await
+      .unwrap();
+
+    // another process holding the write lock
+    let other = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = other.connect().unwrap();
+    conn.execute("BEGIN IMMEDIATE", ()).await.unwrap();
+
+    let e = db.insert_comic(make_comic(1)).await.unwrap_err();
+    assert!(matches!(e, DatabaseError::Busy(_)), "{e:?}");
+    assert!(e.is_retryable());
+
+    conn.execute("COMMIT", ()).await.unwrap();
+    db.insert_comic(make_comic(1)).await.unwrap();
+  }
+}
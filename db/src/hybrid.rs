@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::chunks::{validate_embedding, vec_to_json_string};
+use crate::embedding::EmbeddingModel;
+use crate::error::{DatabaseError, Result};
+use crate::search::{
+  ChunkSearchResult, FILTER_SQL, RESULT_COLUMNS, SearchOptions, row_to_search_result,
+};
+
+/// The `k` constant of reciprocal rank fusion. 60 is the value from the
+/// original paper and dampens the advantage of the very first ranks.
+pub const RRF_K: f64 = 60.0;
+
+/// How many candidates each signal contributes per requested result.
+const CANDIDATE_FACTOR: usize = 4;
+
+/// A chunk found by [`Database::hybrid_search`].
+///
+/// Carries the fused score together with the chunk's rank in each of the two
+/// result lists, so callers can see why it was returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HybridSearchResult {
+  /// The matching chunk. `distance` is filled in for lexical-only hits too.
+  pub chunk: ChunkSearchResult,
+  /// Reciprocal rank fusion score, higher is better.
+  pub score: f64,
+  /// 1-based rank in the vector search results, if the chunk was among them.
+  pub vector_rank: Option<usize>,
+  /// 1-based rank in the BM25 full-text results, if the chunk was among them.
+  pub lexical_rank: Option<usize>,
+  /// The raw FTS5 `bm25()` value (lower is better), if lexically matched.
+  pub bm25: Option<f64>,
+}
+
+/// Turn free text into an FTS5 query that ORs every word together.
+///
+/// Each word is quoted so that user input can't inject FTS5 syntax (`-`, `:`,
+/// `NEAR`, unbalanced quotes). Returns `None` if the text has no words.
+pub(crate) fn fts_match_query(text: &str) -> Option<String> {
+  let terms: Vec<String> = text
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|t| !t.is_empty())
+    .map(|t| format!("\"{t}\""))
+    .collect();
+  if terms.is_empty() {
+    None
+  } else {
+    Some(terms.join(" OR "))
+  }
+}
+
+impl Database {
+  /// Full-text search over chunk text and the comic number, title and hover
+  /// text, ranked by BM25.
+  ///
+  /// Returns each hit with its `bm25()` value. `distance` is computed against
+  /// the query vector (as `vector32` JSON) so the results line up with vector
+  /// search results.
+  pub(crate) async fn lexical_search(
+    &self,
+    query_text: &str,
+    query_vec_json: &str,
+    options: &SearchOptions,
+  ) -> Result<Vec<(ChunkSearchResult, f64)>> {
+    let Some(match_query) = fts_match_query(query_text) else {
+      return Ok(Vec::new());
+    };
+
+    let sql = format!(
+      "SELECT {RESULT_COLUMNS},
+        COALESCE(vector_distance_cos(xc.embedding, vector32(:query)), 1.0) AS distance,
+        bm25(xkcd_chunks_fts) AS rank
+      FROM xkcd_chunks_fts
+      JOIN xkcd_chunks xc ON xc.id = xkcd_chunks_fts.rowid
+      JOIN xkcd_comics c ON c.comic_number = xc.comic_number
+      WHERE xkcd_chunks_fts MATCH :match AND {FILTER_SQL}
+      ORDER BY rank ASC
+      LIMIT :limit"
+    );
+    let conn = self.reader().await?;
+    let stmt = conn.prepare(&sql).await.map_err(DatabaseError::prepare)?;
+
+    let mut params = options.filter_params();
+    params.push((":query".to_string(), query_vec_json.into()));
+    params.push((":match".to_string(), match_query.into()));
+    params.push((":limit".to_string(), (options.top_k as i64).into()));
+
+    let mut rows = stmt.query(params).await.map_err(DatabaseError::query)?;
+
+    let mut results = Vec::new();
+    while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+      let bm25: f64 = row
+        .get(8)
+        .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+      results.push((row_to_search_result(&row)?, bm25));
+    }
+    Ok(results)
+  }
+
+  /// Search with both BM25 full-text matching and vector similarity, fused
+  /// with reciprocal rank fusion.
+  ///
+  /// Catches exact-term queries ("Bobby Tables", "927") that embeddings miss
+  /// while keeping semantic matches. Both signals honour the filters in
+  /// `options`, and each contributes `top_k * 4` candidates before fusion.
+  ///
+  /// # Errors
+  /// Returns an error if `model` isn't the model recorded in the database, the
+  /// embedding dimension doesn't match `EMBEDDING_DIM`, or either query fails.
+  pub async fn hybrid_search(
+    &self,
+    model: &EmbeddingModel,
+    query_text: &str,
+    query_embedding: Vec<f32>,
+    options: &SearchOptions,
+  ) -> Result<Vec<HybridSearchResult>> {
+    validate_embedding(&query_embedding)?;
+    if options.top_k == 0 {
+      return Ok(Vec::new());
+    }
+
+    let candidates = SearchOptions {
+      top_k: options.top_k.saturating_mul(CANDIDATE_FACTOR),
+      ..options.clone()
+    };
+    let query_vec_json = vec_to_json_string(query_embedding.clone());
+    // the vector side checks the model, so run it first
+    let semantic = self
+      .vector_search_with_options(model, query_embedding, &candidates)
+      .await?;
+    let lexical = self
+      .lexical_search(query_text, &query_vec_json, &candidates)
+      .await?;
+
+    let mut fused: HashMap<u64, HybridSearchResult> = HashMap::new();
+    for (rank, chunk) in (1..).zip(semantic) {
+      fused.insert(
+        chunk.chunk_id,
+        HybridSearchResult {
+          chunk,
+          score: 1.0 / (RRF_K + rank as f64),
+          vector_rank: Some(rank),
+          lexical_rank: None,
+          bm25: None,
+        },
+      );
+    }
+    for (rank, (chunk, bm25)) in (1..).zip(lexical) {
+      let entry = fused
+        .entry(chunk.chunk_id)
+        .or_insert_with(|| HybridSearchResult {
+          chunk,
+          score: 0.0,
+          vector_rank: None,
+          lexical_rank: None,
+          bm25: None,
+        });
+      entry.score += 1.0 / (RRF_K + rank as f64);
+      entry.lexical_rank = Some(rank);
+      entry.bm25 = Some(bm25);
+    }
+
+    let mut results: Vec<HybridSearchResult> = fused.into_values().collect();
+    results.sort_by(|a, b| {
+      b.score
+        .total_cmp(&a.score)
+        .then(a.chunk.distance.total_cmp(&b.chunk.distance))
+    });
+    results.truncate(options.top_k);
+    Ok(results)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::EMBEDDING_DIM;
+  use crate::models::{Chunks, Comics};
+  use crate::test_support::{make_chunk, make_comic, setup_db};
+
+  fn text_chunk(comic: u64, text: &str, embedding: Vec<f32>) -> Chunks {
+    Chunks {
+      chunk_text: text.to_string(),
+      embedding,
+      ..make_chunk(comic, 0)
+    }
+  }
+
+  fn orthogonal() -> Vec<f32> {
+    (0..EMBEDDING_DIM)
+      .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+      .collect()
+  }
+
+  /// Comic 327 only matches lexically, 1 and 2 only semantically.
+  async fn setup_corpus() -> Database {
+    let db = setup_db().await;
+    db.insert_comic(Comics {
+      title: "Barrel - Part 1".to_string(),
+      ..make_comic(1)
+    })
+    .await
+    .unwrap();
+    db.insert_comic(Comics {
+      title: "Petit Trees (sketch)".to_string(),
+      ..make_comic(2)
+    })
+    .await
+    .unwrap();
+    db.insert_comic(Comics {
+      title: "Exploits of a Mom".to_string(),
+      ..make_comic(327)
+    })
+    .await
+    .unwrap();
+    db.insert_chunk(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      text_chunk(1, "A boy floats in a barrel.", vec![1.0; EMBEDDING_DIM]),
+    )
+    .await
+    .unwrap();
+    db.insert_chunk(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      text_chunk(
+        2,
+        "Some small trees.",
+        (0..EMBEDDING_DIM)
+          .map(|i| if i % 2 == 0 { 1.0 } else { 0.8 })
+          .collect(),
+      ),
+    )
+    .await
+    .unwrap();
+    db.insert_chunk(
+      &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+      text_chunk(
+        327,
+        "Little Bobby Tables: Robert'); DROP TABLE Students;--",
+        orthogonal(),
+      ),
+    )
+    .await
+    .unwrap();
+    db
+  }
+
+  #[test]
+  fn test_fts_match_query_quotes_terms() {
+    assert_eq!(
+      fts_match_query("Robert'); DROP TABLE -- \"x").as_deref(),
+      Some("\"Robert\" OR \"DROP\" OR \"TABLE\" OR \"x\"")
+    );
+    assert_eq!(fts_match_query("  ;-- "), None);
+  }
+
+  #[tokio::test]
+  async fn test_hybrid_finds_exact_term() {
+    let db = setup_corpus().await;
+    let results = db
+      .hybrid_search(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        "bobby tables",
+        vec![1.0; EMBEDDING_DIM],
+        &SearchOptions::new(3),
+      )
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 3);
+    let bobby = results
+      .iter()
+      .find(|r| r.chunk.comic_number == 327)
+      .unwrap();
+    assert_eq!(bobby.lexical_rank, Some(1));
+    assert!(bobby.bm25.is_some());
+    assert!((bobby.chunk.distance - 1.0).abs() < 1e-4);
+    // the best semantic hit has no lexical match
+    let barrel = results.iter().find(|r| r.chunk.comic_number == 1).unwrap();
+    assert_eq!(barrel.vector_rank, Some(1));
+    assert_eq!(barrel.lexical_rank, None);
+  }
+
+  #[tokio::test]
+  async fn test_hybrid_both_signals_rank_first() {
+    let db = setup_corpus().await;
+    let results = db
+      .hybrid_search(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        "barrel",
+        vec![1.0; EMBEDDING_DIM],
+        &SearchOptions::new(3),
+      )
+      .await
+      .unwrap();
+    assert_eq!(results[0].chunk.comic_number, 1);
+    assert_eq!(results[0].vector_rank, Some(1));
+    assert_eq!(results[0].lexical_rank, Some(1));
+    assert!((results[0].score - 2.0 / (RRF_K + 1.0)).abs() < 1e-9);
+  }
+
+  #[tokio::test]
+  async fn test_lexical_matches_comic_number_and_title() {
+    let db = setup_corpus().await;
+    let query = vec_to_json_string(vec![1.0; EMBEDDING_DIM]);
+    let by_number = db
+      .lexical_search("327", &query, &SearchOptions::new(5))
+      .await
+      .unwrap();
+    assert_eq!(by_number.len(), 1);
+    assert_eq!(by_number[0].0.comic_number, 327);
+
+    let by_title = db
+      .lexical_search("exploits", &query, &SearchOptions::new(5))
+      .await
+      .unwrap();
+    assert_eq!(by_title.len(), 1);
+    assert_eq!(by_title[0].0.comic_number, 327);
+  }
+
+  #[tokio::test]
+  async fn test_hybrid_respects_filters() {
+    let db = setup_corpus().await;
+    let options = SearchOptions {
+      exclude_comics: vec![327],
+      ..SearchOptions::new(3)
+    };
+    let results = db
+      .hybrid_search(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        "bobby tables",
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert!(results.iter().all(|r| r.chunk.comic_number != 327));
+  }
+
+  #[tokio::test]
+  async fn test_fts_follows_deletes_and_title_changes() {
+    let db = setup_corpus().await;
+    let query = vec_to_json_string(vec![1.0; EMBEDDING_DIM]);
+
+    db.writer()
+      .await
+      .unwrap()
+      .execute(
+        "UPDATE xkcd_comics SET title = 'Little Bobby' WHERE comic_number = 1",
+        (),
+      )
+      .await
+      .unwrap();
+    let renamed = db
+      .lexical_search("little", &query, &SearchOptions::new(5))
+      .await
+      .unwrap();
+    assert_eq!(renamed.len(), 2);
+
+    db.delete_comic(327).await.unwrap();
+    let deleted = db
+      .lexical_search("bobby tables", &query, &SearchOptions::new(5))
+      .await
+      .unwrap();
+    let comics: Vec<u64> = deleted.iter().map(|(c, _)| c.comic_number).collect();
+    assert_eq!(comics, vec![1]);
+  }
+
+  #[tokio::test]
+  async fn test_migration_backfills_fts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+
+    // a version 1 database with content in it
+    let raw = libsql::Builder::new_local(&path).build().await.unwrap();
+    let conn = raw.connect().unwrap();
+    conn
+      .execute_batch(include_str!("../migrations/001_schema.sql"))
+      .await
+      .unwrap();
+    conn
+      .execute_batch(&format!(
+        "INSERT INTO xkcd_comics VALUES (927, 'Standards', 'u', 'x', NULL, 1, '20250127000000', 's', 'u');
+         INSERT INTO xkcd_chunks (comic_number, chunk_text, chunk_index, section_type, embedding)
+         VALUES (927, 'competing standards', 0, 'explanation', vector32('{}'));",
+        vec_to_json_string(vec![1.0; EMBEDDING_DIM])
+      ))
+      .await
+      .unwrap();
+    drop(conn);
+    drop(raw);
+
+    let db = Database::new(&path).await.unwrap();
+    let query = vec_to_json_string(vec![1.0; EMBEDDING_DIM]);
+    let results = db
+      .lexical_search("standard", &query, &SearchOptions::new(5))
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0.comic_number, 927);
+  }
+}
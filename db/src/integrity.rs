@@ -0,0 +1,663 @@
+use libsql::params::IntoParams;
+use libsql::{Connection, TransactionBehavior, params};
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::EMBEDDING_DIM;
+use crate::embedding::{DIMENSION_KEY, DistanceMetric, EmbeddingModel, METRIC_KEY, NORMALIZED_KEY};
+use crate::error::{DatabaseError, Result};
+use crate::migrations::SCHEMA_VERSION_KEY;
+use crate::reembed::{CHECKPOINT_KEY, TARGET_KEY};
+use crate::storage::{EmbeddingStorage, STORAGE_KEY, read_storage, requantize};
+use crate::vector_index::{VECTOR_INDEX_OPTIONS_KEY, VectorIndexOptions, read_index_options};
+
+/// Name of the vector index and of the table libSQL keeps its graph in.
+pub(crate) const VECTOR_INDEX: &str = "chunks_vec_idx";
+const VECTOR_INDEX_SHADOW: &str = "chunks_vec_idx_shadow";
+
+/// A chunk whose embedding blob isn't `EMBEDDING_DIM` little-endian `f32`s.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BadEmbedding {
+  pub chunk_id: u64,
+  pub comic_number: u64,
+  /// Length of the stored blob in bytes.
+  pub bytes: u64,
+}
+
+/// A comic whose `chunk_index` values aren't exactly `0..n`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkIndexGap {
+  pub comic_number: u64,
+  /// Indexes below the highest one that no chunk has.
+  pub missing: Vec<u64>,
+  /// Indexes held by more than one chunk.
+  pub duplicates: Vec<u64>,
+}
+
+/// A row whose foreign key points at a row that no longer exists, which can
+/// only happen if it was written with `foreign_keys` off.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanedRow {
+  pub table: String,
+  pub rowid: u64,
+  /// The table the missing parent row belongs in.
+  pub parent: String,
+}
+
+/// A `metadata` row this crate reads whose value it can't parse.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BadMetadata {
+  pub key: String,
+  pub value: String,
+  pub reason: String,
+}
+
+/// How the vector index compares with the `xkcd_chunks` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorIndexCheck {
+  /// Whether `chunks_vec_idx` exists at all.
+  pub present: bool,
+  /// Chunks the index doesn't know about.
+  pub missing: Vec<u64>,
+  /// Index entries for chunks that no longer exist.
+  pub stale: Vec<u64>,
+}
+
+impl VectorIndexCheck {
+  pub fn is_consistent(&self) -> bool {
+    self.present && self.missing.is_empty() && self.stale.is_empty()
+  }
+}
+
+/// Which problems [`Database::repair_integrity`] fixes. Nothing is fixed by
+/// default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepairOptions {
+  /// Delete chunks with a malformed embedding blob.
+  pub delete_bad_embeddings: bool,
+  /// Delete orphaned rows.
+  pub delete_orphans: bool,
+  /// Renumber each affected comic's chunks `0..n`, keeping their order.
+  pub renumber_chunks: bool,
+  /// Rebuild the vector index if it is missing or disagrees with the table.
+  pub rebuild_vector_index: bool,
+}
+
+impl RepairOptions {
+  /// Fix everything that can be fixed without losing good data.
+  #[must_use]
+  pub fn all() -> Self {
+    Self {
+      delete_bad_embeddings: true,
+      delete_orphans: true,
+      renumber_chunks: true,
+      rebuild_vector_index: true,
+    }
+  }
+}
+
+/// Something [`Database::repair_integrity`] changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairAction {
+  DeletedBadEmbeddings { chunk_ids: Vec<u64> },
+  DeletedOrphans { count: u64 },
+  RenumberedChunks { comic_number: u64 },
+  RebuiltVectorIndex,
+}
+
+/// Result of an integrity check.
+///
+/// Comics without chunks and unparseable metadata are only reported; the
+/// first may be a scrape in progress and the second needs a person to decide
+/// the right value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+  pub bad_embeddings: Vec<BadEmbedding>,
+  pub empty_comics: Vec<u64>,
+  pub chunk_index_gaps: Vec<ChunkIndexGap>,
+  pub orphans: Vec<OrphanedRow>,
+  pub bad_metadata: Vec<BadMetadata>,
+  pub vector_index: VectorIndexCheck,
+  /// What was repaired, in order. Always empty for
+  /// [`Database::check_integrity`].
+  pub repairs: Vec<RepairAction>,
+}
+
+impl IntegrityReport {
+  /// Whether no problems were found.
+  pub fn is_ok(&self) -> bool {
+    self.bad_embeddings.is_empty()
+      && self.empty_comics.is_empty()
+      && self.chunk_index_gaps.is_empty()
+      && self.orphans.is_empty()
+      && self.bad_metadata.is_empty()
+      && self.vector_index.is_consistent()
+  }
+}
+
+async fn query_u64s(conn: &Connection, sql: &str, params: impl IntoParams) -> Result<Vec<u64>> {
+  let mut rows = conn
+    .query(sql, params)
+    .await
+    .map_err(DatabaseError::query)?;
+  let mut values = Vec::new();
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    values.push(
+      row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+    );
+  }
+  Ok(values)
+}
+
+async fn bad_embeddings(conn: &Connection) -> Result<Vec<BadEmbedding>> {
+  let mut rows = conn
+    .query(
+      "SELECT id, comic_number, length(embedding) FROM xkcd_chunks
+       WHERE embedding IS NULL OR typeof(embedding) != 'blob' OR length(embedding) != ?
+       ORDER BY id",
+      params![(EMBEDDING_DIM * 4) as i64],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let mut bad = Vec::new();
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    bad.push(BadEmbedding {
+      chunk_id: row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+      comic_number: row
+        .get(1)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+      bytes: row
+        .get::<Option<u64>>(2)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?
+        .unwrap_or(0),
+    });
+  }
+  Ok(bad)
+}
+
+async fn chunk_index_gaps(conn: &Connection) -> Result<Vec<ChunkIndexGap>> {
+  let mut rows = conn
+    .query(
+      "SELECT comic_number, chunk_index FROM xkcd_chunks ORDER BY comic_number, chunk_index",
+      (),
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+
+  let mut gaps = Vec::new();
+  let mut current: Option<(u64, Vec<u64>)> = None;
+  let finish = |comic_number: u64, indexes: Vec<u64>, gaps: &mut Vec<ChunkIndexGap>| {
+    let mut missing = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut expected = 0;
+    for index in indexes {
+      if index < expected {
+        if duplicates.last() != Some(&index) {
+          duplicates.push(index);
+        }
+        continue;
+      }
+      missing.extend(expected..index);
+      expected = index + 1;
+    }
+    if !missing.is_empty() || !duplicates.is_empty() {
+      gaps.push(ChunkIndexGap {
+        comic_number,
+        missing,
+        duplicates,
+      });
+    }
+  };
+
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let comic_number: u64 = row
+      .get(0)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    let chunk_index: u64 = row
+      .get(1)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    match &mut current {
+      Some((comic, indexes)) if *comic == comic_number => indexes.push(chunk_index),
+      _ => {
+        if let Some((comic, indexes)) = current.take() {
+          finish(comic, indexes, &mut gaps);
+        }
+        current = Some((comic_number, vec![chunk_index]));
+      }
+    }
+  }
+  if let Some((comic, indexes)) = current {
+    finish(comic, indexes, &mut gaps);
+  }
+  Ok(gaps)
+}
+
+async fn orphans(conn: &Connection) -> Result<Vec<OrphanedRow>> {
+  let mut rows = conn
+    .query("PRAGMA foreign_key_check", ())
+    .await
+    .map_err(DatabaseError::query)?;
+  let mut orphans = Vec::new();
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    orphans.push(OrphanedRow {
+      table: row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+      rowid: row
+        .get(1)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+      parent: row
+        .get(2)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+    });
+  }
+  Ok(orphans)
+}
+
+/// Why `value` isn't valid for `key`, for the keys this crate reads.
+fn metadata_problem(key: &str, value: &str) -> Option<String> {
+  fn check<T: std::str::FromStr>(value: &str, what: &str) -> Option<String> {
+    value.parse::<T>().err().map(|_| format!("expected {what}"))
+  }
+  match key {
+    "INITIALIZED" => (value != "true").then(|| "expected \"true\"".to_string()),
+    SCHEMA_VERSION_KEY => check::<u32>(value, "a schema version"),
+    DIMENSION_KEY => check::<usize>(value, "a dimension"),
+    NORMALIZED_KEY => check::<bool>(value, "true or false"),
+    METRIC_KEY => check::<DistanceMetric>(value, "a distance metric"),
+    CHECKPOINT_KEY => check::<u64>(value, "a chunk id"),
+    TARGET_KEY => serde_json::from_str::<EmbeddingModel>(value)
+      .err()
+      .map(|e| format!("expected an embedding model: {e}")),
+    STORAGE_KEY => check::<EmbeddingStorage>(value, "an embedding storage mode"),
+    VECTOR_INDEX_OPTIONS_KEY => serde_json::from_str::<VectorIndexOptions>(value)
+      .err()
+      .map(|e| format!("expected vector index options: {e}")),
+    _ => None,
+  }
+}
+
+async fn bad_metadata(conn: &Connection) -> Result<Vec<BadMetadata>> {
+  let mut rows = conn
+    .query("SELECT key, value FROM metadata ORDER BY key", ())
+    .await
+    .map_err(DatabaseError::query)?;
+  let mut bad = Vec::new();
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let key: String = row
+      .get(0)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    let value: String = row
+      .get(1)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    if let Some(reason) = metadata_problem(&key, &value) {
+      bad.push(BadMetadata { key, value, reason });
+    }
+  }
+  Ok(bad)
+}
+
+/// The `CREATE INDEX` statement of the vector index, if it exists.
+pub(crate) async fn vector_index_sql(conn: &Connection) -> Result<Option<String>> {
+  let mut rows = conn
+    .query(
+      "SELECT sql FROM sqlite_master WHERE type = 'index' AND name = ?",
+      params![VECTOR_INDEX],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  match rows.next().await.map_err(DatabaseError::query)? {
+    Some(row) => Ok(Some(
+      row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+    )),
+    None => Ok(None),
+  }
+}
+
+async fn vector_index(conn: &Connection) -> Result<VectorIndexCheck> {
+  if vector_index_sql(conn).await?.is_none() {
+    return Ok(VectorIndexCheck::default());
+  }
+  Ok(VectorIndexCheck {
+    present: true,
+    missing: query_u64s(
+      conn,
+      &format!(
+        "SELECT id FROM xkcd_chunks
+         WHERE id NOT IN (SELECT index_key FROM {VECTOR_INDEX_SHADOW}) ORDER BY id"
+      ),
+      (),
+    )
+    .await?,
+    stale: query_u64s(
+      conn,
+      &format!(
+        "SELECT index_key FROM {VECTOR_INDEX_SHADOW}
+         WHERE index_key NOT IN (SELECT id FROM xkcd_chunks) ORDER BY index_key"
+      ),
+      (),
+    )
+    .await?,
+  })
+}
+
+async fn inspect(conn: &Connection) -> Result<IntegrityReport> {
+  Ok(IntegrityReport {
+    bad_embeddings: bad_embeddings(conn).await?,
+    empty_comics: query_u64s(
+      conn,
+      "SELECT comic_number FROM xkcd_comics c
+       WHERE NOT EXISTS (SELECT 1 FROM xkcd_chunks xc WHERE xc.comic_number = c.comic_number)
+       ORDER BY comic_number",
+      (),
+    )
+    .await?,
+    chunk_index_gaps: chunk_index_gaps(conn).await?,
+    orphans: orphans(conn).await?,
+    bad_metadata: bad_metadata(conn).await?,
+    vector_index: vector_index(conn).await?,
+    repairs: Vec::new(),
+  })
+}
+
+/// Drop and recreate the vector index with its current definition. In a
+/// quantized database the compact vectors are recomputed first.
+pub(crate) async fn rebuild_vector_index_in(conn: &Connection) -> Result<()> {
+  let storage = read_storage(conn).await?;
+  // a missing index is rebuilt from the options it was last built with
+  let sql = match vector_index_sql(conn).await? {
+    Some(sql) => sql,
+    None => read_index_options(conn).await?.create_sql(storage),
+  };
+  conn
+    .execute(&format!("DROP INDEX IF EXISTS {VECTOR_INDEX}"), ())
+    .await
+    .map_err(DatabaseError::query)?;
+  requantize(conn, storage).await?;
+  conn
+    .execute(&sql, ())
+    .await
+    .map_err(|e| DatabaseError::QueryFailed(format!("rebuilding {VECTOR_INDEX}: {e}")))?;
+  Ok(())
+}
+
+async fn renumber_chunks(conn: &Connection, comic_number: u64) -> Result<()> {
+  let ids = query_u64s(
+    conn,
+    "SELECT id FROM xkcd_chunks WHERE comic_number = ? ORDER BY chunk_index, id",
+    params![comic_number],
+  )
+  .await?;
+  let stmt = conn
+    .prepare("UPDATE xkcd_chunks SET chunk_index = ? WHERE id = ?")
+    .await
+    .map_err(DatabaseError::prepare)?;
+  for (index, id) in (0u64..).zip(ids) {
+    stmt
+      .execute(params![index, id])
+      .await
+      .map_err(DatabaseError::query)?;
+    stmt.reset();
+  }
+  Ok(())
+}
+
+impl Database {
+  /// Look for problems in the stored data without changing anything.
+  ///
+  /// Checks run against a single read snapshot, so concurrent writes can't
+  /// produce false positives.
+  pub async fn check_integrity(&self) -> Result<IntegrityReport> {
+    let conn = self.reader().await?;
+    let tx = conn
+      .transaction()
+      .await
+      .map_err(DatabaseError::transaction)?;
+    let report = inspect(&tx).await?;
+    tx.rollback().await.map_err(DatabaseError::transaction)?;
+    Ok(report)
+  }
+
+  /// Check the database and fix the problems selected in `options`, all in
+  /// one transaction.
+  ///
+  /// The returned report describes the database as it was found, with
+  /// [`IntegrityReport::repairs`] listing what was changed; run
+  /// [`Database::check_integrity`] afterwards to confirm the result.
+  ///
+  /// # Errors
+  /// Returns an error if a query fails, including rebuilding the vector index
+  /// while chunks with malformed embeddings remain. Nothing is changed then.
+  pub async fn repair_integrity(&self, options: &RepairOptions) -> Result<IntegrityReport> {
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    let mut report = inspect(&tx).await?;
+
+    if options.delete_orphans && !report.orphans.is_empty() {
+      for orphan in &report.orphans {
+        // table names come from PRAGMA foreign_key_check, not user input
+        tx.execute(
+          &format!("DELETE FROM \"{}\" WHERE rowid = ?", orphan.table),
+          params![orphan.rowid],
+        )
+        .await
+        .map_err(DatabaseError::query)?;
+      }
+      report.repairs.push(RepairAction::DeletedOrphans {
+        count: report.orphans.len() as u64,
+      });
+    }
+
+    if options.delete_bad_embeddings && !report.bad_embeddings.is_empty() {
+      let chunk_ids: Vec<u64> = report.bad_embeddings.iter().map(|b| b.chunk_id).collect();
+      for id in &chunk_ids {
+        tx.execute("DELETE FROM xkcd_chunks WHERE id = ?", params![*id])
+          .await
+          .map_err(DatabaseError::query)?;
+      }
+      report
+        .repairs
+        .push(RepairAction::DeletedBadEmbeddings { chunk_ids });
+    }
+
+    if options.renumber_chunks {
+      // deleting chunks above can open new gaps, so look again
+      for gap in chunk_index_gaps(&tx).await? {
+        renumber_chunks(&tx, gap.comic_number).await?;
+        report.repairs.push(RepairAction::RenumberedChunks {
+          comic_number: gap.comic_number,
+        });
+      }
+    }
+
+    if options.rebuild_vector_index && !vector_index(&tx).await?.is_consistent() {
+      rebuild_vector_index_in(&tx).await?;
+      report.repairs.push(RepairAction::RebuiltVectorIndex);
+    }
+
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(report)
+  }
+
+  /// Drop and recreate the vector index from the embeddings in
+  /// `xkcd_chunks`, keeping its settings.
+  ///
+  /// Searches keep using the old index until the rebuild commits.
+  ///
+  /// # Errors
+  /// Returns an error if any chunk has a malformed embedding (see
+  /// [`Database::repair_integrity`]) or a query fails.
+  pub async fn rebuild_vector_index(&self) -> Result<()> {
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    rebuild_vector_index_in(&tx).await?;
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::{make_chunk, make_comic};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  /// Comics 1 and 2 with three chunks each.
+  async fn setup() -> Database {
+    let db = Database::new(":memory:").await.unwrap();
+    for n in 1..=2 {
+      db.insert_comic(make_comic(n)).await.unwrap();
+      db.insert_chunks_batch(&MODEL, (0..3).map(|i| make_chunk(n, i)).collect())
+        .await
+        .unwrap();
+    }
+    db
+  }
+
+  async fn exec(db: &Database, sql: &str) {
+    db.writer().await.unwrap().execute_batch(sql).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_clean_database_passes() {
+    let report = setup().await.check_integrity().await.unwrap();
+    assert!(report.is_ok(), "{report:?}");
+  }
+
+  #[tokio::test]
+  async fn test_bad_embedding_and_missing_index() {
+    let db = setup().await;
+    // the index refuses malformed vectors, so they only get in without it
+    exec(&db, "DROP INDEX chunks_vec_idx").await;
+    exec(
+      &db,
+      "INSERT INTO xkcd_chunks (comic_number, chunk_text, chunk_index, embedding)
+       VALUES (2, 'broken', 3, X'00000000')",
+    )
+    .await;
+
+    let report = db.check_integrity().await.unwrap();
+    assert_eq!(report.bad_embeddings.len(), 1);
+    assert_eq!(report.bad_embeddings[0].bytes, 4);
+    assert!(!report.vector_index.present);
+
+    // the index can't be rebuilt around the broken chunk
+    assert!(db.rebuild_vector_index().await.is_err());
+
+    let report = db.repair_integrity(&RepairOptions::all()).await.unwrap();
+    assert_eq!(
+      report.repairs,
+      vec![
+        RepairAction::DeletedBadEmbeddings {
+          chunk_ids: vec![report.bad_embeddings[0].chunk_id]
+        },
+        RepairAction::RebuiltVectorIndex,
+      ]
+    );
+    assert!(db.check_integrity().await.unwrap().is_ok());
+    let results = db
+      .vector_search(&MODEL, vec![1.0; EMBEDDING_DIM], 10)
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 6);
+  }
+
+  #[tokio::test]
+  async fn test_index_disagreeing_with_table_rebuilt() {
+    let db = setup().await;
+    exec(
+      &db,
+      "DELETE FROM chunks_vec_idx_shadow WHERE index_key = (SELECT MIN(id) FROM xkcd_chunks)",
+    )
+    .await;
+
+    let report = db.check_integrity().await.unwrap();
+    assert_eq!(report.vector_index.missing.len(), 1);
+    assert!(!report.is_ok());
+
+    db.rebuild_vector_index().await.unwrap();
+    assert!(db.check_integrity().await.unwrap().is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_orphans_gaps_and_empty_comics() {
+    let db = setup().await;
+    db.insert_comic(make_comic(3)).await.unwrap();
+    exec(
+      &db,
+      "DELETE FROM xkcd_chunks WHERE comic_number = 2 AND chunk_index = 1",
+    )
+    .await;
+    exec(&db, "PRAGMA foreign_keys = OFF").await;
+    exec(&db, "DELETE FROM xkcd_comics WHERE comic_number = 1").await;
+    exec(&db, "PRAGMA foreign_keys = ON").await;
+
+    let report = db.check_integrity().await.unwrap();
+    assert_eq!(report.empty_comics, vec![3]);
+    assert_eq!(report.orphans.len(), 3);
+    assert!(
+      report
+        .orphans
+        .iter()
+        .all(|o| o.table == "xkcd_chunks" && o.parent == "xkcd_comics")
+    );
+    assert_eq!(
+      report.chunk_index_gaps,
+      vec![ChunkIndexGap {
+        comic_number: 2,
+        missing: vec![1],
+        duplicates: vec![],
+      }]
+    );
+
+    db.repair_integrity(&RepairOptions::all()).await.unwrap();
+    let report = db.check_integrity().await.unwrap();
+    assert!(report.orphans.is_empty());
+    assert!(report.chunk_index_gaps.is_empty());
+    // reported, never deleted
+    assert_eq!(report.empty_comics, vec![3]);
+    let indexes: Vec<u64> = db
+      .get_chunks_for_comic(2)
+      .await
+      .unwrap()
+      .iter()
+      .map(|c| c.chunk_index)
+      .collect();
+    assert_eq!(indexes, vec![0, 1]);
+  }
+
+  #[tokio::test]
+  async fn test_unparseable_metadata_reported() {
+    let db = setup().await;
+    db.set_metadata(NORMALIZED_KEY, "maybe".to_string())
+      .await
+      .unwrap();
+    db.set_metadata("LAST_SCRAPE", "whenever".to_string())
+      .await
+      .unwrap();
+
+    let report = db.check_integrity().await.unwrap();
+    assert_eq!(report.bad_metadata.len(), 1);
+    assert_eq!(report.bad_metadata[0].key, NORMALIZED_KEY);
+  }
+
+  #[test]
+  fn test_metadata_problem() {
+    assert_eq!(metadata_problem("SCHEMA_VERSION", "5"), None);
+    assert!(metadata_problem("SCHEMA_VERSION", "five").is_some());
+  }
+}
@@ -1,56 +1,80 @@
+mod backup;
 mod chunks;
+mod comic_search;
 mod comics;
+mod context;
+mod embedding;
 mod error;
+mod hybrid;
+mod integrity;
 mod metadata;
+mod migrations;
+mod mmr;
 mod models;
+mod options;
+mod pool;
+mod recall;
+mod reembed;
 mod schema;
+mod search;
+mod snapshot;
+mod storage;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod timestamp;
+mod vector_index;
 
-use libsql::{Builder, Connection};
 use std::path::Path;
+use std::sync::Arc;
+
+use crate::pool::Connections;
 
-pub use chunks::ChunkSearchResult;
-pub use error::{DatabaseError, Result};
-pub use models::{Chunks, Comics, Metadata, SectionType};
+pub use backup::{Backup, BackupPolicy};
+pub use comic_search::{ComicAggregation, ComicSearchOptions, ComicSearchResult};
+pub use context::{ChunkContext, ComicDocument, ContextChunk, DocumentSection};
+pub use embedding::{DistanceMetric, Embedder, EmbeddingModel, QWEN3_QUERY_PREFIX};
+pub use error::{Constraint, DatabaseError, Result};
+pub use hybrid::{HybridSearchResult, RRF_K};
+pub use integrity::{
+  BadEmbedding, BadMetadata, ChunkIndexGap, IntegrityReport, OrphanedRow, RepairAction,
+  RepairOptions, VectorIndexCheck,
+};
+pub use migrations::SCHEMA_VERSION;
+pub use mmr::{Mmr, MmrSimilarity};
+pub use models::{Chunks, ComicNumber, Comics, Metadata, SectionType};
+pub use options::{DatabaseOptions, OpenMode, Synchronous};
+pub use recall::RecallReport;
+pub use reembed::ReembedProgress;
+pub use search::{ChunkSearchResult, SearchOptions};
+pub use snapshot::{EmbeddingEncoding, SNAPSHOT_VERSION, SnapshotCounts, SnapshotManifest};
+pub use storage::EmbeddingStorage;
+pub use vector_index::{IndexMetric, NeighborCompression, VectorIndexOptions, VectorIndexStats};
 
 /// The dimension of the embedding vectors (must match F32_BLOB(1024) in schema) for qwen 0.6b
 pub const EMBEDDING_DIM: usize = 1024;
 
+/// Handle to the comic database.
+///
+/// Cheap to clone and safe to share between tokio tasks: clones share one
+/// serialized write connection and a pool of read connections.
+#[derive(Clone)]
 pub struct Database {
-  pub(crate) conn: Connection,
+  pub(crate) inner: Arc<Connections>,
 }
 
 impl Database {
+  /// Open the database at `path`, creating and initializing it if it doesn't
+  /// exist. Use [`DatabaseOptions`] for anything else.
   pub async fn new(path: impl AsRef<Path>) -> Result<Self> {
-    let path = path.as_ref();
-    // if file exists - open.
-    if std::fs::metadata(path).is_ok() {
-      // check if initialization
-      let db = Builder::new_local(path)
-        .build()
-        .await
-        .map_err(|e| DatabaseError::LibSql(e))?;
-      let conn = db
-        .connect()
-        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
-
-      // Enable foreign key constraints (must be done per-connection)
-      conn
-        .execute("PRAGMA foreign_keys = ON", ())
-        .await
-        .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+    DatabaseOptions::new(path).open().await
+  }
 
-      let database = Database { conn };
-      let initialized: Metadata = database.get_metadata("INITIALIZED").await?;
-      if initialized.value == "true" {
-        Ok(database)
-      } else {
-        Err(DatabaseError::InitializationError(
-          "Database Schema Mismatch - File exists".to_string(),
-        ))
-      }
-    } else {
-      Self::init(path).await
-    }
+  /// Wrap an opened libSQL database in a handle with its connection pool.
+  pub(crate) async fn from_libsql(db: libsql::Database, options: &DatabaseOptions) -> Result<Self> {
+    let connections = Connections::open(db, options).await?;
+    Ok(Self {
+      inner: Arc::new(connections),
+    })
   }
 }
 
@@ -65,7 +89,13 @@ mod tests {
 
     let db = Database::new(&test_path).await.unwrap();
 
-    let mut rows = db.conn.query("PRAGMA journal_mode", ()).await.unwrap();
+    let mut rows = db
+      .writer()
+      .await
+      .unwrap()
+      .query("PRAGMA journal_mode", ())
+      .await
+      .unwrap();
     let row = rows.next().await.unwrap().expect("expected row");
     let mode: String = row.get(0).unwrap();
 
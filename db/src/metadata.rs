@@ -5,31 +5,31 @@ use crate::{Database, Metadata};
 
 impl Database {
   pub async fn get_metadata(&self, key: &str) -> Result<Metadata> {
-    let mut stmt = self
-      .conn
+    let conn = self.reader().await?;
+    let mut stmt = conn
       .prepare("SELECT * FROM metadata WHERE key = ?")
       .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
+      .map_err(DatabaseError::prepare)?;
     let row = stmt.query_row(params!(key)).await.map_err(|e| match e {
       libsql::Error::QueryReturnedNoRows => DatabaseError::MetadataNotFound(key.to_string()),
-      _ => DatabaseError::QueryFailed(e.to_string()),
+      _ => DatabaseError::query(e),
     })?;
 
     de::from_row::<Metadata>(&row).map_err(|e| DatabaseError::Serialization(e.to_string()))
   }
 
   pub async fn set_metadata(&self, key: &str, value: String) -> Result<()> {
-    let stmt = self
-      .conn
+    let conn = self.writer().await?;
+    let stmt = conn
       .prepare(
         "INSERT INTO metadata (key, value) VALUES ($1, $2) ON CONFLICT (key) DO UPDATE SET value = $2",
       )
       .await
-      .map_err(|e| DatabaseError::PreparedFailed(e.to_string()))?;
+      .map_err(DatabaseError::prepare)?;
     stmt
       .execute(params!(key, value))
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::query)?;
 
     Ok(())
   }
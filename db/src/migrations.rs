@@ -0,0 +1,291 @@
+use libsql::{Connection, TransactionBehavior, params};
+
+use crate::Database;
+use crate::error::{DatabaseError, Result};
+
+/// Metadata key holding the schema version the database file is at.
+pub(crate) const SCHEMA_VERSION_KEY: &str = "SCHEMA_VERSION";
+
+/// A single numbered migration from `db/migrations/`.
+///
+/// Migrations are applied in ascending `version` order, each inside its own
+/// transaction together with the bump of [`SCHEMA_VERSION_KEY`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Migration {
+  pub version: u32,
+  pub name: &'static str,
+  pub sql: &'static str,
+}
+
+/// Every migration known to this binary, in order. Add new files to the end.
+pub(crate) const MIGRATIONS: &[Migration] = &[
+  Migration {
+    version: 1,
+    name: "001_schema",
+    sql: include_str!("../migrations/001_schema.sql"),
+  },
+  Migration {
+    version: 2,
+    name: "002_published_at",
+    sql: include_str!("../migrations/002_published_at.sql"),
+  },
+  Migration {
+    version: 3,
+    name: "003_fts",
+    sql: include_str!("../migrations/003_fts.sql"),
+  },
+  Migration {
+    version: 4,
+    name: "004_embedding_model",
+    sql: include_str!("../migrations/004_embedding_model.sql"),
+  },
+  Migration {
+    version: 5,
+    name: "005_reembed",
+    sql: include_str!("../migrations/005_reembed.sql"),
+  },
+  Migration {
+    version: 6,
+    name: "006_timestamps",
+    sql: include_str!("../migrations/006_timestamps.sql"),
+  },
+  Migration {
+    version: 7,
+    name: "007_vector_index",
+    sql: include_str!("../migrations/007_vector_index.sql"),
+  },
+  Migration {
+    version: 8,
+    name: "008_embedding_storage",
+    sql: include_str!("../migrations/008_embedding_storage.sql"),
+  },
+  Migration {
+    version: 9,
+    name: "009_published_date",
+    sql: include_str!("../migrations/009_published_date.sql"),
+  },
+];
+
+/// The schema version this binary expects. Databases at a lower version are
+/// migrated on open; databases at a higher version are refused.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Read the schema version recorded in the database.
+///
+/// Returns `0` for a database without a `metadata` table (a fresh file) and
+/// `1` for databases created before versions were recorded, which only ever
+/// had `001_schema.sql` applied.
+pub(crate) async fn read_schema_version(conn: &Connection) -> Result<u32> {
+  let mut rows = conn
+    .query(
+      "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'metadata'",
+      (),
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  if rows.next().await.map_err(DatabaseError::query)?.is_none() {
+    return Ok(0);
+  }
+
+  let mut rows = conn
+    .query(
+      "SELECT value FROM metadata WHERE key = ?",
+      params![SCHEMA_VERSION_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  match rows.next().await.map_err(DatabaseError::query)? {
+    Some(row) => {
+      let value: String = row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+      value
+        .parse()
+        .map_err(|_| DatabaseError::MetaParseFailed(format!("{SCHEMA_VERSION_KEY}={value}")))
+    }
+    None => Ok(1),
+  }
+}
+
+/// Bring the database up to the newest version in `migrations`.
+///
+/// Each pending migration runs in its own immediate transaction, and the
+/// version is re-read once the write lock is held so that two processes
+/// opening the same file don't both apply a migration.
+pub(crate) async fn apply_migrations(conn: &Connection, migrations: &[Migration]) -> Result<u32> {
+  let supported = migrations.last().map_or(0, |m| m.version);
+  let current = read_schema_version(conn).await?;
+  if current > supported {
+    return Err(DatabaseError::SchemaTooNew {
+      found: current,
+      supported,
+    });
+  }
+
+  for migration in migrations.iter().filter(|m| m.version > current) {
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+
+    if read_schema_version(&tx).await? >= migration.version {
+      // another connection got here first
+      continue;
+    }
+
+    tx.execute_batch(migration.sql)
+      .await
+      .map_err(|e| DatabaseError::MigrationFailed {
+        version: migration.version,
+        reason: format!("{}: {e}", migration.name),
+      })?;
+    tx.execute(
+      "INSERT INTO metadata (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = ?2",
+      params![SCHEMA_VERSION_KEY, migration.version.to_string()],
+    )
+    .await
+    .map_err(|e| DatabaseError::MigrationFailed {
+      version: migration.version,
+      reason: e.to_string(),
+    })?;
+
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+  }
+
+  read_schema_version(conn).await
+}
+
+impl Database {
+  /// The schema version currently recorded in the database.
+  pub async fn schema_version(&self) -> Result<u32> {
+    read_schema_version(&*self.reader().await?).await
+  }
+
+  /// Apply any migrations this binary knows about that the database hasn't
+  /// seen yet.
+  ///
+  /// # Errors
+  /// Returns [`DatabaseError::SchemaTooNew`] if the database was written by a
+  /// newer binary, or [`DatabaseError::MigrationFailed`] if a migration script
+  /// fails (that migration is rolled back).
+  pub(crate) async fn migrate(&self) -> Result<u32> {
+    apply_migrations(&*self.writer().await?, MIGRATIONS).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::setup_db;
+
+  /// The real migrations plus one more on top.
+  fn with_extra(sql: &'static str) -> Vec<Migration> {
+    let mut migrations = MIGRATIONS.to_vec();
+    migrations.push(Migration {
+      version: SCHEMA_VERSION + 1,
+      name: "test_extra",
+      sql,
+    });
+    migrations
+  }
+
+  #[tokio::test]
+  async fn test_new_database_is_at_latest_version() {
+    let db = setup_db().await;
+    assert_eq!(db.schema_version().await.unwrap(), SCHEMA_VERSION);
+  }
+
+  #[tokio::test]
+  async fn test_unversioned_database_reads_as_version_one() {
+    let db = setup_db().await;
+    db.writer()
+      .await
+      .unwrap()
+      .execute(
+        "DELETE FROM metadata WHERE key = ?",
+        params![SCHEMA_VERSION_KEY],
+      )
+      .await
+      .unwrap();
+    assert_eq!(db.schema_version().await.unwrap(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_version_one_file_migrated_on_open() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+
+    // a database as created before the migration runner existed
+    let raw = libsql::Builder::new_local(&path).build().await.unwrap();
+    raw
+      .connect()
+      .unwrap()
+      .execute_batch(MIGRATIONS[0].sql)
+      .await
+      .unwrap();
+    drop(raw);
+
+    let db = Database::new(&path).await.unwrap();
+    assert_eq!(db.schema_version().await.unwrap(), SCHEMA_VERSION);
+  }
+
+  #[tokio::test]
+  async fn test_pending_migration_applied() {
+    let db = setup_db().await;
+    let migrations = with_extra("CREATE TABLE migration_test (id INTEGER PRIMARY KEY);");
+    let version = apply_migrations(&db.writer().await.unwrap(), &migrations)
+      .await
+      .unwrap();
+    assert_eq!(version, SCHEMA_VERSION + 1);
+    assert!(
+      db.writer()
+        .await
+        .unwrap()
+        .execute("INSERT INTO migration_test DEFAULT VALUES", ())
+        .await
+        .is_ok()
+    );
+
+    // applying again is a no-op
+    let version = apply_migrations(&db.writer().await.unwrap(), &migrations)
+      .await
+      .unwrap();
+    assert_eq!(version, SCHEMA_VERSION + 1);
+  }
+
+  #[tokio::test]
+  async fn test_failed_migration_rolls_back() {
+    let db = setup_db().await;
+    let migrations = with_extra("CREATE TABLE half_done (id INTEGER); THIS IS NOT SQL;");
+    let result = apply_migrations(&db.writer().await.unwrap(), &migrations).await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::MigrationFailed { version, .. }) if version == SCHEMA_VERSION + 1
+    ));
+    assert_eq!(db.schema_version().await.unwrap(), SCHEMA_VERSION);
+    assert!(
+      db.writer()
+        .await
+        .unwrap()
+        .execute("SELECT * FROM half_done", ())
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_newer_database_refused() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let db = Database::new(&path).await.unwrap();
+    db.set_metadata(SCHEMA_VERSION_KEY, (SCHEMA_VERSION + 1).to_string())
+      .await
+      .unwrap();
+    drop(db);
+
+    assert!(matches!(
+      Database::new(&path).await,
+      Err(DatabaseError::SchemaTooNew { .. })
+    ));
+  }
+}
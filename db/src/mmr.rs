@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+
+use libsql::{Connection, params};
+
+use crate::Database;
+use crate::chunks::{f32_blob_to_vec, vec_to_json_string};
+use crate::embedding::EmbeddingModel;
+use crate::error::{DatabaseError, Result};
+use crate::search::{ChunkSearchResult, SearchOptions};
+
+/// What two candidates are compared by when measuring how alike they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MmrSimilarity {
+  /// The candidates' own embeddings.
+  Chunk,
+  /// The mean embedding of each candidate's comic, so two chunks from the
+  /// same comic, or from near-identical comics, count as duplicates.
+  #[default]
+  ComicCentroid,
+}
+
+/// Maximal marginal relevance re-ranking.
+///
+/// Results are picked one at a time, each maximising
+/// `lambda * relevance - (1 - lambda) * max similarity to the picks so far`,
+/// from a pool of `top_k * candidate_factor` candidates. `lambda = 1` keeps
+/// the plain ranking, `0` only cares about being different.
+///
+/// # Example
+/// ```
+/// use db::{Mmr, MmrSimilarity};
+/// let mmr = Mmr::new(0.5);
+/// assert_eq!(mmr.similarity, MmrSimilarity::ComicCentroid);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mmr {
+  /// Weight of relevance against diversity, clamped to `0.0..=1.0`.
+  pub lambda: f64,
+  pub similarity: MmrSimilarity,
+  /// Candidates fetched per result.
+  pub candidate_factor: usize,
+}
+
+impl Mmr {
+  /// Compare by comic centroid and pick from four candidates per result.
+  #[must_use]
+  pub fn new(lambda: f64) -> Self {
+    Self {
+      lambda,
+      similarity: MmrSimilarity::default(),
+      candidate_factor: 4,
+    }
+  }
+
+  /// Size of the candidate pool for `top_k` results.
+  pub(crate) fn pool_size(&self, top_k: usize) -> usize {
+    top_k.saturating_mul(self.candidate_factor.max(1))
+  }
+
+  /// Greedily pick up to `k` candidates, returning their indexes in pick
+  /// order. `relevance` should be on the same `0..=1` scale as cosine
+  /// similarity.
+  pub(crate) fn select(&self, relevance: &[f64], vectors: &[&[f32]], k: usize) -> Vec<usize> {
+    let lambda = self.lambda.clamp(0.0, 1.0);
+    let mut picked: Vec<usize> = Vec::with_capacity(k.min(relevance.len()));
+    // highest similarity of each candidate to anything picked so far
+    let mut redundancy = vec![f64::NEG_INFINITY; relevance.len()];
+    while picked.len() < k {
+      let best = (0..relevance.len())
+        .filter(|i| !picked.contains(i))
+        .map(|i| {
+          let penalty = if picked.is_empty() {
+            0.0
+          } else {
+            redundancy[i]
+          };
+          (i, lambda * relevance[i] - (1.0 - lambda) * penalty)
+        })
+        // earlier candidates win ties, keeping the input ranking
+        .fold(None, |best: Option<(usize, f64)>, (i, score)| match best {
+          Some((_, best_score)) if best_score >= score => best,
+          _ => Some((i, score)),
+        });
+      let Some((next, _)) = best else { break };
+      for (i, r) in redundancy.iter_mut().enumerate() {
+        *r = r.max(cosine_similarity(vectors[i], vectors[next]));
+      }
+      picked.push(next);
+    }
+    picked
+  }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+  let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+  for (x, y) in a.iter().zip(b) {
+    let (x, y) = (f64::from(*x), f64::from(*y));
+    dot += x * y;
+    norm_a += x * x;
+    norm_b += y * y;
+  }
+  if norm_a == 0.0 || norm_b == 0.0 {
+    // like vector_distance_cos, treat zero vectors as orthogonal
+    return 0.0;
+  }
+  dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// Stored embeddings of the chunks in `chunk_ids`.
+pub(crate) async fn chunk_embeddings(
+  conn: &Connection,
+  chunk_ids: Vec<u64>,
+) -> Result<HashMap<u64, Vec<f32>>> {
+  let mut rows = conn
+    .query(
+      "SELECT id, embedding FROM xkcd_chunks
+       WHERE id IN (SELECT value FROM json_each(?))",
+      params![vec_to_json_string(chunk_ids)],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let mut embeddings = HashMap::new();
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let id: u64 = row
+      .get(0)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    let blob: Vec<u8> = row
+      .get(1)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    embeddings.insert(id, f32_blob_to_vec(&blob));
+  }
+  Ok(embeddings)
+}
+
+/// Mean embedding of every chunk of each comic in `comic_numbers`.
+pub(crate) async fn comic_centroids(
+  conn: &Connection,
+  comic_numbers: Vec<u64>,
+) -> Result<HashMap<u64, Vec<f32>>> {
+  let mut rows = conn
+    .query(
+      "SELECT comic_number, embedding FROM xkcd_chunks
+       WHERE comic_number IN (SELECT value FROM json_each(?))",
+      params![vec_to_json_string(comic_numbers)],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let mut sums: HashMap<u64, (Vec<f32>, usize)> = HashMap::new();
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let comic_number: u64 = row
+      .get(0)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    let blob: Vec<u8> = row
+      .get(1)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+    let embedding = f32_blob_to_vec(&blob);
+    let (sum, count) = sums
+      .entry(comic_number)
+      .or_insert_with(|| (vec![0.0; embedding.len()], 0));
+    for (s, x) in sum.iter_mut().zip(&embedding) {
+      *s += x;
+    }
+    *count += 1;
+  }
+  Ok(
+    sums
+      .into_iter()
+      .map(|(comic, (sum, count))| (comic, sum.into_iter().map(|s| s / count as f32).collect()))
+      .collect(),
+  )
+}
+
+impl Database {
+  /// Vector search re-ranked with maximal marginal relevance, so the results
+  /// cover different ground instead of repeating the best match.
+  ///
+  /// Candidates are fetched with [`Database::vector_search_with_options`],
+  /// which applies the filters in `options`, then `options.top_k` of them are
+  /// picked as described on [`Mmr`]. Each result keeps its original
+  /// `distance`.
+  ///
+  /// # Errors
+  /// Returns an error if `model` isn't the model recorded in the database, the
+  /// embedding dimension doesn't match `EMBEDDING_DIM`, or a query fails.
+  pub async fn vector_search_mmr(
+    &self,
+    model: &EmbeddingModel,
+    query_embedding: Vec<f32>,
+    options: &SearchOptions,
+    mmr: &Mmr,
+  ) -> Result<Vec<ChunkSearchResult>> {
+    let pool = SearchOptions {
+      top_k: mmr.pool_size(options.top_k),
+      ..options.clone()
+    };
+    let candidates = self
+      .vector_search_with_options(model, query_embedding, &pool)
+      .await?;
+    if candidates.len() <= 1 {
+      return Ok(candidates);
+    }
+
+    let conn = self.reader().await?;
+    let (vectors, key): (_, fn(&ChunkSearchResult) -> u64) = match mmr.similarity {
+      MmrSimilarity::Chunk => (
+        chunk_embeddings(&conn, candidates.iter().map(|c| c.chunk_id).collect()).await?,
+        |c| c.chunk_id,
+      ),
+      MmrSimilarity::ComicCentroid => (
+        comic_centroids(&conn, candidates.iter().map(|c| c.comic_number).collect()).await?,
+        |c| c.comic_number,
+      ),
+    };
+    drop(conn);
+
+    // a candidate deleted since the search has no vector and can't be
+    // compared, so it's dropped
+    let candidates: Vec<ChunkSearchResult> = candidates
+      .into_iter()
+      .filter(|c| vectors.contains_key(&key(c)))
+      .collect();
+    let relevance: Vec<f64> = candidates
+      .iter()
+      .map(ChunkSearchResult::similarity)
+      .collect();
+    let candidate_vectors: Vec<&[f32]> = candidates
+      .iter()
+      .map(|c| vectors[&key(c)].as_slice())
+      .collect();
+    let picked = mmr.select(&relevance, &candidate_vectors, options.top_k);
+
+    let mut candidates: Vec<Option<ChunkSearchResult>> = candidates.into_iter().map(Some).collect();
+    Ok(
+      picked
+        .into_iter()
+        .filter_map(|i| candidates[i].take())
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::EMBEDDING_DIM;
+  use crate::models::Chunks;
+  use crate::test_support::{make_chunk, make_comic};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  /// The query direction `e0`, pushed `amount` towards axis `axis`.
+  fn toward(axis: usize, amount: f32) -> Vec<f32> {
+    let mut v = vec![0.0; EMBEDDING_DIM];
+    v[0] = 1.0;
+    v[axis] += amount;
+    v
+  }
+
+  fn embedded_chunk(comic: u64, idx: u64, embedding: Vec<f32>) -> Chunks {
+    Chunks {
+      embedding,
+      ..make_chunk(comic, idx)
+    }
+  }
+
+  /// Comics 1 and 2 are near-duplicates right next to the query, comic 1
+  /// with two identical chunks; comic 3 is less relevant but different.
+  async fn setup_corpus() -> Database {
+    let db = Database::new(":memory:").await.unwrap();
+    for n in 1..=3 {
+      db.insert_comic(make_comic(n)).await.unwrap();
+    }
+    let chunks = vec![
+      embedded_chunk(1, 0, toward(1, 0.1)),
+      embedded_chunk(1, 1, toward(1, 0.1)),
+      embedded_chunk(2, 0, toward(1, 0.12)),
+      embedded_chunk(3, 0, toward(2, 0.8)),
+    ];
+    db.insert_chunks_batch(&MODEL, chunks).await.unwrap();
+    db
+  }
+
+  #[test]
+  fn test_select() {
+    let relevance = [0.9, 0.89, 0.6];
+    let a = [1.0, 0.0];
+    let b = [0.0, 1.0];
+    let vectors: Vec<&[f32]> = vec![&a, &a, &b];
+
+    assert_eq!(Mmr::new(0.5).select(&relevance, &vectors, 2), vec![0, 2]);
+    assert_eq!(Mmr::new(1.0).select(&relevance, &vectors, 2), vec![0, 1]);
+    // out of range lambdas are clamped, and k can exceed the candidates
+    assert_eq!(Mmr::new(7.0).select(&relevance, &vectors, 5), vec![0, 1, 2]);
+    assert!(Mmr::new(0.5).select(&[], &[], 3).is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_mmr_trades_relevance_for_diversity() {
+    let db = setup_corpus().await;
+    let query = toward(1, 0.0);
+
+    let plain = db.vector_search(&MODEL, query.clone(), 2).await.unwrap();
+    let comics: Vec<u64> = plain.iter().map(|r| r.comic_number).collect();
+    assert_eq!(comics, vec![1, 1]);
+
+    for similarity in [MmrSimilarity::Chunk, MmrSimilarity::ComicCentroid] {
+      let mmr = Mmr {
+        similarity,
+        ..Mmr::new(0.3)
+      };
+      let results = db
+        .vector_search_mmr(&MODEL, query.clone(), &SearchOptions::new(2), &mmr)
+        .await
+        .unwrap();
+      let comics: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+      assert_eq!(comics, vec![1, 3], "{similarity:?}");
+    }
+
+    let results = db
+      .vector_search_mmr(&MODEL, query, &SearchOptions::new(2), &Mmr::new(1.0))
+      .await
+      .unwrap();
+    // comic 1's two chunks tie, so compare distances rather than ids
+    let distances: Vec<f64> = results.iter().map(|r| r.distance).collect();
+    let plain_distances: Vec<f64> = plain.iter().map(|r| r.distance).collect();
+    assert_eq!(distances, plain_distances);
+  }
+}
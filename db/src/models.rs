@@ -1,44 +1,118 @@
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString};
 
+use crate::error::DatabaseError;
+
+/// An xkcd comic number. xkcd counts from 1, so 0 is rejected.
+///
+/// # Example
+/// ```
+/// use db::{ComicNumber, DatabaseError};
+/// assert_eq!(ComicNumber::new(327).unwrap().get(), 327);
+/// assert!(matches!(ComicNumber::new(0), Err(DatabaseError::InvalidComicNumber(0))));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u64", into = "u64")]
+pub struct ComicNumber(u64);
+
+impl ComicNumber {
+  /// # Errors
+  /// Returns [`DatabaseError::InvalidComicNumber`] for 0.
+  pub fn new(number: u64) -> Result<Self, DatabaseError> {
+    if number == 0 {
+      return Err(DatabaseError::InvalidComicNumber(number));
+    }
+    Ok(Self(number))
+  }
+
+  pub const fn get(self) -> u64 {
+    self.0
+  }
+}
+
+impl TryFrom<u64> for ComicNumber {
+  type Error = DatabaseError;
+
+  fn try_from(number: u64) -> Result<Self, Self::Error> {
+    Self::new(number)
+  }
+}
+
+impl From<ComicNumber> for u64 {
+  fn from(number: ComicNumber) -> Self {
+    number.0
+  }
+}
+
+impl From<ComicNumber> for libsql::Value {
+  fn from(number: ComicNumber) -> Self {
+    libsql::Value::Integer(number.0 as i64)
+  }
+}
+
+impl PartialEq<u64> for ComicNumber {
+  fn eq(&self, other: &u64) -> bool {
+    self.0 == *other
+  }
+}
+
+impl fmt::Display for ComicNumber {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.0.fmt(f)
+  }
+}
+
 /// Represents a full comic that has been scraped.
 ///
 /// This struct contains all the information about a comic, including its number, title, URL, and other metadata.
 ///
+/// Timestamps are stored to the second. They deserialize from RFC 3339 or
+/// MediaWiki's `YYYYMMDDHHMMSS`.
 ///
 /// # Example
 /// ```
-/// use db::Comics;
+/// use chrono::{NaiveDate, TimeZone, Utc};
+/// use db::{ComicNumber, Comics};
+/// let at = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
 /// let comics = Comics {
-///    comic_number: 1,
+///    comic_number: ComicNumber::new(1).unwrap(),
 ///    title: "Title".to_string(),
 ///    url: "https://example.com".to_string(),
 ///    xkcd_url: "https://xkcd.com".to_string(),
 ///    hover_text: Some("Hover Text".to_string()),
+///    published_at: NaiveDate::from_ymd_opt(2023, 1, 1),
 ///    last_revision_id: 1,
-///    last_revision_timestamp: "2023-01-01T00:00:00Z".to_string(),
-///    scraped_at: "2023-01-01T00:00:00Z".to_string(),
-///    updated_at: "2023-01-01T00:00:00Z".to_string(),
+///    last_revision_timestamp: at,
+///    scraped_at: at,
+///    updated_at: at,
 ///};
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Comics {
-  pub comic_number: u64,
+  pub comic_number: ComicNumber,
   pub title: String,
   pub url: String,      //explainxkcd.com url
   pub xkcd_url: String, //xkcd.com url
   pub hover_text: Option<String>,
+  /// Publish date on xkcd.com, stored as `YYYY-MM-DD`.
+  pub published_at: Option<NaiveDate>,
   pub last_revision_id: u64,
-  pub last_revision_timestamp: String,
-  pub scraped_at: String,
-  pub updated_at: String,
+  #[serde(with = "crate::timestamp")]
+  pub last_revision_timestamp: DateTime<Utc>,
+  #[serde(with = "crate::timestamp")]
+  pub scraped_at: DateTime<Utc>,
+  #[serde(with = "crate::timestamp")]
+  pub updated_at: DateTime<Utc>,
 }
 
 /// Represents the type of section in a comic.
 ///
 /// This enum defines the different types of sections that can be found in a comic. Supports direct conversion to and from string via the `Display` and `FromStr` traits.
 ///
-#[derive(Debug, Clone, Serialize, Deserialize, Display, EnumString)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display, EnumString)]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum SectionType {
@@ -56,10 +130,10 @@ pub enum SectionType {
 ///
 /// # Example
 /// ```
-/// use db::{Chunks, SectionType};
+/// use db::{Chunks, ComicNumber, SectionType};
 /// let chunks = Chunks {
 ///    id: Some(1),
-///    comic_number: 1,
+///    comic_number: ComicNumber::new(1).unwrap(),
 ///    chunk_text: "Chunk Text".to_string(),
 ///    chunk_index: 1,
 ///    section_type: Some(SectionType::TitleHover),
@@ -69,7 +143,7 @@ pub enum SectionType {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunks {
   pub id: Option<u64>,
-  pub comic_number: u64,
+  pub comic_number: ComicNumber,
   pub chunk_text: String,
   pub chunk_index: u64,
   pub section_type: Option<SectionType>,
@@ -0,0 +1,459 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use libsql::{Builder, Connection, OpenFlags};
+
+use crate::Database;
+use crate::embedding::{EmbeddingModel, check_model};
+use crate::error::{DatabaseError, Result};
+use crate::migrations::{SCHEMA_VERSION, read_schema_version};
+use crate::models::Metadata;
+use crate::pool::{DEFAULT_BUSY_TIMEOUT, DEFAULT_READERS};
+use crate::storage::EmbeddingStorage;
+use crate::vector_index::VectorIndexOptions;
+
+/// How [`DatabaseOptions::open`] treats the database file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OpenMode {
+  /// Open the file if it exists, otherwise create and initialize it.
+  #[default]
+  CreateIfMissing,
+  /// Open an existing, initialized file; fail if there isn't one.
+  MustExist,
+  /// Open an existing file without ever writing to it. Pending migrations
+  /// are not applied, so the file must already be at [`SCHEMA_VERSION`].
+  ReadOnly,
+}
+
+/// Value for `PRAGMA synchronous`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+  Off,
+  Normal,
+  Full,
+  Extra,
+}
+
+impl Synchronous {
+  fn as_sql(self) -> &'static str {
+    match self {
+      Self::Off => "OFF",
+      Self::Normal => "NORMAL",
+      Self::Full => "FULL",
+      Self::Extra => "EXTRA",
+    }
+  }
+}
+
+/// Settings for opening a [`Database`].
+///
+/// Pragmas left unset keep SQLite's defaults. Every setting is applied to
+/// each connection the handle opens.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> db::Result<()> {
+/// use db::{DatabaseOptions, OpenMode};
+///
+/// // the bot only ever reads the file the scraper writes
+/// let db = DatabaseOptions::new("xkcd.db")
+///   .mode(OpenMode::ReadOnly)
+///   .mmap_size(256 * 1024 * 1024)
+///   .open()
+///   .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DatabaseOptions {
+  path: PathBuf,
+  mode: OpenMode,
+  busy_timeout: Duration,
+  readers: usize,
+  synchronous: Option<Synchronous>,
+  cache_size: Option<i64>,
+  mmap_size: Option<u64>,
+  embedding_model: Option<EmbeddingModel>,
+  vector_index: Option<VectorIndexOptions>,
+  embedding_storage: EmbeddingStorage,
+}
+
+impl DatabaseOptions {
+  /// Options for the database file at `path`, with the same behaviour as
+  /// [`Database::new`].
+  #[must_use]
+  pub fn new(path: impl AsRef<Path>) -> Self {
+    Self {
+      path: path.as_ref().to_path_buf(),
+      mode: OpenMode::default(),
+      busy_timeout: DEFAULT_BUSY_TIMEOUT,
+      readers: DEFAULT_READERS,
+      synchronous: None,
+      cache_size: None,
+      mmap_size: None,
+      embedding_model: None,
+      vector_index: None,
+      embedding_storage: EmbeddingStorage::default(),
+    }
+  }
+
+  /// Options for a fresh in-memory database, gone when the last handle is
+  /// dropped.
+  #[must_use]
+  pub fn in_memory() -> Self {
+    Self::new(":memory:")
+  }
+
+  #[must_use]
+  pub fn mode(mut self, mode: OpenMode) -> Self {
+    self.mode = mode;
+    self
+  }
+
+  /// How long a connection waits for a lock held elsewhere before failing.
+  #[must_use]
+  pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+    self.busy_timeout = timeout;
+    self
+  }
+
+  /// Number of read connections to keep open (at least one). Ignored for
+  /// in-memory databases, which have a single connection.
+  #[must_use]
+  pub fn readers(mut self, readers: usize) -> Self {
+    self.readers = readers.max(1);
+    self
+  }
+
+  #[must_use]
+  pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+    self.synchronous = Some(synchronous);
+    self
+  }
+
+  /// `PRAGMA cache_size`: pages if positive, KiB if negative.
+  #[must_use]
+  pub fn cache_size(mut self, cache_size: i64) -> Self {
+    self.cache_size = Some(cache_size);
+    self
+  }
+
+  /// `PRAGMA mmap_size` in bytes; `0` disables memory-mapped I/O.
+  #[must_use]
+  pub fn mmap_size(mut self, bytes: u64) -> Self {
+    self.mmap_size = Some(bytes);
+    self
+  }
+
+  /// The model the stored embeddings come from. A new database records it
+  /// ([`EmbeddingModel::QWEN3_EMBEDDING_0_6B`] if unset); an existing one
+  /// must already hold it.
+  #[must_use]
+  pub fn embedding_model(mut self, model: EmbeddingModel) -> Self {
+    self.embedding_model = Some(model);
+    self
+  }
+
+  /// How a new database builds its vector index. Ignored when opening an
+  /// existing file; use [`Database::configure_vector_index`] to change it.
+  #[must_use]
+  pub fn vector_index(mut self, options: VectorIndexOptions) -> Self {
+    self.vector_index = Some(options);
+    self
+  }
+
+  /// How a new database stores embeddings for the vector index. Ignored when
+  /// opening an existing file, which keeps the mode it was created with.
+  #[must_use]
+  pub fn embedding_storage(mut self, storage: EmbeddingStorage) -> Self {
+    self.embedding_storage = storage;
+    self
+  }
+
+  pub(crate) fn storage_for_new_database(&self) -> EmbeddingStorage {
+    self.embedding_storage
+  }
+
+  /// The vector index options for a database being created, if not the
+  /// defaults.
+  pub(crate) fn vector_index_for_new_database(&self) -> Option<&VectorIndexOptions> {
+    self.vector_index.as_ref()
+  }
+
+  /// The model to record in a database being created.
+  pub(crate) fn model_for_new_database(&self) -> EmbeddingModel {
+    self.embedding_model.clone().unwrap_or_default()
+  }
+
+  pub(crate) fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub(crate) fn is_in_memory(&self) -> bool {
+    self.path == Path::new(":memory:")
+  }
+
+  pub(crate) fn is_read_only(&self) -> bool {
+    self.mode == OpenMode::ReadOnly
+  }
+
+  pub(crate) fn readers_count(&self) -> usize {
+    self.readers
+  }
+
+  /// Apply the per-connection settings to `conn`.
+  pub(crate) async fn configure(&self, conn: &Connection, read_only: bool) -> Result<()> {
+    conn
+      .busy_timeout(self.busy_timeout)
+      .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+
+    // foreign keys are off by default and have to be enabled per connection
+    let mut pragmas = vec!["PRAGMA foreign_keys = ON".to_string()];
+    if read_only {
+      pragmas.push("PRAGMA query_only = ON".to_string());
+    }
+    if let Some(synchronous) = self.synchronous {
+      pragmas.push(format!("PRAGMA synchronous = {}", synchronous.as_sql()));
+    }
+    if let Some(cache_size) = self.cache_size {
+      pragmas.push(format!("PRAGMA cache_size = {cache_size}"));
+    }
+    if let Some(mmap_size) = self.mmap_size {
+      pragmas.push(format!("PRAGMA mmap_size = {mmap_size}"));
+    }
+
+    for pragma in pragmas {
+      // some pragmas report their new value as a row, so run them as queries
+      conn
+        .query(&pragma, ())
+        .await
+        .map_err(|e| match DatabaseError::query(e) {
+          DatabaseError::QueryFailed(e) => DatabaseError::QueryFailed(format!("{pragma}: {e}")),
+          e => e,
+        })?;
+    }
+    Ok(())
+  }
+
+  /// Open the database with these options.
+  ///
+  /// # Errors
+  /// Returns [`DatabaseError::InitializationError`] if the file is missing in
+  /// [`OpenMode::MustExist`] or [`OpenMode::ReadOnly`], or exists but was
+  /// never initialized, and [`DatabaseError::EmbeddingModelMismatch`] if an
+  /// existing file holds embeddings from a different model than the one
+  /// given. A read-only open of a file that still needs
+  /// migrating fails with [`DatabaseError::SchemaTooOld`].
+  pub async fn open(&self) -> Result<Database> {
+    let exists = !self.is_in_memory() && std::fs::metadata(&self.path).is_ok();
+
+    match self.mode {
+      OpenMode::CreateIfMissing if !exists => return Database::init(self).await,
+      OpenMode::MustExist | OpenMode::ReadOnly if self.is_in_memory() => {
+        return Err(DatabaseError::InitializationError(
+          "an in-memory database can only be created".to_string(),
+        ));
+      }
+      OpenMode::MustExist | OpenMode::ReadOnly if !exists => {
+        return Err(DatabaseError::InitializationError(format!(
+          "{} does not exist",
+          self.path.display()
+        )));
+      }
+      _ => {}
+    }
+
+    let flags = if self.is_read_only() {
+      OpenFlags::SQLITE_OPEN_READ_ONLY
+    } else {
+      OpenFlags::SQLITE_OPEN_READ_WRITE
+    };
+    let db = Builder::new_local(&self.path)
+      .flags(flags)
+      .build()
+      .await
+      .map_err(DatabaseError::query)?;
+
+    let database = Database::from_libsql(db, self).await?;
+    let initialized: Metadata = database.get_metadata("INITIALIZED").await?;
+    if initialized.value != "true" {
+      return Err(DatabaseError::InitializationError(
+        "Database Schema Mismatch - File exists".to_string(),
+      ));
+    }
+
+    if self.is_read_only() {
+      let found = read_schema_version(&*database.reader().await?).await?;
+      if found < SCHEMA_VERSION {
+        return Err(DatabaseError::SchemaTooOld {
+          found,
+          required: SCHEMA_VERSION,
+        });
+      }
+      if found > SCHEMA_VERSION {
+        return Err(DatabaseError::SchemaTooNew {
+          found,
+          supported: SCHEMA_VERSION,
+        });
+      }
+    } else {
+      database.migrate().await?;
+    }
+
+    // the model is recorded from migration 004 on
+    if let Some(model) = &self.embedding_model {
+      check_model(&*database.reader().await?, model).await?;
+    }
+    Ok(database)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::make_comic;
+
+  async fn pragma(db: &Database, name: &str) -> i64 {
+    let conn = db.reader().await.unwrap();
+    let mut rows = conn.query(&format!("PRAGMA {name}"), ()).await.unwrap();
+    rows.next().await.unwrap().unwrap().get(0).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_must_exist_refuses_missing_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let result = DatabaseOptions::new(&path)
+      .mode(OpenMode::MustExist)
+      .open()
+      .await;
+    assert!(matches!(result, Err(DatabaseError::InitializationError(_))));
+    assert!(!path.exists());
+
+    Database::new(&path).await.unwrap();
+    assert!(
+      DatabaseOptions::new(&path)
+        .mode(OpenMode::MustExist)
+        .open()
+        .await
+        .is_ok()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_read_only_sees_writes_but_cannot_write() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let scraper = Database::new(&path).await.unwrap();
+    let bot = DatabaseOptions::new(&path)
+      .mode(OpenMode::ReadOnly)
+      .open()
+      .await
+      .unwrap();
+
+    scraper.insert_comic(make_comic(1)).await.unwrap();
+    assert_eq!(
+      bot.get_comic_by_number(1).await.unwrap().unwrap().title,
+      "C1"
+    );
+
+    assert!(matches!(
+      bot.insert_comic(make_comic(2)).await,
+      Err(DatabaseError::ReadOnly)
+    ));
+    assert!(
+      bot
+        .reader()
+        .await
+        .unwrap()
+        .execute("DELETE FROM xkcd_comics", ())
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_read_only_refuses_outdated_schema() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let raw = Builder::new_local(&path).build().await.unwrap();
+    raw
+      .connect()
+      .unwrap()
+      .execute_batch(crate::migrations::MIGRATIONS[0].sql)
+      .await
+      .unwrap();
+    drop(raw);
+
+    let result = DatabaseOptions::new(&path)
+      .mode(OpenMode::ReadOnly)
+      .open()
+      .await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::SchemaTooOld { found: 1, .. })
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_pre_model_schema_migrated_before_model_check() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let raw = Builder::new_local(&path).build().await.unwrap();
+    crate::migrations::apply_migrations(
+      &raw.connect().unwrap(),
+      &crate::migrations::MIGRATIONS[..3],
+    )
+    .await
+    .unwrap();
+    drop(raw);
+
+    let result = DatabaseOptions::new(&path)
+      .mode(OpenMode::ReadOnly)
+      .embedding_model(EmbeddingModel::QWEN3_EMBEDDING_0_6B)
+      .open()
+      .await;
+    assert!(matches!(
+      result,
+      Err(DatabaseError::SchemaTooOld { found: 3, .. })
+    ));
+
+    let db = DatabaseOptions::new(&path)
+      .embedding_model(EmbeddingModel::QWEN3_EMBEDDING_0_6B)
+      .open()
+      .await
+      .unwrap();
+    assert_eq!(db.schema_version().await.unwrap(), SCHEMA_VERSION);
+    assert_eq!(
+      db.embedding_model().await.unwrap(),
+      EmbeddingModel::QWEN3_EMBEDDING_0_6B
+    );
+  }
+
+  #[tokio::test]
+  async fn test_in_memory_cannot_be_opened_read_only() {
+    let result = DatabaseOptions::in_memory()
+      .mode(OpenMode::ReadOnly)
+      .open()
+      .await;
+    assert!(matches!(result, Err(DatabaseError::InitializationError(_))));
+    assert!(DatabaseOptions::in_memory().open().await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_pragmas_applied() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db = DatabaseOptions::new(temp_dir.path().join("test.db"))
+      .synchronous(Synchronous::Normal)
+      .cache_size(-64000)
+      .mmap_size(1 << 20)
+      .open()
+      .await
+      .unwrap();
+
+    assert_eq!(pragma(&db, "synchronous").await, 1);
+    assert_eq!(pragma(&db, "cache_size").await, -64000);
+    assert_eq!(pragma(&db, "mmap_size").await, 1 << 20);
+    assert_eq!(pragma(&db, "foreign_keys").await, 1);
+  }
+}
@@ -0,0 +1,334 @@
+use std::ops::Deref;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use libsql::Connection;
+use tokio::sync::{Mutex, MutexGuard, Semaphore, SemaphorePermit};
+
+use crate::Database;
+use crate::error::{DatabaseError, Result};
+use crate::options::DatabaseOptions;
+
+/// Number of read connections opened for a file database.
+pub(crate) const DEFAULT_READERS: usize = 4;
+
+/// How long a connection waits on a lock held by another connection (or
+/// another process, e.g. the scraper) before failing with `SQLITE_BUSY`.
+pub(crate) const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The connections behind a [`Database`] handle.
+///
+/// All writes go through the single `writer` connection, one at a time, in
+/// the order they asked for it. Reads are spread over a pool of separate
+/// connections, which in WAL mode see the last committed state and never wait
+/// on the writer, so a long `insert_chunks_batch` doesn't hold up searches.
+pub(crate) struct Connections {
+  // keeps the underlying database open for as long as any handle exists
+  db: libsql::Database,
+  options: DatabaseOptions,
+  writer: Mutex<Connection>,
+  readers: StdMutex<Vec<Connection>>,
+  available: Semaphore,
+  read_only: bool,
+  /// Reads take the writer, as an in-memory database has no other
+  /// connection to read from.
+  read_through_writer: bool,
+}
+
+impl Connections {
+  /// Open the writer and the read connections on `db`.
+  ///
+  /// Every connection to `:memory:` is a separate empty database, so an
+  /// in-memory database has no read connections and reads wait for the
+  /// writer instead.
+  pub(crate) async fn open(db: libsql::Database, options: &DatabaseOptions) -> Result<Self> {
+    let read_only = options.is_read_only();
+    let writer = db
+      .connect()
+      .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+    options.configure(&writer, read_only).await?;
+
+    let read_through_writer = options.is_in_memory();
+    let mut pool = Vec::new();
+    if !read_through_writer {
+      for _ in 0..options.readers_count() {
+        let reader = db
+          .connect()
+          .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+        options.configure(&reader, true).await?;
+        pool.push(reader);
+      }
+    }
+
+    Ok(Self {
+      db,
+      options: options.clone(),
+      writer: Mutex::new(writer),
+      available: Semaphore::new(pool.len()),
+      readers: StdMutex::new(pool),
+      read_only,
+      read_through_writer,
+    })
+  }
+}
+
+/// A read connection checked out of the pool, returned on drop, a
+/// connection opened for one caller, or the writer of an in-memory database.
+pub(crate) enum ReaderGuard<'a> {
+  Pooled {
+    conn: Option<Connection>,
+    pool: &'a Connections,
+    _permit: SemaphorePermit<'a>,
+  },
+  Unpooled(Connection),
+  Writer(MutexGuard<'a, Connection>),
+}
+
+impl Deref for ReaderGuard<'_> {
+  type Target = Connection;
+
+  fn deref(&self) -> &Connection {
+    match self {
+      Self::Pooled { conn, .. } => conn.as_ref().expect("connection is only taken on drop"),
+      Self::Unpooled(conn) => conn,
+      Self::Writer(conn) => conn,
+    }
+  }
+}
+
+impl Drop for ReaderGuard<'_> {
+  fn drop(&mut self) {
+    if let Self::Pooled { conn, pool, .. } = self
+      && let Some(conn) = conn.take()
+    {
+      pool
+        .readers
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .push(conn);
+    }
+  }
+}
+
+impl Database {
+  /// Check out a read connection, waiting if all of them are in use.
+  ///
+  /// Don't hold one while asking for another or for the writer: an
+  /// in-memory database reads through its writer.
+  pub(crate) async fn reader(&self) -> Result<ReaderGuard<'_>> {
+    let pool = &*self.inner;
+    if pool.read_through_writer {
+      return Ok(ReaderGuard::Writer(pool.writer.lock().await));
+    }
+    let permit = pool
+      .available
+      .acquire()
+      .await
+      .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+    let conn = pool
+      .readers
+      .lock()
+      .unwrap_or_else(std::sync::PoisonError::into_inner)
+      .pop()
+      .ok_or_else(|| DatabaseError::Connection("reader pool is empty".to_string()))?;
+    Ok(ReaderGuard::Pooled {
+      conn: Some(conn),
+      pool,
+      _permit: permit,
+    })
+  }
+
+  /// Open a read connection outside the pool, closed on drop, for work that
+  /// can't run under `query_only`. An in-memory database reads through its
+  /// writer instead.
+  pub(crate) async fn unpooled_reader(&self) -> Result<ReaderGuard<'_>> {
+    let pool = &*self.inner;
+    if pool.read_through_writer {
+      return Ok(ReaderGuard::Writer(pool.writer.lock().await));
+    }
+    let conn = pool
+      .db
+      .connect()
+      .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+    pool.options.configure(&conn, false).await?;
+    Ok(ReaderGuard::Unpooled(conn))
+  }
+
+  /// Wait for exclusive use of the write connection. Writers are served in
+  /// the order they ask.
+  ///
+  /// # Errors
+  /// Returns [`DatabaseError::ReadOnly`] if the database was opened with
+  /// [`OpenMode::ReadOnly`](crate::OpenMode::ReadOnly).
+  pub(crate) async fn writer(&self) -> Result<MutexGuard<'_, Connection>> {
+    if self.inner.read_only {
+      return Err(DatabaseError::ReadOnly);
+    }
+    Ok(self.inner.writer.lock().await)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::EMBEDDING_DIM;
+  use crate::embedding::EmbeddingModel;
+  use crate::models::Chunks;
+  use crate::test_support::{make_chunk, make_comic};
+
+  fn distinct_chunk(comic: u64, idx: u64) -> Chunks {
+    Chunks {
+      // distinct vectors, the index is slow to build over identical ones
+      embedding: (0..EMBEDDING_DIM)
+        .map(|i| ((i as u64 * (idx + 1) + comic) % 7) as f32 + 1.0)
+        .collect(),
+      ..make_chunk(comic, idx)
+    }
+  }
+
+  #[test]
+  fn test_database_is_shareable() {
+    fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+    assert_shareable::<Database>();
+  }
+
+  #[tokio::test]
+  async fn test_every_connection_configured() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db = Database::new(temp_dir.path().join("test.db"))
+      .await
+      .unwrap();
+
+    // check out every reader at once so each one gets looked at
+    let mut readers = Vec::new();
+    for _ in 0..DEFAULT_READERS {
+      readers.push(db.reader().await.unwrap());
+    }
+    for reader in &readers {
+      let mut rows = reader.query("PRAGMA foreign_keys", ()).await.unwrap();
+      let enabled: i64 = rows.next().await.unwrap().unwrap().get(0).unwrap();
+      assert_eq!(enabled, 1);
+      assert!(
+        reader.execute("DELETE FROM metadata", ()).await.is_err(),
+        "readers must be read-only"
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn test_in_memory_reads_wait_for_open_write() {
+    let db = Database::new(":memory:").await.unwrap();
+    let writer = db.writer().await.unwrap();
+    let tx = writer.transaction().await.unwrap();
+    tx.execute(
+      "INSERT INTO metadata (key, value) VALUES ('PENDING', 'yes')",
+      (),
+    )
+    .await
+    .unwrap();
+
+    // the only connection is mid-transaction, so the read has to wait
+    assert!(
+      tokio::time::timeout(Duration::from_millis(50), db.reader())
+        .await
+        .is_err()
+    );
+    tx.commit().await.unwrap();
+    drop(writer);
+
+    let reader = db.reader().await.unwrap();
+    let tx = reader.transaction().await.unwrap();
+    let mut rows = tx
+      .query("SELECT value FROM metadata WHERE key = 'PENDING'", ())
+      .await
+      .unwrap();
+    let value: String = rows.next().await.unwrap().unwrap().get(0).unwrap();
+    assert_eq!(value, "yes");
+  }
+
+  #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+  async fn test_parallel_searches_during_batch_insert() {
+    const BATCH: usize = 200;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db = Database::new(temp_dir.path().join("test.db"))
+      .await
+      .unwrap();
+    db.insert_comic(make_comic(1)).await.unwrap();
+    db.insert_comic(make_comic(2)).await.unwrap();
+    db.insert_chunk(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, distinct_chunk(1, 0))
+      .await
+      .unwrap();
+
+    let writer = {
+      let db = db.clone();
+      tokio::spawn(async move {
+        let chunks = (0..BATCH as u64).map(|i| distinct_chunk(2, i)).collect();
+        db.insert_chunks_batch(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, chunks)
+          .await
+      })
+    };
+
+    let searchers: Vec<_> = (0..4)
+      .map(|_| {
+        let db = db.clone();
+        tokio::spawn(async move {
+          let mut searches = 0;
+          loop {
+            let done = db.get_chunks_for_comic(2).await.unwrap().len();
+            // the batch is one transaction: all or nothing
+            assert!(done == 0 || done == BATCH, "saw {done} chunks");
+            let results = db
+              .vector_search(
+                &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+                vec![0.5; EMBEDDING_DIM],
+                10,
+              )
+              .await
+              .unwrap();
+            assert!(!results.is_empty());
+            searches += 1;
+            if done == BATCH {
+              return searches;
+            }
+            tokio::task::yield_now().await;
+          }
+        })
+      })
+      .collect();
+
+    writer.await.unwrap().unwrap();
+    for searcher in searchers {
+      assert!(searcher.await.unwrap() > 0);
+    }
+  }
+
+  #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+  async fn test_concurrent_writers_are_serialized() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db = Database::new(temp_dir.path().join("test.db"))
+      .await
+      .unwrap();
+
+    let tasks: Vec<_> = (1..=8)
+      .map(|n| {
+        let db = db.clone();
+        tokio::spawn(async move {
+          db.insert_comic(make_comic(n)).await?;
+          db.insert_chunks_batch(
+            &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+            (0..20).map(|i| distinct_chunk(n, i)).collect(),
+          )
+          .await
+        })
+      })
+      .collect();
+    for task in tasks {
+      task.await.unwrap().unwrap();
+    }
+
+    for n in 1..=8 {
+      assert_eq!(db.get_chunks_for_comic(n).await.unwrap().len(), 20);
+    }
+  }
+}
@@ -0,0 +1,207 @@
+use std::time::{Duration, Instant};
+
+use libsql::params;
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::chunks::f32_blob_to_vec;
+use crate::embedding::EmbeddingModel;
+use crate::error::{DatabaseError, Result};
+use crate::search::{ChunkSearchResult, SearchOptions};
+
+/// Distances closer than this to the exact k-th distance count as ties.
+const TIE_EPSILON: f64 = 1e-6;
+
+/// How well the vector index agrees with exact search, from
+/// [`Database::evaluate_recall`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecallReport {
+  /// The `k` in recall@k.
+  pub k: usize,
+  /// Recall of each query, in the order given.
+  pub recalls: Vec<f64>,
+  pub mean_recall: f64,
+  pub min_recall: f64,
+  /// Mean time per query through the index.
+  pub mean_ann_latency: Duration,
+  /// Mean time per query scanning every chunk.
+  pub mean_exact_latency: Duration,
+}
+
+/// Fraction of the exact top-k that the approximate search found. A result
+/// counts if it's as close as the exact k-th result, so equally distant
+/// chunks are interchangeable.
+fn recall(ann: &[ChunkSearchResult], exact: &[ChunkSearchResult]) -> f64 {
+  let Some(kth) = exact.last() else {
+    // nothing passes the filters, so there's nothing to miss
+    return 1.0;
+  };
+  let found = ann
+    .iter()
+    .filter(|r| r.distance <= kth.distance + TIE_EPSILON)
+    .count()
+    .min(exact.len());
+  found as f64 / exact.len() as f64
+}
+
+impl Database {
+  /// Measure recall@k of the vector index for `queries`.
+  ///
+  /// Each query is run through [`Database::vector_search_with_options`] and
+  /// [`Database::exact_search_with_options`] with `options`, whose `top_k`
+  /// is the `k`. Use it to compare index settings on the real corpus.
+  ///
+  /// # Errors
+  /// Returns an error if `model` isn't the model recorded in the database, a
+  /// query has the wrong dimension, or a search fails.
+  pub async fn evaluate_recall(
+    &self,
+    model: &EmbeddingModel,
+    queries: &[Vec<f32>],
+    options: &SearchOptions,
+  ) -> Result<RecallReport> {
+    let mut recalls = Vec::with_capacity(queries.len());
+    let mut ann_time = Duration::ZERO;
+    let mut exact_time = Duration::ZERO;
+    for query in queries {
+      let start = Instant::now();
+      let ann = self
+        .vector_search_with_options(model, query.clone(), options)
+        .await?;
+      ann_time += start.elapsed();
+
+      let start = Instant::now();
+      let exact = self
+        .exact_search_with_options(model, query.clone(), options)
+        .await?;
+      exact_time += start.elapsed();
+
+      recalls.push(recall(&ann, &exact));
+    }
+
+    // with no queries there's nothing to miss, as in `recall`
+    let n = queries.len().max(1);
+    let mean_recall = if recalls.is_empty() {
+      1.0
+    } else {
+      recalls.iter().sum::<f64>() / n as f64
+    };
+    Ok(RecallReport {
+      k: options.top_k,
+      mean_recall,
+      min_recall: recalls.iter().copied().fold(1.0, f64::min),
+      recalls,
+      mean_ann_latency: ann_time / n as u32,
+      mean_exact_latency: exact_time / n as u32,
+    })
+  }
+
+  /// Up to `n` stored chunk embeddings picked at random, for use as
+  /// [`Database::evaluate_recall`] queries when no real queries are at hand.
+  ///
+  /// # Errors
+  /// Returns an error if the query fails.
+  pub async fn sample_query_embeddings(&self, n: usize) -> Result<Vec<Vec<f32>>> {
+    let conn = self.reader().await?;
+    let mut rows = conn
+      .query(
+        "SELECT embedding FROM xkcd_chunks ORDER BY random() LIMIT ?",
+        params![n as i64],
+      )
+      .await
+      .map_err(DatabaseError::query)?;
+    let mut embeddings = Vec::new();
+    while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+      let blob: Vec<u8> = row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+      embeddings.push(f32_blob_to_vec(&blob));
+    }
+    Ok(embeddings)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::EMBEDDING_DIM;
+  use crate::models::Chunks;
+  use crate::test_support::{make_chunk, make_comic};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  fn hit(chunk_id: u64, distance: f64) -> ChunkSearchResult {
+    ChunkSearchResult {
+      chunk_id,
+      comic_number: 1,
+      chunk_text: String::new(),
+      section_type: None,
+      comic_title: String::new(),
+      xkcd_url: String::new(),
+      hover_text: None,
+      distance,
+    }
+  }
+
+  /// Deterministic pseudo-random embedding.
+  fn embedding(seed: u64) -> Vec<f32> {
+    let mut state = seed
+      .wrapping_mul(6364136223846793005)
+      .wrapping_add(1442695040888963407);
+    (0..EMBEDDING_DIM)
+      .map(|_| {
+        state = state
+          .wrapping_mul(6364136223846793005)
+          .wrapping_add(1442695040888963407);
+        (state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_recall() {
+    let exact = [hit(1, 0.1), hit(2, 0.2), hit(3, 0.3)];
+    assert_eq!(recall(&exact, &exact), 1.0);
+    let missed = [hit(1, 0.1), hit(2, 0.2), hit(9, 0.5)];
+    assert!((recall(&missed, &exact) - 2.0 / 3.0).abs() < 1e-9);
+    // a different chunk at the same distance as the k-th is as good
+    let tied = [hit(1, 0.1), hit(2, 0.2), hit(4, 0.3)];
+    assert_eq!(recall(&tied, &exact), 1.0);
+    assert_eq!(recall(&[], &[]), 1.0);
+    assert_eq!(recall(&[], &exact), 0.0);
+  }
+
+  #[tokio::test]
+  async fn test_evaluate_recall() {
+    let db = Database::new(":memory:").await.unwrap();
+    db.insert_comic(make_comic(1)).await.unwrap();
+    let chunks = (0..50)
+      .map(|i| Chunks {
+        embedding: embedding(i),
+        ..make_chunk(1, i)
+      })
+      .collect();
+    db.insert_chunks_batch(&MODEL, chunks).await.unwrap();
+
+    let queries = db.sample_query_embeddings(5).await.unwrap();
+    assert_eq!(queries.len(), 5);
+    assert_eq!(queries[0].len(), EMBEDDING_DIM);
+
+    let report = db
+      .evaluate_recall(&MODEL, &queries, &SearchOptions::new(5))
+      .await
+      .unwrap();
+    assert_eq!(report.k, 5);
+    assert_eq!(report.recalls.len(), 5);
+    assert!(report.min_recall <= report.mean_recall);
+    // every query is a stored chunk, which both searches must find first
+    assert!(report.min_recall >= 0.2);
+
+    let empty = db
+      .evaluate_recall(&MODEL, &[], &SearchOptions::new(5))
+      .await
+      .unwrap();
+    assert!(empty.recalls.is_empty());
+    assert_eq!(empty.mean_recall, 1.0);
+  }
+}
@@ -0,0 +1,521 @@
+use libsql::{Connection, TransactionBehavior, params};
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::chunks::{validate_embedding, vec_to_json_string};
+use crate::embedding::{Embedder, EmbeddingModel, validate_model, write_model};
+use crate::error::{DatabaseError, Result};
+
+/// Metadata key holding the model a re-embedding job is moving to, as JSON.
+pub(crate) const TARGET_KEY: &str = "REEMBED_TARGET_MODEL";
+/// Metadata key holding the highest chunk id the job has embedded.
+pub(crate) const CHECKPOINT_KEY: &str = "REEMBED_CHECKPOINT";
+
+/// Chunks that don't have a vector from the target model yet.
+const PENDING_SQL: &str = "FROM xkcd_chunks c
+  WHERE NOT EXISTS (SELECT 1 FROM xkcd_chunks_reembed r WHERE r.chunk_id = c.id)";
+
+/// State of an unfinished re-embedding job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReembedProgress {
+  /// The model the job is moving to.
+  pub target: EmbeddingModel,
+  /// Highest chunk id embedded so far; the job resumes after it.
+  pub checkpoint: u64,
+  /// Chunks that already have a vector from `target`.
+  pub embedded: u64,
+  /// Chunks in the database.
+  pub total: u64,
+}
+
+async fn read_target(conn: &Connection) -> Result<Option<EmbeddingModel>> {
+  let mut rows = conn
+    .query(
+      "SELECT value FROM metadata WHERE key = ?",
+      params![TARGET_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let Some(row) = rows.next().await.map_err(DatabaseError::query)? else {
+    return Ok(None);
+  };
+  let value: String = row
+    .get(0)
+    .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+  serde_json::from_str(&value)
+    .map(Some)
+    .map_err(|_| DatabaseError::MetaParseFailed(format!("{TARGET_KEY}={value}")))
+}
+
+async fn read_checkpoint(conn: &Connection) -> Result<u64> {
+  let mut rows = conn
+    .query(
+      "SELECT value FROM metadata WHERE key = ?",
+      params![CHECKPOINT_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  match rows.next().await.map_err(DatabaseError::query)? {
+    Some(row) => {
+      let value: String = row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+      value
+        .parse()
+        .map_err(|_| DatabaseError::MetaParseFailed(format!("{CHECKPOINT_KEY}={value}")))
+    }
+    None => Ok(0),
+  }
+}
+
+/// Fail unless the job in progress is moving to `target`.
+async fn ensure_target(conn: &Connection, target: &EmbeddingModel) -> Result<()> {
+  match read_target(conn).await? {
+    Some(current) if &current == target => Ok(()),
+    Some(current) => Err(DatabaseError::ReembedConflict(format!(
+      "a job moving to {} is in progress",
+      current.id
+    ))),
+    None => Err(DatabaseError::ReembedConflict(format!(
+      "the job moving to {} was cancelled",
+      target.id
+    ))),
+  }
+}
+
+async fn count(conn: &Connection, sql: &str) -> Result<u64> {
+  let mut rows = conn.query(sql, ()).await.map_err(DatabaseError::query)?;
+  let row = rows
+    .next()
+    .await
+    .map_err(DatabaseError::query)?
+    .ok_or_else(|| DatabaseError::QueryFailed("COUNT(*) returned no rows".to_string()))?;
+  row
+    .get(0)
+    .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))
+}
+
+impl Database {
+  /// The unfinished re-embedding job, if there is one.
+  pub async fn reembed_progress(&self) -> Result<Option<ReembedProgress>> {
+    let conn = self.reader().await?;
+    let Some(target) = read_target(&conn).await? else {
+      return Ok(None);
+    };
+    Ok(Some(ReembedProgress {
+      target,
+      checkpoint: read_checkpoint(&conn).await?,
+      embedded: count(&conn, "SELECT COUNT(*) FROM xkcd_chunks_reembed").await?,
+      total: count(&conn, "SELECT COUNT(*) FROM xkcd_chunks").await?,
+    }))
+  }
+
+  /// Re-embed every chunk with `embedder` and switch the database over to
+  /// its model.
+  ///
+  /// Chunks are read `batch_size` at a time and their new vectors written to
+  /// a shadow table, with a checkpoint in `metadata` after every batch, so an
+  /// interrupted job picks up where it stopped when called again with the
+  /// same model. The write lock is only held while a batch is saved, and
+  /// searches (and inserts, which still take the old model) keep using the
+  /// current vectors throughout. Chunks added while the job runs are embedded
+  /// too. Once every chunk has a new vector they are copied over the old ones
+  /// and the new model is recorded in a single transaction.
+  ///
+  /// Intended to run in a spawned task; returns the number of chunks embedded
+  /// by this call.
+  ///
+  /// # Errors
+  /// Returns [`DatabaseError::ReembedConflict`] if a job moving to a different
+  /// model is unfinished (see [`Database::cancel_reembed`]) or this one was
+  /// cancelled while running, [`DatabaseError::Embedding`] if the embedder
+  /// fails or returns the wrong number of vectors, and
+  /// [`DatabaseError::InvalidEmbeddingDimension`] if a vector has the wrong
+  /// length. The checkpoint is kept on error.
+  pub async fn reembed<E: Embedder>(&self, embedder: &E, batch_size: usize) -> Result<u64> {
+    let target = embedder.model().clone();
+    validate_model(&target)?;
+    let batch_size = batch_size.max(1);
+
+    let mut after = self.start_reembed(&target).await?;
+    let mut embedded = 0;
+    loop {
+      let batch = self.pending_chunks(after, batch_size).await?;
+      let Some(&(last_id, _)) = batch.last() else {
+        if self.finish_reembed(&target).await? {
+          return Ok(embedded);
+        }
+        // chunks were added behind the checkpoint, go round again
+        after = 0;
+        continue;
+      };
+
+      let (ids, texts): (Vec<u64>, Vec<String>) = batch.into_iter().unzip();
+      let vectors = embedder
+        .embed_documents(&texts)
+        .await
+        .map_err(|e| DatabaseError::Embedding(e.to_string()))?;
+      if vectors.len() != ids.len() {
+        return Err(DatabaseError::Embedding(format!(
+          "asked for {} embeddings, got {}",
+          ids.len(),
+          vectors.len()
+        )));
+      }
+      for vector in &vectors {
+        validate_embedding(vector)?;
+      }
+
+      self.save_batch(&target, &ids, vectors, last_id).await?;
+      embedded += ids.len() as u64;
+      after = last_id;
+    }
+  }
+
+  /// Abandon the unfinished re-embedding job and discard its vectors. The
+  /// database keeps its current model.
+  pub async fn cancel_reembed(&self) -> Result<()> {
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    tx.execute("DELETE FROM xkcd_chunks_reembed", ())
+      .await
+      .map_err(DatabaseError::query)?;
+    tx.execute(
+      "DELETE FROM metadata WHERE key IN (?1, ?2)",
+      params![TARGET_KEY, CHECKPOINT_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(())
+  }
+
+  /// Record a new job for `target`, or resume the one in progress. Returns
+  /// the checkpoint to continue after.
+  async fn start_reembed(&self, target: &EmbeddingModel) -> Result<u64> {
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+
+    if read_target(&tx).await?.is_some() {
+      ensure_target(&tx, target).await?;
+      return read_checkpoint(&tx).await;
+    }
+
+    let target_json =
+      serde_json::to_string(target).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+    tx.execute("DELETE FROM xkcd_chunks_reembed", ())
+      .await
+      .map_err(DatabaseError::query)?;
+    tx.execute(
+      "INSERT INTO metadata (key, value) VALUES (?1, ?2), (?3, '0')
+       ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+      params![TARGET_KEY, target_json, CHECKPOINT_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(0)
+  }
+
+  /// The next `limit` chunks after `after` still waiting for a new vector.
+  async fn pending_chunks(&self, after: u64, limit: usize) -> Result<Vec<(u64, String)>> {
+    let conn = self.reader().await?;
+    let mut rows = conn
+      .query(
+        &format!("SELECT c.id, c.chunk_text {PENDING_SQL} AND c.id > ?1 ORDER BY c.id LIMIT ?2"),
+        params![after, limit as i64],
+      )
+      .await
+      .map_err(DatabaseError::query)?;
+
+    let mut batch = Vec::new();
+    while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+      let id: u64 = row
+        .get(0)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+      let text: String = row
+        .get(1)
+        .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+      batch.push((id, text));
+    }
+    Ok(batch)
+  }
+
+  /// Store a batch of new vectors and move the checkpoint past it.
+  async fn save_batch(
+    &self,
+    target: &EmbeddingModel,
+    ids: &[u64],
+    vectors: Vec<Vec<f32>>,
+    checkpoint: u64,
+  ) -> Result<()> {
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    ensure_target(&tx, target).await?;
+
+    // chunks deleted since the batch was read are skipped
+    let stmt = tx
+      .prepare(
+        "INSERT OR REPLACE INTO xkcd_chunks_reembed (chunk_id, embedding)
+         SELECT id, vector32(?2) FROM xkcd_chunks WHERE id = ?1",
+      )
+      .await
+      .map_err(DatabaseError::prepare)?;
+    for (id, vector) in ids.iter().zip(vectors) {
+      stmt
+        .execute(params![*id, vec_to_json_string(vector)])
+        .await
+        .map_err(DatabaseError::query)?;
+      stmt.reset();
+    }
+    tx.execute(
+      "UPDATE metadata SET value = ?2 WHERE key = ?1",
+      params![CHECKPOINT_KEY, checkpoint.to_string()],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(())
+  }
+
+  /// Swap the new vectors in if every chunk has one. Returns `false`, without
+  /// changing anything, if some chunks are still waiting.
+  async fn finish_reembed(&self, target: &EmbeddingModel) -> Result<bool> {
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    ensure_target(&tx, target).await?;
+
+    if count(&tx, &format!("SELECT COUNT(*) {PENDING_SQL}")).await? > 0 {
+      return Ok(false);
+    }
+
+    // rewriting the column updates the vector index in the same transaction,
+    // so readers go straight from the old index to the new one
+    tx.execute(
+      "UPDATE xkcd_chunks SET embedding = (
+         SELECT r.embedding FROM xkcd_chunks_reembed r WHERE r.chunk_id = xkcd_chunks.id
+       )",
+      (),
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+    write_model(&tx, target).await?;
+    tx.execute("DELETE FROM xkcd_chunks_reembed", ())
+      .await
+      .map_err(DatabaseError::query)?;
+    tx.execute(
+      "DELETE FROM metadata WHERE key IN (?1, ?2)",
+      params![TARGET_KEY, CHECKPOINT_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(true)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use super::*;
+  use crate::EMBEDDING_DIM;
+  use crate::test_support::{make_chunk, make_comic};
+
+  const OLD: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  fn new_model() -> EmbeddingModel {
+    EmbeddingModel {
+      id: "Qwen/Qwen3-Embedding-0.6B-v2".into(),
+      ..OLD
+    }
+  }
+
+  /// Embeds every text as `[-1.0; EMBEDDING_DIM]`, failing once `fail_after`
+  /// batches have been embedded.
+  struct TestEmbedder {
+    model: EmbeddingModel,
+    fail_after: Option<usize>,
+    batches: AtomicUsize,
+  }
+
+  impl TestEmbedder {
+    fn new(model: EmbeddingModel) -> Self {
+      Self {
+        model,
+        fail_after: None,
+        batches: AtomicUsize::new(0),
+      }
+    }
+
+    fn failing_after(model: EmbeddingModel, batches: usize) -> Self {
+      Self {
+        fail_after: Some(batches),
+        ..Self::new(model)
+      }
+    }
+  }
+
+  impl Embedder for TestEmbedder {
+    type Error = std::io::Error;
+
+    fn model(&self) -> &EmbeddingModel {
+      &self.model
+    }
+
+    async fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, Self::Error> {
+      let done = self.batches.fetch_add(1, Ordering::SeqCst);
+      if self.fail_after.is_some_and(|limit| done >= limit) {
+        return Err(std::io::Error::other("embedding service unavailable"));
+      }
+      Ok(texts.iter().map(|_| vec![-1.0; EMBEDDING_DIM]).collect())
+    }
+  }
+
+  /// One comic with `chunks` chunks, all embedded with the old model.
+  async fn setup(chunks: u64) -> Database {
+    let db = Database::new(":memory:").await.unwrap();
+    db.insert_comic(make_comic(1)).await.unwrap();
+    db.insert_chunks_batch(&OLD, (0..chunks).map(|i| make_chunk(1, i)).collect())
+      .await
+      .unwrap();
+    db
+  }
+
+  #[tokio::test]
+  async fn test_reembed_switches_model() {
+    let db = setup(5).await;
+    let job = {
+      let db = db.clone();
+      tokio::spawn(async move { db.reembed(&TestEmbedder::new(new_model()), 2).await })
+    };
+    assert_eq!(job.await.unwrap().unwrap(), 5);
+
+    assert_eq!(db.embedding_model().await.unwrap(), new_model());
+    assert_eq!(db.reembed_progress().await.unwrap(), None);
+    for chunk in db.get_chunks_for_comic(1).await.unwrap() {
+      assert_eq!(chunk.embedding, vec![-1.0; EMBEDDING_DIM]);
+    }
+
+    let results = db
+      .vector_search(&new_model(), vec![-1.0; EMBEDDING_DIM], 5)
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 5);
+    assert!(results[0].distance < 1e-6);
+    assert!(matches!(
+      db.vector_search(&OLD, vec![1.0; EMBEDDING_DIM], 5).await,
+      Err(DatabaseError::EmbeddingModelMismatch { .. })
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_search_uses_old_vectors_until_switch() {
+    let db = setup(5).await;
+    let result = db
+      .reembed(&TestEmbedder::failing_after(new_model(), 1), 2)
+      .await;
+    assert!(matches!(result, Err(DatabaseError::Embedding(_))));
+
+    let progress = db.reembed_progress().await.unwrap().unwrap();
+    assert_eq!(progress.target, new_model());
+    assert_eq!(progress.embedded, 2);
+    assert_eq!(progress.total, 5);
+    assert!(progress.checkpoint > 0);
+
+    // still the old model and the old vectors
+    assert_eq!(db.embedding_model().await.unwrap(), OLD);
+    let results = db
+      .vector_search(&OLD, vec![1.0; EMBEDDING_DIM], 5)
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 5);
+    assert!(results.iter().all(|r| r.distance < 1e-6));
+  }
+
+  #[tokio::test]
+  async fn test_resume_from_checkpoint() {
+    let db = setup(5).await;
+    db.reembed(&TestEmbedder::failing_after(new_model(), 1), 2)
+      .await
+      .unwrap_err();
+
+    // added while the job was interrupted, with the old model
+    db.insert_chunk(&OLD, make_chunk(1, 5)).await.unwrap();
+
+    let embedder = TestEmbedder::new(new_model());
+    let embedded = db.reembed(&embedder, 2).await.unwrap();
+    assert_eq!(embedded, 4);
+    assert_eq!(embedder.batches.load(Ordering::SeqCst), 2);
+
+    let chunks = db.get_chunks_for_comic(1).await.unwrap();
+    assert_eq!(chunks.len(), 6);
+    assert!(
+      chunks
+        .iter()
+        .all(|c| c.embedding == vec![-1.0; EMBEDDING_DIM])
+    );
+  }
+
+  #[tokio::test]
+  async fn test_conflicting_job_refused_until_cancelled() {
+    let db = setup(3).await;
+    db.reembed(&TestEmbedder::failing_after(new_model(), 1), 1)
+      .await
+      .unwrap_err();
+
+    let other = EmbeddingModel {
+      id: "some/other-model".into(),
+      ..OLD
+    };
+    assert!(matches!(
+      db.reembed(&TestEmbedder::new(other.clone()), 1).await,
+      Err(DatabaseError::ReembedConflict(_))
+    ));
+
+    db.cancel_reembed().await.unwrap();
+    assert_eq!(db.reembed_progress().await.unwrap(), None);
+    assert_eq!(
+      db.reembed(&TestEmbedder::new(other.clone()), 1)
+        .await
+        .unwrap(),
+      3
+    );
+    assert_eq!(db.embedding_model().await.unwrap(), other);
+  }
+
+  #[tokio::test]
+  async fn test_wrong_vectors_rejected() {
+    struct ShortEmbedder(EmbeddingModel);
+    impl Embedder for ShortEmbedder {
+      type Error = std::io::Error;
+      fn model(&self) -> &EmbeddingModel {
+        &self.0
+      }
+      async fn embed(&self, texts: &[String]) -> std::result::Result<Vec<Vec<f32>>, Self::Error> {
+        Ok(texts.iter().map(|_| vec![1.0; 3]).collect())
+      }
+    }
+
+    let db = setup(2).await;
+    assert!(matches!(
+      db.reembed(&ShortEmbedder(new_model()), 2).await,
+      Err(DatabaseError::InvalidEmbeddingDimension(_))
+    ));
+    assert_eq!(db.embedding_model().await.unwrap(), OLD);
+  }
+}
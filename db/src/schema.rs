@@ -1,10 +1,12 @@
-use libsql::Builder;
+use libsql::{Builder, TransactionBehavior};
 
+use crate::embedding::{EmbeddingModel, validate_model, write_model};
+use crate::storage::{EmbeddingStorage, set_up_storage};
+use crate::vector_index::{VectorIndexOptions, create_index};
 use crate::{
-  Database,
+  Database, DatabaseOptions,
   error::{DatabaseError, Result},
 };
-use std::path::Path;
 
 /// Represents a database connection.
 ///
@@ -12,11 +14,18 @@ use std::path::Path;
 ///
 ///
 impl Database {
-  pub(crate) async fn init(path: impl AsRef<Path>) -> Result<Self> {
-    let path = path.as_ref();
+  pub(crate) async fn init(options: &DatabaseOptions) -> Result<Self> {
+    let path = options.path();
+    let model = options.model_for_new_database();
+    validate_model(&model)?;
+    let storage = options.storage_for_new_database();
+    let vector_index = options.vector_index_for_new_database();
+    if let Some(vector_index) = vector_index {
+      vector_index.validate(&model, storage)?;
+    }
 
     // check if file exists
-    if std::fs::metadata(path).is_ok() {
+    if !options.is_in_memory() && std::fs::metadata(path).is_ok() {
       return Err(DatabaseError::InitializationError(
         "File already exists".to_string(),
       ));
@@ -24,29 +33,46 @@ impl Database {
     let db = Builder::new_local(path)
       .build()
       .await
-      .map_err(|e| DatabaseError::LibSql(e))?;
+      .map_err(DatabaseError::query)?;
 
+    // WAL can't be switched on inside the migration transaction, and has to
+    // be on before the read connections open
     let conn = db
       .connect()
       .map_err(|e| DatabaseError::Connection(e.to_string()))?;
-
-    // Enable foreign key constraints (must be done per-connection)
     conn
-      .execute("PRAGMA foreign_keys = ON", ())
+      .query("PRAGMA journal_mode = WAL", ())
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::query)?;
+    drop(conn);
 
-    let database = Self { conn };
-    database.create_tables().await?;
+    let database = Self::from_libsql(db, options).await?;
+    database
+      .create_tables(&model, storage, vector_index)
+      .await?;
     Ok(database)
   }
-  async fn create_tables(&self) -> Result<()> {
-    let query = include_str!("../migrations/001_schema.sql");
-    self
-      .conn
-      .execute_batch(query)
+
+  async fn create_tables(
+    &self,
+    model: &EmbeddingModel,
+    storage: EmbeddingStorage,
+    vector_index: Option<&VectorIndexOptions>,
+  ) -> Result<()> {
+    self.migrate().await?;
+
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
       .await
-      .map_err(|e| DatabaseError::QueryFailed(e.to_string()))?;
+      .map_err(DatabaseError::transaction)?;
+    write_model(&tx, model).await?;
+    set_up_storage(&tx, storage).await?;
+    // a quantized database indexes the compact copies instead
+    if vector_index.is_some() || storage != EmbeddingStorage::Full {
+      create_index(&tx, vector_index.unwrap_or(&VectorIndexOptions::default())).await?;
+    }
+    tx.commit().await.map_err(DatabaseError::transaction)?;
     Ok(())
   }
 }
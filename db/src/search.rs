@@ -0,0 +1,647 @@
+use chrono::NaiveDate;
+use libsql::{Connection, Row, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::chunks::{validate_embedding, vec_to_json_string};
+use crate::embedding::{EmbeddingModel, check_model};
+use crate::error::{DatabaseError, Result};
+use crate::models::SectionType;
+use crate::storage::read_storage;
+
+/// How many candidates to pull from the vector index per requested result
+/// when filters are active. Grows by the same factor if that isn't enough.
+const OVERSAMPLE_FACTOR: usize = 4;
+
+/// Result of a vector similarity search operation.
+///
+/// Contains the chunk data along with metadata from the associated comic and
+/// the cosine distance between the chunk's embedding and the query.
+/// Results are ordered by distance (most similar first).
+/// Returned by vector search operations such as [`Database::vector_search`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkSearchResult {
+  /// The unique identifier of the chunk.
+  pub chunk_id: u64,
+  /// The XKCD comic number associated with this chunk.
+  pub comic_number: u64,
+  /// The text content of the chunk.
+  pub chunk_text: String,
+  /// The section type of the chunk (e.g., "transcript", "explanation", etc.), if available.
+  pub section_type: Option<String>,
+  /// The title of the associated XKCD comic.
+  pub comic_title: String,
+  /// The URL to the XKCD comic.
+  pub xkcd_url: String,
+  /// The hover text (alt text) of the comic, if available.
+  pub hover_text: Option<String>,
+  /// Cosine distance between the query and the chunk embedding, as computed by
+  /// `vector_distance_cos` (0 = identical direction, 2 = opposite).
+  pub distance: f64,
+}
+
+impl ChunkSearchResult {
+  /// Cosine similarity between the query and the chunk (`1 - distance`).
+  #[must_use]
+  pub fn similarity(&self) -> f64 {
+    1.0 - self.distance
+  }
+}
+
+/// Filters and limits for a search.
+///
+/// All filters are combined with AND. Leaving a field at its default means
+/// "don't filter on this".
+///
+/// # Example
+/// ```
+/// use db::{SearchOptions, SectionType};
+/// let options = SearchOptions {
+///    section_types: Some(vec![SectionType::Explanation, SectionType::TitleHover]),
+///    exclude_comics: vec![327, 927],
+///    ..SearchOptions::new(5)
+/// };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+  /// Number of results to return.
+  pub top_k: usize,
+  /// Only return chunks from these sections. Chunks without a section type
+  /// never match when this is set.
+  pub section_types: Option<Vec<SectionType>>,
+  /// Lowest comic number to return (inclusive).
+  pub min_comic: Option<u64>,
+  /// Highest comic number to return (inclusive).
+  pub max_comic: Option<u64>,
+  /// Only comics published on or after this date.
+  pub published_from: Option<NaiveDate>,
+  /// Only comics published on or before this date.
+  pub published_until: Option<NaiveDate>,
+  /// Only return chunks from these comics.
+  pub include_comics: Option<Vec<u64>>,
+  /// Never return chunks from these comics (e.g. already posted).
+  pub exclude_comics: Vec<u64>,
+}
+
+impl SearchOptions {
+  /// Unfiltered search returning `top_k` results.
+  #[must_use]
+  pub fn new(top_k: usize) -> Self {
+    Self {
+      top_k,
+      ..Self::default()
+    }
+  }
+
+  /// Whether any filter is set, i.e. whether the index has to be oversampled.
+  #[must_use]
+  pub fn has_filters(&self) -> bool {
+    self.section_types.is_some()
+      || self.min_comic.is_some()
+      || self.max_comic.is_some()
+      || self.published_from.is_some()
+      || self.published_until.is_some()
+      || self.include_comics.is_some()
+      || !self.exclude_comics.is_empty()
+  }
+
+  /// Named parameters for [`FILTER_SQL`].
+  pub(crate) fn filter_params(&self) -> Vec<(String, Value)> {
+    let date = |d: Option<NaiveDate>| d.map(|d| d.format("%Y-%m-%d").to_string());
+    vec![
+      (
+        ":sections".to_string(),
+        self
+          .section_types
+          .as_ref()
+          .map(|s| vec_to_json_string(s.iter().map(ToString::to_string).collect()))
+          .into(),
+      ),
+      (
+        ":min_comic".to_string(),
+        self.min_comic.map(|n| n as i64).into(),
+      ),
+      (
+        ":max_comic".to_string(),
+        self.max_comic.map(|n| n as i64).into(),
+      ),
+      (
+        ":published_from".to_string(),
+        date(self.published_from).into(),
+      ),
+      (
+        ":published_until".to_string(),
+        date(self.published_until).into(),
+      ),
+      (
+        ":include".to_string(),
+        self
+          .include_comics
+          .as_ref()
+          .map(|c| vec_to_json_string(c.clone()))
+          .into(),
+      ),
+      (
+        ":exclude".to_string(),
+        vec_to_json_string(self.exclude_comics.clone()).into(),
+      ),
+    ]
+  }
+}
+
+/// WHERE clause applying [`SearchOptions`] to a query over `xkcd_chunks xc`
+/// joined with `xkcd_comics c`. Bind with [`SearchOptions::filter_params`].
+pub(crate) const FILTER_SQL: &str = "
+  (:sections IS NULL OR xc.section_type IN (SELECT value FROM json_each(:sections)))
+  AND (:min_comic IS NULL OR xc.comic_number >= :min_comic)
+  AND (:max_comic IS NULL OR xc.comic_number <= :max_comic)
+  AND (:published_from IS NULL OR c.published_at >= :published_from)
+  AND (:published_until IS NULL OR c.published_at <= :published_until)
+  AND (:include IS NULL OR xc.comic_number IN (SELECT value FROM json_each(:include)))
+  AND xc.comic_number NOT IN (SELECT value FROM json_each(:exclude))";
+
+/// Columns read by [`row_to_search_result`], in order.
+pub(crate) const RESULT_COLUMNS: &str = "
+  xc.id,
+  xc.comic_number,
+  xc.chunk_text,
+  xc.section_type,
+  c.title,
+  c.xkcd_url,
+  c.hover_text";
+
+/// Build a search result from a row selecting [`RESULT_COLUMNS`] followed by
+/// the distance.
+pub(crate) fn row_to_search_result(row: &Row) -> Result<ChunkSearchResult> {
+  let chunk_id: u64 = row
+    .get(0)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  let comic_number: u64 = row
+    .get(1)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  let chunk_text: String = row
+    .get(2)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  let section_type: Option<String> = row
+    .get(3)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  let comic_title: String = row
+    .get(4)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  let xkcd_url: String = row
+    .get(5)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  let hover_text: Option<String> = row
+    .get(6)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  let distance: f64 = row
+    .get(7)
+    .map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+
+  Ok(ChunkSearchResult {
+    chunk_id,
+    comic_number,
+    chunk_text,
+    section_type,
+    comic_title,
+    xkcd_url,
+    hover_text,
+    distance,
+  })
+}
+
+impl Database {
+  /// Find the `top_k` chunks closest to `query_embedding`.
+  ///
+  /// Shorthand for [`Database::vector_search_with_options`] without filters.
+  pub async fn vector_search(
+    &self,
+    model: &EmbeddingModel,
+    query_embedding: Vec<f32>,
+    top_k: usize,
+  ) -> Result<Vec<ChunkSearchResult>> {
+    self
+      .vector_search_with_options(model, query_embedding, &SearchOptions::new(top_k))
+      .await
+  }
+
+  /// Find the chunks closest to `query_embedding` that pass `options`.
+  ///
+  /// When filters are set the vector index is oversampled, and the candidate
+  /// pool is widened until `top_k` matches are found or every chunk has been
+  /// considered, so a restrictive filter still fills the result list if it can.
+  /// With quantized [`EmbeddingStorage`](crate::EmbeddingStorage) the index is
+  /// oversampled too, and the candidates reranked by full-precision distance.
+  ///
+  /// # Errors
+  /// Returns an error if `model` isn't the model recorded in the database, the
+  /// embedding dimension doesn't match `EMBEDDING_DIM`, or the query fails.
+  pub async fn vector_search_with_options(
+    &self,
+    model: &EmbeddingModel,
+    query_embedding: Vec<f32>,
+    options: &SearchOptions,
+  ) -> Result<Vec<ChunkSearchResult>> {
+    validate_embedding(&query_embedding)?;
+    if options.top_k == 0 {
+      return Ok(Vec::new());
+    }
+
+    let query_vec_json = vec_to_json_string(query_embedding);
+    let conn = self.reader().await?;
+    check_model(&conn, model).await?;
+    let storage = read_storage(&conn).await?;
+    // the index holds the compact vectors' rowids, which are the chunk ids,
+    // and the distance is always taken from the full-precision vectors
+    let sql = format!(
+      "SELECT {RESULT_COLUMNS},
+        -- zero vectors have no direction, rank them as orthogonal
+        COALESCE(vector_distance_cos(xc.embedding, vector32(:query)), 1.0) AS distance
+      FROM vector_top_k('chunks_vec_idx', {}(:query), :candidates) v
+      JOIN xkcd_chunks xc ON xc.rowid = v.id
+      JOIN xkcd_comics c ON c.comic_number = xc.comic_number
+      WHERE {FILTER_SQL}
+      ORDER BY distance ASC
+      LIMIT :limit",
+      storage.vector_function()
+    );
+    let stmt = conn.prepare(&sql).await.map_err(DatabaseError::prepare)?;
+
+    let mut candidates = options.top_k.saturating_mul(storage.rerank_factor());
+    if options.has_filters() {
+      candidates = candidates.saturating_mul(OVERSAMPLE_FACTOR);
+    }
+    let mut total_chunks = None;
+
+    loop {
+      let mut params = options.filter_params();
+      params.push((":query".to_string(), query_vec_json.clone().into()));
+      params.push((":candidates".to_string(), (candidates as i64).into()));
+      params.push((":limit".to_string(), (options.top_k as i64).into()));
+
+      let mut rows = stmt.query(params).await.map_err(DatabaseError::query)?;
+
+      let mut results = Vec::new();
+      while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+        results.push(row_to_search_result(&row)?);
+      }
+      stmt.reset();
+
+      if results.len() >= options.top_k {
+        return Ok(results);
+      }
+      let total = match total_chunks {
+        Some(total) => total,
+        None => *total_chunks.insert(count_chunks(&conn).await?),
+      };
+      if candidates >= total {
+        return Ok(results);
+      }
+      candidates = candidates.saturating_mul(OVERSAMPLE_FACTOR).min(total);
+    }
+  }
+
+  /// Find the `top_k` chunks closest to `query_embedding` by scanning every
+  /// embedding instead of using the vector index.
+  ///
+  /// Shorthand for [`Database::exact_search_with_options`] without filters.
+  pub async fn exact_search(
+    &self,
+    model: &EmbeddingModel,
+    query_embedding: Vec<f32>,
+    top_k: usize,
+  ) -> Result<Vec<ChunkSearchResult>> {
+    self
+      .exact_search_with_options(model, query_embedding, &SearchOptions::new(top_k))
+      .await
+  }
+
+  /// Like [`Database::vector_search_with_options`], but computes the distance
+  /// to every chunk that passes `options`, so the results are the true
+  /// nearest neighbours. Linear in the number of chunks: meant for small
+  /// corpora, debugging and as ground truth for the index. Ties are broken by
+  /// chunk id.
+  ///
+  /// # Errors
+  /// Returns an error if `model` isn't the model recorded in the database, the
+  /// embedding dimension doesn't match `EMBEDDING_DIM`, or the query fails.
+  pub async fn exact_search_with_options(
+    &self,
+    model: &EmbeddingModel,
+    query_embedding: Vec<f32>,
+    options: &SearchOptions,
+  ) -> Result<Vec<ChunkSearchResult>> {
+    validate_embedding(&query_embedding)?;
+    if options.top_k == 0 {
+      return Ok(Vec::new());
+    }
+
+    let sql = format!(
+      "SELECT {RESULT_COLUMNS},
+        COALESCE(vector_distance_cos(xc.embedding, vector32(:query)), 1.0) AS distance
+      FROM xkcd_chunks xc
+      JOIN xkcd_comics c ON c.comic_number = xc.comic_number
+      WHERE {FILTER_SQL}
+      ORDER BY distance ASC, xc.id ASC
+      LIMIT :limit"
+    );
+    let conn = self.reader().await?;
+    check_model(&conn, model).await?;
+
+    let mut params = options.filter_params();
+    params.push((
+      ":query".to_string(),
+      vec_to_json_string(query_embedding).into(),
+    ));
+    params.push((":limit".to_string(), (options.top_k as i64).into()));
+    let mut rows = conn
+      .query(&sql, params)
+      .await
+      .map_err(DatabaseError::query)?;
+
+    let mut results = Vec::new();
+    while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+      results.push(row_to_search_result(&row)?);
+    }
+    Ok(results)
+  }
+}
+
+async fn count_chunks(conn: &Connection) -> Result<usize> {
+  let mut rows = conn
+    .query("SELECT COUNT(*) FROM xkcd_chunks", ())
+    .await
+    .map_err(DatabaseError::query)?;
+  let row = rows
+    .next()
+    .await
+    .map_err(DatabaseError::query)?
+    .ok_or_else(|| DatabaseError::QueryFailed("COUNT(*) returned no rows".to_string()))?;
+  let count: u64 = row
+    .get(0)
+    .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+  Ok(count as usize)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::EMBEDDING_DIM;
+  use crate::test_support::{make_chunk, make_comic, setup_db};
+
+  /// Embedding pointing mostly along the all-ones direction, tilted by `tilt`
+  /// so that lower tilts rank closer to an all-ones query.
+  fn tilted(tilt: f32) -> Vec<f32> {
+    (0..EMBEDDING_DIM)
+      .map(|i| if i % 2 == 0 { 1.0 } else { 1.0 - tilt })
+      .collect()
+  }
+
+  /// Comics 1..=5, each with a transcript chunk and an explanation chunk.
+  /// Higher comic numbers are further from the all-ones query.
+  async fn setup_corpus() -> Database {
+    let db = setup_db().await;
+    for n in 1..=5 {
+      let mut comic = make_comic(n);
+      comic.published_at = NaiveDate::from_ymd_opt(2020, 1, n as u32);
+      db.insert_comic(comic).await.unwrap();
+      let mut transcript = make_chunk(n, 0);
+      transcript.section_type = Some(SectionType::Transcript);
+      transcript.embedding = tilted(n as f32 * 0.1);
+      let mut explanation = make_chunk(n, 1);
+      explanation.embedding = tilted(n as f32 * 0.1 + 0.05);
+      db.insert_chunks_batch(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![transcript, explanation],
+      )
+      .await
+      .unwrap();
+    }
+    db
+  }
+
+  #[tokio::test]
+  async fn test_vector_search() {
+    let db = setup_db().await;
+    db.insert_comic(make_comic(1)).await.unwrap();
+    db.insert_comic(make_comic(2)).await.unwrap();
+    let mut c1 = make_chunk(1, 0);
+    c1.embedding = vec![1.0; EMBEDDING_DIM];
+    let mut c2 = make_chunk(2, 0);
+    c2.embedding = vec![0.0; EMBEDDING_DIM];
+    db.insert_chunk(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, c1)
+      .await
+      .unwrap();
+    db.insert_chunk(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, c2)
+      .await
+      .unwrap();
+    let query = vec![0.9; EMBEDDING_DIM];
+    let results = db
+      .vector_search(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, query, 2)
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 2);
+    // vector_top_k returns top K results, ordering depends on index implementation
+    let comic_numbers: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+    assert!(comic_numbers.contains(&1));
+    assert!(comic_numbers.contains(&2));
+  }
+
+  #[tokio::test]
+  async fn test_exact_search() {
+    let db = setup_corpus().await;
+    let results = db
+      .exact_search(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        4,
+      )
+      .await
+      .unwrap();
+    // transcripts tilt 0.1 per comic, explanations 0.05 further
+    let order: Vec<(u64, Option<&str>)> = results
+      .iter()
+      .map(|r| (r.comic_number, r.section_type.as_deref()))
+      .collect();
+    assert_eq!(
+      order,
+      vec![
+        (1, Some("transcript")),
+        (1, Some("explanation")),
+        (2, Some("transcript")),
+        (2, Some("explanation")),
+      ]
+    );
+
+    let options = SearchOptions {
+      section_types: Some(vec![SectionType::Explanation]),
+      exclude_comics: vec![1],
+      ..SearchOptions::new(10)
+    };
+    let results = db
+      .exact_search_with_options(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    let comics: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+    assert_eq!(comics, vec![2, 3, 4, 5]);
+  }
+
+  #[tokio::test]
+  async fn test_vector_search_scores_sorted() {
+    let db = setup_db().await;
+    for n in 1..=3 {
+      db.insert_comic(make_comic(n)).await.unwrap();
+    }
+    // 1: same direction as the query, 2: orthogonal, 3: opposite
+    let mut c1 = make_chunk(1, 0);
+    c1.embedding = vec![1.0; EMBEDDING_DIM];
+    let mut c2 = make_chunk(2, 0);
+    c2.embedding = (0..EMBEDDING_DIM)
+      .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+      .collect();
+    let mut c3 = make_chunk(3, 0);
+    c3.embedding = vec![-1.0; EMBEDDING_DIM];
+    db.insert_chunk(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, c3)
+      .await
+      .unwrap();
+    db.insert_chunk(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, c2)
+      .await
+      .unwrap();
+    db.insert_chunk(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, c1)
+      .await
+      .unwrap();
+
+    let results = db
+      .vector_search(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![0.5; EMBEDDING_DIM],
+        3,
+      )
+      .await
+      .unwrap();
+    let order: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+    assert_eq!(order, vec![1, 2, 3]);
+    assert!(results[0].distance.abs() < 1e-4);
+    assert!((results[1].distance - 1.0).abs() < 1e-4);
+    assert!((results[2].distance - 2.0).abs() < 1e-4);
+    assert!((results[0].similarity() - 1.0).abs() < 1e-4);
+  }
+
+  #[tokio::test]
+  async fn test_vector_search_invalid_embedding_dimension() {
+    let db = setup_db().await;
+    let query = vec![0.5; 100];
+    assert!(
+      db.vector_search(&EmbeddingModel::QWEN3_EMBEDDING_0_6B, query, 10)
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_filter_by_section_type() {
+    let db = setup_corpus().await;
+    let options = SearchOptions {
+      section_types: Some(vec![SectionType::Explanation]),
+      ..SearchOptions::new(3)
+    };
+    let results = db
+      .vector_search_with_options(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(
+      results
+        .iter()
+        .all(|r| r.section_type.as_deref() == Some("explanation"))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_filter_by_comic_range_fills_top_k() {
+    let db = setup_corpus().await;
+    // the two furthest comics only - needs the candidate pool to grow
+    let options = SearchOptions {
+      min_comic: Some(4),
+      max_comic: Some(5),
+      ..SearchOptions::new(4)
+    };
+    let results = db
+      .vector_search_with_options(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(results.iter().all(|r| (4..=5).contains(&r.comic_number)));
+  }
+
+  #[tokio::test]
+  async fn test_filter_by_publish_window() {
+    let db = setup_corpus().await;
+    let options = SearchOptions {
+      published_from: NaiveDate::from_ymd_opt(2020, 1, 2),
+      published_until: NaiveDate::from_ymd_opt(2020, 1, 3),
+      ..SearchOptions::new(10)
+    };
+    let results = db
+      .vector_search_with_options(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    let mut comics: Vec<u64> = results.iter().map(|r| r.comic_number).collect();
+    comics.dedup();
+    assert_eq!(comics, vec![2, 3]);
+  }
+
+  #[tokio::test]
+  async fn test_include_and_exclude_comics() {
+    let db = setup_corpus().await;
+    let options = SearchOptions {
+      include_comics: Some(vec![1, 2, 3]),
+      exclude_comics: vec![1],
+      ..SearchOptions::new(10)
+    };
+    let results = db
+      .vector_search_with_options(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(results.iter().all(|r| [2, 3].contains(&r.comic_number)));
+  }
+
+  #[tokio::test]
+  async fn test_filter_matching_nothing() {
+    let db = setup_corpus().await;
+    let options = SearchOptions {
+      section_types: Some(vec![SectionType::Trivia]),
+      ..SearchOptions::new(3)
+    };
+    let results = db
+      .vector_search_with_options(
+        &EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+        vec![1.0; EMBEDDING_DIM],
+        &options,
+      )
+      .await
+      .unwrap();
+    assert!(results.is_empty());
+  }
+}
@@ -0,0 +1,534 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use libsql::{Connection, TransactionBehavior, de, params};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::Database;
+use crate::chunks::{insert_chunks_in, row_to_chunk, validate_chunk};
+use crate::comics::insert_comic_in;
+use crate::embedding::{EmbeddingModel, check_model, read_model};
+use crate::error::{DatabaseError, Result};
+use crate::migrations::{SCHEMA_VERSION_KEY, read_schema_version};
+use crate::models::{Chunks, ComicNumber, Comics, Metadata, SectionType};
+use crate::vector_index::VECTOR_INDEX_OPTIONS_KEY;
+
+/// Value of [`SnapshotManifest::format`].
+const SNAPSHOT_FORMAT: &str = "xkcd-snapshot";
+
+/// Version of the snapshot line format this binary writes. Snapshots with a
+/// newer version are refused on import.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Chunks buffered before they are written during an import.
+const IMPORT_BATCH: usize = 256;
+
+/// How chunk embeddings are written in a snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingEncoding {
+  /// A JSON array of numbers. Readable, but about three times the size.
+  #[default]
+  Json,
+  /// The little-endian `f32` bytes, base64 encoded.
+  Base64F32,
+}
+
+/// Number of records of each kind in a snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotCounts {
+  pub comics: u64,
+  pub chunks: u64,
+  pub metadata: u64,
+}
+
+/// First line of a snapshot, describing the records that follow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+  pub format: String,
+  pub version: u32,
+  /// Schema version of the database the snapshot was taken from.
+  pub schema_version: u32,
+  /// The model the chunk embeddings come from.
+  pub model: EmbeddingModel,
+  pub embedding_encoding: EmbeddingEncoding,
+  pub counts: SnapshotCounts,
+  /// Hex SHA-256 of every line after the manifest, newlines included.
+  pub checksum: String,
+}
+
+/// A chunk as written to a snapshot. Chunk ids are local to a database and
+/// are assigned again on import.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotChunk {
+  comic_number: ComicNumber,
+  chunk_index: u64,
+  section_type: Option<SectionType>,
+  chunk_text: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  embedding: Option<Vec<f32>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  embedding_base64: Option<String>,
+}
+
+impl SnapshotChunk {
+  fn new(chunk: Chunks, encoding: EmbeddingEncoding) -> Self {
+    let (embedding, embedding_base64) = match encoding {
+      EmbeddingEncoding::Json => (Some(chunk.embedding), None),
+      EmbeddingEncoding::Base64F32 => {
+        let bytes: Vec<u8> = chunk
+          .embedding
+          .iter()
+          .flat_map(|value| value.to_le_bytes())
+          .collect();
+        (None, Some(BASE64.encode(bytes)))
+      }
+    };
+    Self {
+      comic_number: chunk.comic_number,
+      chunk_index: chunk.chunk_index,
+      section_type: chunk.section_type,
+      chunk_text: chunk.chunk_text,
+      embedding,
+      embedding_base64,
+    }
+  }
+
+  fn into_chunk(self) -> Result<Chunks> {
+    let embedding = match (self.embedding, self.embedding_base64) {
+      (Some(embedding), None) => embedding,
+      (None, Some(encoded)) => {
+        let bytes = BASE64
+          .decode(encoded)
+          .map_err(|e| DatabaseError::Snapshot(format!("bad base64 embedding: {e}")))?;
+        if bytes.len() % 4 != 0 {
+          return Err(DatabaseError::Snapshot(format!(
+            "base64 embedding of {} bytes is not a list of f32",
+            bytes.len()
+          )));
+        }
+        bytes
+          .chunks_exact(4)
+          .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+          .collect()
+      }
+      _ => {
+        return Err(DatabaseError::Snapshot(format!(
+          "chunk {} of comic {} needs exactly one of embedding and embedding_base64",
+          self.chunk_index, self.comic_number
+        )));
+      }
+    };
+    let chunk = Chunks {
+      id: None,
+      comic_number: self.comic_number,
+      chunk_text: self.chunk_text,
+      chunk_index: self.chunk_index,
+      section_type: self.section_type,
+      embedding,
+    };
+    validate_chunk(&chunk)?;
+    Ok(chunk)
+  }
+}
+
+/// One line of a snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+  Manifest(SnapshotManifest),
+  Comic(Comics),
+  Chunk(SnapshotChunk),
+  Metadata(Metadata),
+}
+
+impl Record {
+  fn to_line(&self) -> Result<Vec<u8>> {
+    let mut line =
+      serde_json::to_vec(self).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+    line.push(b'\n');
+    Ok(line)
+  }
+}
+
+/// Metadata that describes this particular database file rather than the
+/// data, and so isn't carried over: the initialization flag, schema version,
+/// embedding model (which travels in the manifest), vector index options and
+/// re-embedding state.
+fn is_local_metadata(key: &str) -> bool {
+  key == "INITIALIZED"
+    || key == SCHEMA_VERSION_KEY
+    || key == VECTOR_INDEX_OPTIONS_KEY
+    || key.starts_with("EMBEDDING_")
+    || key.starts_with("REEMBED_")
+}
+
+/// Feed every record line of the database to `sink`, in snapshot order.
+async fn for_each_record(
+  conn: &Connection,
+  encoding: EmbeddingEncoding,
+  mut sink: impl AsyncFnMut(&[u8]) -> Result<()>,
+) -> Result<SnapshotCounts> {
+  let mut counts = SnapshotCounts::default();
+
+  let mut rows = conn
+    .query("SELECT * FROM xkcd_comics ORDER BY comic_number", ())
+    .await
+    .map_err(DatabaseError::query)?;
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let comic =
+      de::from_row::<Comics>(&row).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+    sink(&Record::Comic(comic).to_line()?).await?;
+    counts.comics += 1;
+  }
+
+  let mut rows = conn
+    .query(
+      "SELECT id, comic_number, chunk_text, chunk_index, section_type, embedding
+       FROM xkcd_chunks
+       ORDER BY comic_number, chunk_index, id",
+      (),
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let chunk = SnapshotChunk::new(row_to_chunk(&row)?, encoding);
+    sink(&Record::Chunk(chunk).to_line()?).await?;
+    counts.chunks += 1;
+  }
+
+  let mut rows = conn
+    .query("SELECT key, value FROM metadata ORDER BY key", ())
+    .await
+    .map_err(DatabaseError::query)?;
+  while let Some(row) = rows.next().await.map_err(DatabaseError::query)? {
+    let metadata =
+      de::from_row::<Metadata>(&row).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+    if is_local_metadata(&metadata.key) {
+      continue;
+    }
+    sink(&Record::Metadata(metadata).to_line()?).await?;
+    counts.metadata += 1;
+  }
+
+  Ok(counts)
+}
+
+fn hex(digest: &[u8]) -> String {
+  digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl Database {
+  /// Write every comic, chunk and piece of user metadata to `out` as a
+  /// snapshot: a manifest line followed by one JSON record per line.
+  ///
+  /// The snapshot is taken from a single read transaction, so writes made
+  /// while it runs aren't included. The records are read twice, once to
+  /// count and checksum them for the manifest and once to write them, so
+  /// nothing is held in memory.
+  ///
+  /// # Errors
+  /// Returns an error if a query fails or `out` can't be written to.
+  pub async fn export_snapshot(
+    &self,
+    mut out: impl AsyncWrite + Unpin,
+    encoding: EmbeddingEncoding,
+  ) -> Result<SnapshotManifest> {
+    let conn = self.reader().await?;
+    let tx = conn
+      .transaction()
+      .await
+      .map_err(DatabaseError::transaction)?;
+
+    let model = read_model(&tx).await?;
+    let schema_version = read_schema_version(&tx).await?;
+    let mut hasher = Sha256::new();
+    let counts = for_each_record(&tx, encoding, async |line| {
+      hasher.update(line);
+      Ok(())
+    })
+    .await?;
+
+    let manifest = SnapshotManifest {
+      format: SNAPSHOT_FORMAT.to_string(),
+      version: SNAPSHOT_VERSION,
+      schema_version,
+      model,
+      embedding_encoding: encoding,
+      counts,
+      checksum: hex(&hasher.finalize()),
+    };
+    out
+      .write_all(&Record::Manifest(manifest.clone()).to_line()?)
+      .await?;
+    for_each_record(&tx, encoding, async |line| Ok(out.write_all(line).await?)).await?;
+    out.flush().await?;
+
+    tx.rollback().await.map_err(DatabaseError::transaction)?;
+    Ok(manifest)
+  }
+
+  /// Load a snapshot written by [`Database::export_snapshot`] into this
+  /// database, which must not hold any comics yet.
+  ///
+  /// Everything is written in one transaction that only commits once the
+  /// record counts and checksum match the manifest, so a truncated or
+  /// corrupted file leaves the database as it was.
+  ///
+  /// # Errors
+  /// Returns [`DatabaseError::Snapshot`] if the snapshot is malformed, from a
+  /// newer format version, or fails its checksum or counts,
+  /// [`DatabaseError::EmbeddingModelMismatch`] if its embeddings come from a
+  /// different model than the database holds, and
+  /// [`DatabaseError::InvalidContent`] if the database isn't empty.
+  pub async fn import_snapshot(
+    &self,
+    input: impl AsyncBufRead + Unpin,
+  ) -> Result<SnapshotManifest> {
+    let mut lines = input.lines();
+    let manifest = match lines.next_line().await? {
+      Some(line) => match serde_json::from_str(&line) {
+        Ok(Record::Manifest(manifest)) => manifest,
+        _ => {
+          return Err(DatabaseError::Snapshot(
+            "first line is not a manifest".to_string(),
+          ));
+        }
+      },
+      None => return Err(DatabaseError::Snapshot("empty snapshot".to_string())),
+    };
+    if manifest.format != SNAPSHOT_FORMAT {
+      return Err(DatabaseError::Snapshot(format!(
+        "unknown format {:?}",
+        manifest.format
+      )));
+    }
+    if manifest.version > SNAPSHOT_VERSION {
+      return Err(DatabaseError::Snapshot(format!(
+        "format version {} is newer than supported version {SNAPSHOT_VERSION}",
+        manifest.version
+      )));
+    }
+
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    check_model(&tx, &manifest.model).await?;
+    let mut rows = tx
+      .query("SELECT 1 FROM xkcd_comics LIMIT 1", ())
+      .await
+      .map_err(DatabaseError::query)?;
+    if rows.next().await.map_err(DatabaseError::query)?.is_some() {
+      return Err(DatabaseError::InvalidContent(
+        "snapshots can only be imported into an empty database".to_string(),
+      ));
+    }
+    drop(rows);
+
+    let mut hasher = Sha256::new();
+    let mut counts = SnapshotCounts::default();
+    let mut pending = Vec::with_capacity(IMPORT_BATCH);
+    let mut number = 1;
+    while let Some(line) = lines.next_line().await? {
+      number += 1;
+      hasher.update(line.as_bytes());
+      hasher.update(b"\n");
+
+      let record: Record = serde_json::from_str(&line)
+        .map_err(|e| DatabaseError::Snapshot(format!("line {number}: {e}")))?;
+      match record {
+        Record::Manifest(_) => {
+          return Err(DatabaseError::Snapshot(format!(
+            "line {number}: unexpected second manifest"
+          )));
+        }
+        Record::Comic(comic) => {
+          insert_comic_in(&tx, &comic).await?;
+          counts.comics += 1;
+        }
+        Record::Chunk(chunk) => {
+          pending.push(chunk.into_chunk()?);
+          counts.chunks += 1;
+          if pending.len() == IMPORT_BATCH {
+            insert_chunks_in(&tx, std::mem::take(&mut pending)).await?;
+          }
+        }
+        Record::Metadata(metadata) => {
+          if is_local_metadata(&metadata.key) {
+            continue;
+          }
+          tx.execute(
+            "INSERT INTO metadata (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO UPDATE SET value = ?2",
+            params![metadata.key, metadata.value],
+          )
+          .await
+          .map_err(DatabaseError::query)?;
+          counts.metadata += 1;
+        }
+      }
+    }
+    insert_chunks_in(&tx, pending).await?;
+
+    if counts != manifest.counts {
+      return Err(DatabaseError::Snapshot(format!(
+        "manifest lists {:?}, snapshot holds {counts:?}",
+        manifest.counts
+      )));
+    }
+    let checksum = hex(&hasher.finalize());
+    if checksum != manifest.checksum {
+      return Err(DatabaseError::Snapshot(format!(
+        "checksum mismatch: manifest {}, content {checksum}",
+        manifest.checksum
+      )));
+    }
+
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(manifest)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::{make_chunk, make_comic};
+  use crate::{DatabaseOptions, EMBEDDING_DIM};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  fn distinct_chunk(comic: u64, idx: u64) -> Chunks {
+    Chunks {
+      chunk_text: format!("Chunk {} of {}", idx, comic),
+      // values that don't have a short decimal form
+      embedding: (0..EMBEDDING_DIM)
+        .map(|i| (i as f32 + 1.0) / (comic * 7 + idx + 3) as f32)
+        .collect(),
+      ..make_chunk(comic, idx)
+    }
+  }
+
+  async fn populated() -> Database {
+    let db = Database::new(":memory:").await.unwrap();
+    for n in 1..=2 {
+      db.insert_comic(make_comic(n)).await.unwrap();
+      db.insert_chunks_batch(&MODEL, (0..3).map(|i| distinct_chunk(n, i)).collect())
+        .await
+        .unwrap();
+    }
+    db.set_metadata("LAST_SCRAPE", "2025-01-27T00:00:00Z".to_string())
+      .await
+      .unwrap();
+    db
+  }
+
+  async fn export(db: &Database, encoding: EmbeddingEncoding) -> Vec<u8> {
+    let mut out = Vec::new();
+    db.export_snapshot(&mut out, encoding).await.unwrap();
+    out
+  }
+
+  async fn assert_same_content(a: &Database, b: &Database) {
+    for n in 1..=2 {
+      let (ca, cb) = (
+        a.get_comic_by_number(n).await.unwrap().unwrap(),
+        b.get_comic_by_number(n).await.unwrap().unwrap(),
+      );
+      assert_eq!(ca.title, cb.title);
+      assert_eq!(ca.published_at, cb.published_at);
+
+      let (xa, xb) = (
+        a.get_chunks_for_comic(n).await.unwrap(),
+        b.get_chunks_for_comic(n).await.unwrap(),
+      );
+      assert_eq!(xa.len(), xb.len());
+      for (x, y) in xa.iter().zip(&xb) {
+        assert_eq!(x.chunk_text, y.chunk_text);
+        assert_eq!(x.chunk_index, y.chunk_index);
+        assert_eq!(x.section_type, y.section_type);
+        assert_eq!(x.embedding, y.embedding);
+      }
+    }
+    assert_eq!(
+      b.get_metadata("LAST_SCRAPE").await.unwrap().value,
+      "2025-01-27T00:00:00Z"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_roundtrip_json() {
+    let db = populated().await;
+    let snapshot = export(&db, EmbeddingEncoding::Json).await;
+
+    let copy = Database::new(":memory:").await.unwrap();
+    let manifest = copy.import_snapshot(snapshot.as_slice()).await.unwrap();
+    assert_eq!(manifest.model, MODEL);
+    assert_eq!(
+      manifest.counts,
+      SnapshotCounts {
+        comics: 2,
+        chunks: 6,
+        metadata: 1
+      }
+    );
+    assert_same_content(&db, &copy).await;
+  }
+
+  #[tokio::test]
+  async fn test_roundtrip_base64_is_smaller() {
+    let db = populated().await;
+    let json = export(&db, EmbeddingEncoding::Json).await;
+    let snapshot = export(&db, EmbeddingEncoding::Base64F32).await;
+    assert!(snapshot.len() < json.len());
+
+    let copy = Database::new(":memory:").await.unwrap();
+    copy.import_snapshot(snapshot.as_slice()).await.unwrap();
+    assert_same_content(&db, &copy).await;
+  }
+
+  #[tokio::test]
+  async fn test_corrupted_snapshot_rolled_back() {
+    let db = populated().await;
+    let snapshot = String::from_utf8(export(&db, EmbeddingEncoding::Json).await).unwrap();
+    let tampered = snapshot.replacen("Chunk 1 of 2", "Chunk 1 of 3", 1);
+
+    let copy = Database::new(":memory:").await.unwrap();
+    assert!(matches!(
+      copy.import_snapshot(tampered.as_bytes()).await,
+      Err(DatabaseError::Snapshot(_))
+    ));
+    // truncated: the last record is missing
+    let truncated: String = snapshot.lines().take(5).map(|l| format!("{l}\n")).collect();
+    assert!(matches!(
+      copy.import_snapshot(truncated.as_bytes()).await,
+      Err(DatabaseError::Snapshot(_))
+    ));
+    assert!(copy.get_comic_by_number(1).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn test_import_refuses_other_model_and_non_empty_database() {
+    let db = populated().await;
+    let snapshot = export(&db, EmbeddingEncoding::Json).await;
+
+    let other = DatabaseOptions::in_memory()
+      .embedding_model(EmbeddingModel {
+        id: "some/other-model".into(),
+        ..MODEL
+      })
+      .open()
+      .await
+      .unwrap();
+    assert!(matches!(
+      other.import_snapshot(snapshot.as_slice()).await,
+      Err(DatabaseError::EmbeddingModelMismatch { .. })
+    ));
+
+    assert!(matches!(
+      db.import_snapshot(snapshot.as_slice()).await,
+      Err(DatabaseError::InvalidContent(_))
+    ));
+  }
+}
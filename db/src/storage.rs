@@ -0,0 +1,264 @@
+use libsql::{Connection, params};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::Database;
+use crate::EMBEDDING_DIM;
+use crate::error::{DatabaseError, Result};
+
+/// Metadata key recording the [`EmbeddingStorage`] a database was created
+/// with.
+pub(crate) const STORAGE_KEY: &str = "EMBEDDING_STORAGE";
+
+/// Table holding the compact copies of the embeddings in quantized modes.
+pub(crate) const QUANTIZED_TABLE: &str = "xkcd_chunks_quantized";
+
+/// How embeddings are stored for the vector index.
+///
+/// `xkcd_chunks.embedding` always keeps the full-precision vectors, which
+/// every read and the final ranking use. In the quantized modes the vector
+/// index is built over a compact copy of each vector instead, which shrinks
+/// the index several times over. Searches then pull extra candidates from the
+/// index and rerank them by their full-precision distance.
+///
+/// Chosen when the database is created with
+/// [`DatabaseOptions::embedding_storage`](crate::DatabaseOptions::embedding_storage).
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display, EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum EmbeddingStorage {
+  /// Index the `f32` vectors directly.
+  #[default]
+  Full,
+  /// Index 8-bit quantized copies (`F8_BLOB`), about a quarter of the size.
+  Float8,
+  /// Index one bit per dimension (`F1BIT_BLOB`), a thirty-second of the
+  /// size. Coarse, so it needs a cosine index and a deep rerank.
+  #[serde(rename = "float1bit")]
+  #[strum(serialize = "float1bit")]
+  Float1Bit,
+}
+
+impl EmbeddingStorage {
+  /// The table and column the vector index is built over.
+  pub(crate) fn indexed_table(self) -> &'static str {
+    match self {
+      Self::Full => "xkcd_chunks",
+      Self::Float8 | Self::Float1Bit => QUANTIZED_TABLE,
+    }
+  }
+
+  /// SQL function turning a vector into the indexed column's type, which is
+  /// what `vector_top_k` needs the query in.
+  pub(crate) fn vector_function(self) -> &'static str {
+    match self {
+      Self::Full => "vector32",
+      Self::Float8 => "vector8",
+      Self::Float1Bit => "vector1bit",
+    }
+  }
+
+  /// Index candidates fetched per result, for the full-precision rerank.
+  pub(crate) fn rerank_factor(self) -> usize {
+    match self {
+      Self::Full => 1,
+      Self::Float8 => 4,
+      Self::Float1Bit => 16,
+    }
+  }
+
+  fn column_type(self) -> Option<&'static str> {
+    match self {
+      Self::Full => None,
+      Self::Float8 => Some("F8_BLOB"),
+      Self::Float1Bit => Some("F1BIT_BLOB"),
+    }
+  }
+}
+
+pub(crate) async fn read_storage(conn: &Connection) -> Result<EmbeddingStorage> {
+  let mut rows = conn
+    .query(
+      "SELECT value FROM metadata WHERE key = ?",
+      params![STORAGE_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let Some(row) = rows.next().await.map_err(DatabaseError::query)? else {
+    return Err(DatabaseError::MetadataNotFound(STORAGE_KEY.to_string()));
+  };
+  let value: String = row
+    .get(0)
+    .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+  value
+    .parse()
+    .map_err(|_| DatabaseError::MetaParseFailed(format!("{STORAGE_KEY}={value}")))
+}
+
+/// Switch a freshly created, empty database to `storage`: create the compact
+/// table with the triggers keeping it in step with `xkcd_chunks`, and record
+/// the mode. The caller rebuilds the vector index over the new table.
+pub(crate) async fn set_up_storage(conn: &Connection, storage: EmbeddingStorage) -> Result<()> {
+  if let Some(column_type) = storage.column_type() {
+    let function = storage.vector_function();
+    conn
+      .execute_batch(&format!(
+        "CREATE TABLE {QUANTIZED_TABLE} (
+           chunk_id INTEGER PRIMARY KEY REFERENCES xkcd_chunks(id) ON DELETE CASCADE,
+           embedding {column_type}({EMBEDDING_DIM}) NOT NULL
+         );
+         CREATE TRIGGER xkcd_chunks_quantize_insert AFTER INSERT ON xkcd_chunks BEGIN
+           INSERT INTO {QUANTIZED_TABLE} (chunk_id, embedding)
+           VALUES (new.id, {function}(new.embedding));
+         END;
+         CREATE TRIGGER xkcd_chunks_quantize_update AFTER UPDATE OF embedding ON xkcd_chunks BEGIN
+           UPDATE {QUANTIZED_TABLE} SET embedding = {function}(new.embedding)
+           WHERE chunk_id = new.id;
+         END;"
+      ))
+      .await
+      .map_err(DatabaseError::query)?;
+  }
+  conn
+    .execute(
+      "INSERT INTO metadata (key, value) VALUES (?1, ?2)
+       ON CONFLICT (key) DO UPDATE SET value = ?2",
+      params![STORAGE_KEY, storage.to_string()],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  Ok(())
+}
+
+/// Recompute every compact vector from the full-precision ones. Does nothing
+/// in [`EmbeddingStorage::Full`].
+pub(crate) async fn requantize(conn: &Connection, storage: EmbeddingStorage) -> Result<()> {
+  if storage == EmbeddingStorage::Full {
+    return Ok(());
+  }
+  conn
+    .execute_batch(&format!(
+      "DELETE FROM {QUANTIZED_TABLE};
+       INSERT INTO {QUANTIZED_TABLE} (chunk_id, embedding)
+       SELECT id, {}(embedding) FROM xkcd_chunks;",
+      storage.vector_function()
+    ))
+    .await
+    .map_err(DatabaseError::query)?;
+  Ok(())
+}
+
+impl Database {
+  /// How this database stores embeddings for the vector index.
+  pub async fn embedding_storage(&self) -> Result<EmbeddingStorage> {
+    read_storage(&*self.reader().await?).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::models::Chunks;
+  use crate::test_support::{make_chunk, make_comic};
+  use crate::{DatabaseOptions, EmbeddingModel};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  /// Embedding turned `step` increments away from the first axis, with a
+  /// little noise in the other dimensions so the 1-bit codes differ.
+  fn turned(step: u64) -> Vec<f32> {
+    let mut embedding: Vec<f32> = (0..EMBEDDING_DIM)
+      .map(|i| {
+        if (i as u64 * 7 + step).is_multiple_of(5) {
+          0.01
+        } else {
+          -0.01
+        }
+      })
+      .collect();
+    embedding[0] = 1.0;
+    embedding[1] = step as f32 * 0.1;
+    embedding
+  }
+
+  async fn setup_corpus(storage: EmbeddingStorage) -> Database {
+    let db = DatabaseOptions::in_memory()
+      .embedding_storage(storage)
+      .open()
+      .await
+      .unwrap();
+    for n in 1..=4 {
+      db.insert_comic(make_comic(n)).await.unwrap();
+    }
+    let chunks = (0..20)
+      .map(|i| Chunks {
+        embedding: turned(i),
+        ..make_chunk(i % 4 + 1, i / 4)
+      })
+      .collect();
+    db.insert_chunks_batch(&MODEL, chunks).await.unwrap();
+    db
+  }
+
+  async fn quantized_rows(db: &Database) -> u64 {
+    let conn = db.reader().await.unwrap();
+    let mut rows = conn
+      .query(&format!("SELECT COUNT(*) FROM {QUANTIZED_TABLE}"), ())
+      .await
+      .unwrap();
+    rows.next().await.unwrap().unwrap().get(0).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_quantized_search_matches_full() {
+    let full = setup_corpus(EmbeddingStorage::Full).await;
+    assert_eq!(
+      full.embedding_storage().await.unwrap(),
+      EmbeddingStorage::Full
+    );
+    let expected: Vec<u64> = full
+      .exact_search(&MODEL, turned(7), 3)
+      .await
+      .unwrap()
+      .iter()
+      .map(|r| r.chunk_id)
+      .collect();
+
+    for storage in [EmbeddingStorage::Float8, EmbeddingStorage::Float1Bit] {
+      let db = setup_corpus(storage).await;
+      assert_eq!(db.embedding_storage().await.unwrap(), storage);
+      assert_eq!(quantized_rows(&db).await, 20);
+
+      let results = db.vector_search(&MODEL, turned(7), 3).await.unwrap();
+      let ids: Vec<u64> = results.iter().map(|r| r.chunk_id).collect();
+      assert_eq!(ids, expected, "{storage}");
+      // distances come from the full-precision vectors
+      assert!(results[0].distance.abs() < 1e-6, "{storage}");
+
+      // reads still see the full vectors
+      let chunks = db.get_chunks_for_comic(4).await.unwrap();
+      assert_eq!(chunks[0].embedding, turned(3));
+    }
+  }
+
+  #[tokio::test]
+  async fn test_quantized_copies_follow_chunks() {
+    let db = setup_corpus(EmbeddingStorage::Float8).await;
+    db.delete_comic(1).await.unwrap();
+    assert_eq!(quantized_rows(&db).await, 15);
+    assert!(db.check_integrity().await.unwrap().is_ok());
+
+    let conn = db.writer().await.unwrap();
+    conn
+      .execute(&format!("DELETE FROM {QUANTIZED_TABLE}"), ())
+      .await
+      .unwrap();
+    drop(conn);
+    assert!(!db.check_integrity().await.unwrap().is_ok());
+    db.rebuild_vector_index().await.unwrap();
+    assert_eq!(quantized_rows(&db).await, 15);
+    assert!(db.check_integrity().await.unwrap().is_ok());
+  }
+}
@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests.
+
+use chrono::NaiveDate;
+
+use crate::models::{Chunks, ComicNumber, Comics, SectionType};
+use crate::{Database, EMBEDDING_DIM, timestamp};
+
+/// An in-memory database at the latest schema.
+pub(crate) async fn setup_db() -> Database {
+  Database::new(":memory:").await.unwrap()
+}
+
+pub(crate) fn make_comic(n: u64) -> Comics {
+  Comics {
+    comic_number: ComicNumber::new(n).unwrap(),
+    title: format!("C{}", n),
+    url: format!("https://explainxkcd.com/{}", n),
+    xkcd_url: format!("https://xkcd.com/{}", n),
+    hover_text: Some(format!("H{}", n)),
+    published_at: NaiveDate::from_ymd_opt(2025, 1, 27),
+    last_revision_id: 12345,
+    last_revision_timestamp: timestamp::parse("20250127000000").unwrap(),
+    scraped_at: timestamp::parse("2025-01-27T00:00:00Z").unwrap(),
+    updated_at: timestamp::parse("2025-01-27T00:00:00Z").unwrap(),
+  }
+}
+
+/// An explanation chunk; override fields with struct update syntax.
+pub(crate) fn make_chunk(comic: u64, idx: u64) -> Chunks {
+  Chunks {
+    id: None,
+    comic_number: ComicNumber::new(comic).unwrap(),
+    chunk_text: format!("Chunk {}", idx),
+    chunk_index: idx,
+    section_type: Some(SectionType::Explanation),
+    embedding: vec![1.0; EMBEDDING_DIM],
+  }
+}
@@ -0,0 +1,74 @@
+//! Timestamp (de)serialization for the `xkcd_comics` columns.
+//!
+//! Timestamps are written as RFC 3339 UTC to the second, e.g.
+//! `2025-01-27T00:00:00Z`, which sorts correctly as text. Reading also accepts
+//! MediaWiki's `YYYYMMDDHHMMSS` and RFC 3339 with any offset, which older rows
+//! and API responses use.
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serializer};
+
+const MEDIAWIKI_FORMAT: &str = "%Y%m%d%H%M%S";
+
+/// Format `at` the way it is stored.
+pub(crate) fn to_sql(at: &DateTime<Utc>) -> String {
+  at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Parse a stored or MediaWiki timestamp.
+pub(crate) fn parse(s: &str) -> Option<DateTime<Utc>> {
+  if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+    return Some(t.with_timezone(&Utc));
+  }
+  // chrono accepts single-digit fields, MediaWiki always pads to 14 digits
+  if s.len() != 14 || !s.bytes().all(|b| b.is_ascii_digit()) {
+    return None;
+  }
+  NaiveDateTime::parse_from_str(s, MEDIAWIKI_FORMAT)
+    .ok()
+    .map(|t| t.and_utc())
+}
+
+pub(crate) fn serialize<S: Serializer>(
+  at: &DateTime<Utc>,
+  serializer: S,
+) -> Result<S::Ok, S::Error> {
+  serializer.serialize_str(&to_sql(at))
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<DateTime<Utc>, D::Error> {
+  let s = String::deserialize(deserializer)?;
+  parse(&s).ok_or_else(|| {
+    serde::de::Error::custom(format!(
+      "invalid timestamp {s:?}, expected RFC 3339 or YYYYMMDDHHMMSS"
+    ))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  #[test]
+  fn test_parse_both_formats() {
+    let expected = Utc.with_ymd_and_hms(2025, 1, 27, 13, 5, 9).unwrap();
+    assert_eq!(parse("20250127130509"), Some(expected));
+    assert_eq!(parse("2025-01-27T13:05:09Z"), Some(expected));
+    assert_eq!(parse("2025-01-27T15:05:09+02:00"), Some(expected));
+    assert_eq!(parse("2025-01-27"), None);
+    assert_eq!(parse("2025012713050"), None);
+    assert_eq!(to_sql(&expected), "2025-01-27T13:05:09Z");
+  }
+
+  #[test]
+  fn test_stored_form_sorts_chronologically() {
+    // as raw strings, "20240101000000" > "2024-12-31T00:00:00+00:00"
+    let earlier = parse("20240101000000").unwrap();
+    let later = parse("2024-12-31T00:00:00+00:00").unwrap();
+    assert!(to_sql(&earlier) < to_sql(&later));
+  }
+}
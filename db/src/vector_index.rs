@@ -0,0 +1,408 @@
+use std::time::{Duration, Instant};
+
+use libsql::{Connection, TransactionBehavior, params};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::Database;
+use crate::embedding::{EmbeddingModel, read_model};
+use crate::error::{DatabaseError, Result};
+use crate::integrity::VECTOR_INDEX;
+use crate::storage::{EmbeddingStorage, read_storage};
+
+/// Metadata key holding the [`VectorIndexOptions`] the index was built with,
+/// as JSON.
+pub(crate) const VECTOR_INDEX_OPTIONS_KEY: &str = "VECTOR_INDEX_OPTIONS";
+
+/// Distance the vector index orders candidates by. Results are always ranked
+/// by cosine distance afterwards.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display, EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum IndexMetric {
+  #[default]
+  Cosine,
+  /// Euclidean distance. Only ranks like cosine for normalised embeddings.
+  L2,
+}
+
+/// Precision the index stores neighbour vectors at. Smaller types make the
+/// index file smaller at some cost in recall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NeighborCompression {
+  /// One bit per dimension. Cosine metric only.
+  #[serde(rename = "float1bit")]
+  #[strum(serialize = "float1bit")]
+  Float1Bit,
+  Float8,
+  Float16,
+  Float32,
+}
+
+/// Parameters of the `libsql_vector_idx` (DiskANN) index over the chunk
+/// embeddings. Unset values use libSQL's defaults.
+///
+/// # Example
+/// ```
+/// use db::{NeighborCompression, VectorIndexOptions};
+/// let options = VectorIndexOptions {
+///   compress_neighbors: Some(NeighborCompression::Float8),
+///   max_neighbors: Some(32),
+///   ..VectorIndexOptions::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VectorIndexOptions {
+  #[serde(default)]
+  pub metric: IndexMetric,
+  /// Maximum number of neighbours kept per node.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_neighbors: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub compress_neighbors: Option<NeighborCompression>,
+  /// Pruning factor while building; higher keeps longer edges (at least 1).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub alpha: Option<f64>,
+  /// Candidate list size while searching; higher trades speed for recall.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub search_l: Option<u32>,
+}
+
+impl VectorIndexOptions {
+  /// The `CREATE INDEX` statement building the index with these options
+  /// over the vectors `storage` indexes.
+  pub(crate) fn create_sql(&self, storage: EmbeddingStorage) -> String {
+    let mut settings = vec![format!("metric={}", self.metric)];
+    if let Some(n) = self.max_neighbors {
+      settings.push(format!("max_neighbors={n}"));
+    }
+    if let Some(compression) = self.compress_neighbors {
+      settings.push(format!("compress_neighbors={compression}"));
+    }
+    if let Some(alpha) = self.alpha {
+      settings.push(format!("alpha={alpha}"));
+    }
+    if let Some(l) = self.search_l {
+      settings.push(format!("search_l={l}"));
+    }
+    let settings: Vec<String> = settings.into_iter().map(|s| format!("'{s}'")).collect();
+    format!(
+      "CREATE INDEX {VECTOR_INDEX} ON {}(libsql_vector_idx(embedding, {}))",
+      storage.indexed_table(),
+      settings.join(", ")
+    )
+  }
+
+  /// Fail if the options are out of range or don't suit embeddings from
+  /// `model` stored as `storage`.
+  pub(crate) fn validate(&self, model: &EmbeddingModel, storage: EmbeddingStorage) -> Result<()> {
+    let invalid = |reason: &str| Err(DatabaseError::InvalidIndexOptions(reason.to_string()));
+    if self.max_neighbors == Some(0) {
+      return invalid("max_neighbors must be positive");
+    }
+    if self.search_l == Some(0) {
+      return invalid("search_l must be positive");
+    }
+    if self
+      .alpha
+      .is_some_and(|alpha| !(alpha >= 1.0 && alpha.is_finite()))
+    {
+      return invalid("alpha must be a finite number of at least 1");
+    }
+    if self.metric == IndexMetric::L2 && !model.normalized {
+      return invalid("the l2 metric only agrees with cosine for normalized embeddings");
+    }
+    if self.metric != IndexMetric::Cosine
+      && (self.compress_neighbors == Some(NeighborCompression::Float1Bit)
+        || storage == EmbeddingStorage::Float1Bit)
+    {
+      return invalid("1-bit vectors need the cosine metric");
+    }
+    Ok(())
+  }
+}
+
+/// Size of the vector index, and how long it took to build when it was
+/// just built.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorIndexStats {
+  pub options: VectorIndexOptions,
+  /// Number of vectors in the index.
+  pub entries: u64,
+  /// Bytes of database pages the index occupies.
+  pub size_bytes: u64,
+  pub build_time: Option<Duration>,
+}
+
+pub(crate) async fn read_index_options(conn: &Connection) -> Result<VectorIndexOptions> {
+  let mut rows = conn
+    .query(
+      "SELECT value FROM metadata WHERE key = ?",
+      params![VECTOR_INDEX_OPTIONS_KEY],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let Some(row) = rows.next().await.map_err(DatabaseError::query)? else {
+    return Err(DatabaseError::MetadataNotFound(
+      VECTOR_INDEX_OPTIONS_KEY.to_string(),
+    ));
+  };
+  let value: String = row
+    .get(0)
+    .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?;
+  serde_json::from_str(&value)
+    .map_err(|_| DatabaseError::MetaParseFailed(format!("{VECTOR_INDEX_OPTIONS_KEY}={value}")))
+}
+
+/// Drop the vector index and build it again with `options`, recording them.
+/// The caller supplies the transaction.
+pub(crate) async fn create_index(conn: &Connection, options: &VectorIndexOptions) -> Result<()> {
+  let storage = read_storage(conn).await?;
+  options.validate(&read_model(conn).await?, storage)?;
+  let options_json =
+    serde_json::to_string(options).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+  conn
+    .execute(&format!("DROP INDEX IF EXISTS {VECTOR_INDEX}"), ())
+    .await
+    .map_err(DatabaseError::query)?;
+  conn
+    .execute(&options.create_sql(storage), ())
+    .await
+    .map_err(|e| DatabaseError::QueryFailed(format!("building {VECTOR_INDEX}: {e}")))?;
+  conn
+    .execute(
+      "INSERT INTO metadata (key, value) VALUES (?1, ?2)
+       ON CONFLICT (key) DO UPDATE SET value = ?2",
+      params![VECTOR_INDEX_OPTIONS_KEY, options_json],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  Ok(())
+}
+
+async fn index_stats(conn: &Connection, build_time: Option<Duration>) -> Result<VectorIndexStats> {
+  let shadow = format!("{VECTOR_INDEX}_shadow");
+  let mut rows = conn
+    .query(
+      &format!(
+        "SELECT
+           (SELECT COUNT(*) FROM {shadow}),
+           (SELECT COALESCE(SUM(pgsize), 0) FROM dbstat
+            WHERE name IN (SELECT name FROM sqlite_master WHERE tbl_name = ?1))"
+      ),
+      params![shadow.as_str()],
+    )
+    .await
+    .map_err(DatabaseError::query)?;
+  let row = rows
+    .next()
+    .await
+    .map_err(DatabaseError::query)?
+    .ok_or_else(|| DatabaseError::QueryFailed("index stats returned no rows".to_string()))?;
+  Ok(VectorIndexStats {
+    options: read_index_options(conn).await?,
+    entries: row
+      .get(0)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+    size_bytes: row
+      .get(1)
+      .map_err(|e| DatabaseError::RowParseFailed(e.to_string()))?,
+    build_time,
+  })
+}
+
+impl Database {
+  /// The options the vector index was last built with.
+  pub async fn vector_index_options(&self) -> Result<VectorIndexOptions> {
+    read_index_options(&*self.reader().await?).await
+  }
+
+  /// Current size of the vector index.
+  pub async fn vector_index_stats(&self) -> Result<VectorIndexStats> {
+    index_stats(&*self.reader().await?, None).await
+  }
+
+  /// Rebuild the vector index with new `options`.
+  ///
+  /// The old index is dropped and the new one built in a single write
+  /// transaction, so readers keep searching the old index until it commits
+  /// and never see the database without one. Writers wait for the build.
+  ///
+  /// # Errors
+  /// Returns [`DatabaseError::InvalidIndexOptions`] if the options are out of
+  /// range or don't suit the recorded embedding model; the existing index is
+  /// left in place.
+  pub async fn configure_vector_index(
+    &self,
+    options: &VectorIndexOptions,
+  ) -> Result<VectorIndexStats> {
+    let conn = self.writer().await?;
+    let tx = conn
+      .transaction_with_behavior(TransactionBehavior::Immediate)
+      .await
+      .map_err(DatabaseError::transaction)?;
+    let start = Instant::now();
+    create_index(&tx, options).await?;
+    let build_time = start.elapsed();
+    let stats = index_stats(&tx, Some(build_time)).await?;
+    tx.commit().await.map_err(DatabaseError::transaction)?;
+    Ok(stats)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::integrity::vector_index_sql;
+  use crate::models::Chunks;
+  use crate::test_support::{make_chunk, make_comic};
+  use crate::{DatabaseOptions, EMBEDDING_DIM};
+
+  const MODEL: EmbeddingModel = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+
+  /// Embedding turned `step` increments away from the first axis.
+  fn turned(step: u64) -> Vec<f32> {
+    let mut embedding = vec![0.0; EMBEDDING_DIM];
+    embedding[0] = 1.0;
+    embedding[1] = step as f32 * 0.1;
+    embedding
+  }
+
+  /// Comic 1 with `n` chunks, each turned a little further.
+  async fn setup_corpus(n: u64) -> Database {
+    let db = Database::new(":memory:").await.unwrap();
+    db.insert_comic(make_comic(1)).await.unwrap();
+    let chunks = (0..n)
+      .map(|i| Chunks {
+        embedding: turned(i),
+        ..make_chunk(1, i)
+      })
+      .collect();
+    db.insert_chunks_batch(&MODEL, chunks).await.unwrap();
+    db
+  }
+
+  #[test]
+  fn test_create_sql() {
+    assert_eq!(
+      VectorIndexOptions::default().create_sql(EmbeddingStorage::Full),
+      "CREATE INDEX chunks_vec_idx ON xkcd_chunks(libsql_vector_idx(embedding, 'metric=cosine'))"
+    );
+    let options = VectorIndexOptions {
+      metric: IndexMetric::L2,
+      max_neighbors: Some(16),
+      compress_neighbors: Some(NeighborCompression::Float8),
+      alpha: Some(1.2),
+      search_l: Some(100),
+    };
+    assert!(options.create_sql(EmbeddingStorage::Full).ends_with(
+      "'metric=l2', 'max_neighbors=16', 'compress_neighbors=float8', 'alpha=1.2', 'search_l=100'))"
+    ));
+  }
+
+  #[test]
+  fn test_validate() {
+    let ok = VectorIndexOptions {
+      compress_neighbors: Some(NeighborCompression::Float1Bit),
+      ..VectorIndexOptions::default()
+    };
+    assert!(ok.validate(&MODEL, EmbeddingStorage::Full).is_ok());
+    for bad in [
+      VectorIndexOptions {
+        alpha: Some(0.5),
+        ..VectorIndexOptions::default()
+      },
+      VectorIndexOptions {
+        max_neighbors: Some(0),
+        ..VectorIndexOptions::default()
+      },
+      VectorIndexOptions {
+        metric: IndexMetric::L2,
+        ..ok.clone()
+      },
+    ] {
+      assert!(matches!(
+        bad.validate(&MODEL, EmbeddingStorage::Full),
+        Err(DatabaseError::InvalidIndexOptions(_))
+      ));
+    }
+    let unnormalized = EmbeddingModel {
+      normalized: false,
+      ..MODEL
+    };
+    let l2 = VectorIndexOptions {
+      metric: IndexMetric::L2,
+      ..VectorIndexOptions::default()
+    };
+    assert!(l2.validate(&unnormalized, EmbeddingStorage::Full).is_err());
+    assert!(l2.validate(&MODEL, EmbeddingStorage::Float1Bit).is_err());
+    assert!(
+      VectorIndexOptions::default()
+        .create_sql(EmbeddingStorage::Float8)
+        .contains(" ON xkcd_chunks_quantized(")
+    );
+  }
+
+  #[tokio::test]
+  async fn test_configure_rebuilds_index() {
+    let db = setup_corpus(20).await;
+    assert_eq!(
+      db.vector_index_options().await.unwrap(),
+      VectorIndexOptions::default()
+    );
+    let before = db.vector_index_stats().await.unwrap();
+    assert_eq!(before.entries, 20);
+    assert!(before.size_bytes > 0);
+    assert_eq!(before.build_time, None);
+
+    let options = VectorIndexOptions {
+      max_neighbors: Some(8),
+      compress_neighbors: Some(NeighborCompression::Float8),
+      alpha: Some(1.2),
+      search_l: Some(50),
+      ..VectorIndexOptions::default()
+    };
+    let stats = db.configure_vector_index(&options).await.unwrap();
+    assert_eq!(stats.options, options);
+    assert_eq!(stats.entries, 20);
+    assert!(stats.build_time.is_some());
+    assert!(stats.size_bytes > 0);
+    assert_eq!(db.vector_index_options().await.unwrap(), options);
+    let sql = vector_index_sql(&db.reader().await.unwrap())
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(sql.contains("'compress_neighbors=float8'"), "{sql}");
+
+    // the rebuilt index still finds things
+    let results = db.vector_search(&MODEL, turned(3), 1).await.unwrap();
+    assert_eq!(results[0].chunk_text, "Chunk 3");
+
+    // invalid options leave the index alone
+    let bad = VectorIndexOptions {
+      alpha: Some(0.0),
+      ..VectorIndexOptions::default()
+    };
+    assert!(db.configure_vector_index(&bad).await.is_err());
+    assert_eq!(db.vector_index_options().await.unwrap(), options);
+    assert_eq!(db.vector_index_stats().await.unwrap().entries, 20);
+  }
+
+  #[tokio::test]
+  async fn test_options_for_new_database() {
+    let options = VectorIndexOptions {
+      compress_neighbors: Some(NeighborCompression::Float8),
+      ..VectorIndexOptions::default()
+    };
+    let db = DatabaseOptions::in_memory()
+      .vector_index(options.clone())
+      .open()
+      .await
+      .unwrap();
+    assert_eq!(db.vector_index_options().await.unwrap(), options);
+  }
+}
@@ -0,0 +1,53 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Errors from producing embeddings.
+#[derive(Error, Debug)]
+pub enum EmbedError {
+  /// The request couldn't be sent or the response couldn't be read
+  #[error("HTTP request failed: {0}")]
+  Http(#[from] reqwest::Error),
+
+  /// The server refused the request
+  #[error("Unexpected HTTP status {status}: {body}")]
+  Status { status: StatusCode, body: String },
+
+  /// The response wasn't the JSON the client expects
+  #[error("Failed to decode embedding response: {0}")]
+  Decode(#[from] serde_json::Error),
+
+  /// The server returned a different number of embeddings than asked for
+  #[error("Asked for {expected} embeddings, got {actual}")]
+  Count { expected: usize, actual: usize },
+
+  /// An embedding has the wrong number of dimensions
+  #[error("Expected {expected}-dimensional embeddings, got {actual}")]
+  Dimension { expected: usize, actual: usize },
+
+  /// A local model's files couldn't be read
+  #[error("Failed to load model: {0}")]
+  Load(String),
+
+  /// Running a local model failed
+  #[error("Inference failed: {0}")]
+  Inference(String),
+
+  /// The model can't be stored in the database
+  #[error("Unsupported embedding model: {0}")]
+  UnsupportedModel(String),
+
+  /// The server kept failing or throttling
+  #[error("Gave up after {attempts} attempts: {reason}")]
+  RetriesExhausted { attempts: u32, reason: String },
+}
+
+impl From<http_retry::RetriesExhausted> for EmbedError {
+  fn from(e: http_retry::RetriesExhausted) -> Self {
+    Self::RetriesExhausted {
+      attempts: e.attempts,
+      reason: e.reason,
+    }
+  }
+}
+
+pub type Result<T> = std::result::Result<T, EmbedError>;
@@ -0,0 +1,38 @@
+//! [`Embedder`] backends shared by the scraper and the bot.
+
+mod error;
+pub mod local;
+pub mod openai;
+mod qwen3;
+
+pub use db::{EMBEDDING_DIM, Embedder, EmbeddingModel, QWEN3_QUERY_PREFIX};
+pub use error::{EmbedError, Result};
+pub use local::{LocalEmbedder, LocalEmbedderOptions};
+pub use openai::{OpenAiEmbedder, OpenAiEmbedderOptions};
+
+/// Refuse models whose vectors wouldn't fit the database's embedding column.
+fn check_model(model: &EmbeddingModel) -> Result<()> {
+  if model.dimension != EMBEDDING_DIM {
+    return Err(EmbedError::UnsupportedModel(format!(
+      "{} produces {}-dimensional embeddings, the database stores {EMBEDDING_DIM}",
+      model.id, model.dimension
+    )));
+  }
+  Ok(())
+}
+
+/// The query instruction a model is known to be trained with.
+fn default_query_prefix(model: &EmbeddingModel) -> Option<&'static str> {
+  model
+    .id
+    .starts_with("Qwen/Qwen3-Embedding")
+    .then_some(QWEN3_QUERY_PREFIX)
+}
+
+/// Scale `vector` to unit length. Zero vectors are left alone.
+fn l2_normalize(vector: &mut [f32]) {
+  let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+  if norm > 0.0 {
+    vector.iter_mut().for_each(|x| *x /= norm);
+  }
+}
@@ -0,0 +1,484 @@
+//! In-process CPU inference for Qwen3-Embedding models, so embedding needs no
+//! separate server.
+
+use std::fmt;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use candle_core::{DType, Device};
+use candle_nn::VarBuilder;
+use db::{Embedder, EmbeddingModel};
+use tokenizers::Tokenizer;
+use tokio::sync::oneshot;
+
+use crate::error::{EmbedError, Result};
+use crate::qwen3::{Config, Encoder};
+use crate::{check_model, default_query_prefix, l2_normalize};
+
+/// Token the model reads the embedding from; appended when the tokenizer
+/// doesn't add it.
+const END_OF_TEXT: &str = "<|endoftext|>";
+
+/// Texts per forward pass.
+const DEFAULT_BATCH_SIZE: usize = 8;
+
+/// Longer texts are truncated. Chunks are far shorter; this bounds the
+/// memory a stray long text can take.
+const DEFAULT_MAX_TOKENS: usize = 512;
+
+/// Settings for a [`LocalEmbedder`].
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> embeddings::Result<()> {
+/// use embeddings::Embedder;
+/// use embeddings::local::LocalEmbedderOptions;
+///
+/// // a download of huggingface.co/Qwen/Qwen3-Embedding-0.6B
+/// let embedder = LocalEmbedderOptions::new("/var/lib/xkcd/Qwen3-Embedding-0.6B")
+///   .threads(2)
+///   .build()?;
+/// let vectors = embedder.embed_queries(&["sudo make me a sandwich".into()]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocalEmbedderOptions {
+  model_dir: PathBuf,
+  model: EmbeddingModel,
+  threads: usize,
+  batch_size: usize,
+  max_tokens: usize,
+  query_prefix: Option<String>,
+  document_prefix: Option<String>,
+}
+
+impl LocalEmbedderOptions {
+  /// Options for the model in `model_dir`, which holds its `config.json`,
+  /// `tokenizer.json` and `*.safetensors` weights. The model is taken to be
+  /// Qwen3-Embedding-0.6B unless set with [`LocalEmbedderOptions::model`].
+  #[must_use]
+  pub fn new(model_dir: impl Into<PathBuf>) -> Self {
+    let model = EmbeddingModel::QWEN3_EMBEDDING_0_6B;
+    Self {
+      model_dir: model_dir.into(),
+      query_prefix: default_query_prefix(&model).map(str::to_string),
+      model,
+      threads: std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+      batch_size: DEFAULT_BATCH_SIZE,
+      max_tokens: DEFAULT_MAX_TOKENS,
+      document_prefix: None,
+    }
+  }
+
+  /// The model the weights are, e.g. a fine-tune of Qwen3-Embedding. Its
+  /// dimension must match the weights' hidden size.
+  #[must_use]
+  pub fn model(mut self, model: EmbeddingModel) -> Self {
+    self.model = model;
+    self
+  }
+
+  /// Threads running inference (at least one). Embedding calls queue for
+  /// them, so this also bounds the CPU the embedder takes. Defaults to one
+  /// per core.
+  #[must_use]
+  pub fn threads(mut self, threads: usize) -> Self {
+    self.threads = threads.max(1);
+    self
+  }
+
+  /// Texts run through the model together (at least one).
+  #[must_use]
+  pub fn batch_size(mut self, batch_size: usize) -> Self {
+    self.batch_size = batch_size.max(1);
+    self
+  }
+
+  /// Texts are cut to this many tokens (at least two).
+  #[must_use]
+  pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+    self.max_tokens = max_tokens.max(2);
+    self
+  }
+
+  /// Instruction put before queries. Defaults to
+  /// [`QWEN3_QUERY_PREFIX`](db::QWEN3_QUERY_PREFIX) for Qwen3-Embedding
+  /// models and to none otherwise.
+  #[must_use]
+  pub fn query_prefix(mut self, prefix: Option<String>) -> Self {
+    self.query_prefix = prefix;
+    self
+  }
+
+  /// Instruction put before documents. None by default.
+  #[must_use]
+  pub fn document_prefix(mut self, prefix: Option<String>) -> Self {
+    self.document_prefix = prefix;
+    self
+  }
+
+  /// Load the tokenizer and weights and start the inference threads.
+  ///
+  /// This reads the whole model, so in async code call it from
+  /// `spawn_blocking`.
+  ///
+  /// # Errors
+  /// Returns [`EmbedError::UnsupportedModel`] if the model's dimension
+  /// doesn't match [`EMBEDDING_DIM`](db::EMBEDDING_DIM) or the weights, and
+  /// [`EmbedError::Load`] or [`EmbedError::Decode`] if the model directory
+  /// can't be read.
+  pub fn build(self) -> Result<LocalEmbedder> {
+    check_model(&self.model)?;
+    let dir = &self.model_dir;
+    let config: Config = serde_json::from_str(&read(&dir.join("config.json"))?)?;
+    if config.hidden_size != self.model.dimension {
+      return Err(EmbedError::UnsupportedModel(format!(
+        "{} has hidden size {}, expected {}",
+        dir.display(),
+        config.hidden_size,
+        self.model.dimension
+      )));
+    }
+
+    let tokenizer_path = dir.join("tokenizer.json");
+    let mut tokenizer = Tokenizer::from_file(&tokenizer_path)
+      .map_err(|e| EmbedError::Load(format!("{}: {e}", tokenizer_path.display())))?;
+    // padding and truncation are done here, keeping the end-of-text token
+    tokenizer.with_padding(None);
+    tokenizer
+      .with_truncation(None)
+      .map_err(|e| EmbedError::Load(e.to_string()))?;
+    let end_of_text = tokenizer.token_to_id(END_OF_TEXT);
+
+    let mut weights: Vec<PathBuf> = fs::read_dir(dir)
+      .map_err(|e| EmbedError::Load(format!("{}: {e}", dir.display())))?
+      .filter_map(|entry| entry.ok().map(|e| e.path()))
+      .filter(|path| path.extension().is_some_and(|ext| ext == "safetensors"))
+      .collect();
+    if weights.is_empty() {
+      return Err(EmbedError::Load(format!(
+        "no .safetensors weights in {}",
+        dir.display()
+      )));
+    }
+    weights.sort();
+    // SAFETY: the weight files are memory-mapped and must not be modified
+    // while the embedder is alive.
+    let vb = unsafe { VarBuilder::from_mmaped_safetensors(&weights, DType::F32, &Device::Cpu) }
+      .map_err(|e| EmbedError::Load(e.to_string()))?;
+    let encoder =
+      Encoder::new(&config, vb, self.max_tokens).map_err(|e| EmbedError::Load(e.to_string()))?;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+      .num_threads(self.threads)
+      .thread_name(|i| format!("embedder-{i}"))
+      .build()
+      .map_err(|e| EmbedError::Load(e.to_string()))?;
+
+    Ok(LocalEmbedder {
+      inner: Arc::new(Inner {
+        encoder,
+        tokenizer,
+        end_of_text,
+        normalize: self.model.normalized,
+      }),
+      pool: Arc::new(pool),
+      options: self,
+    })
+  }
+}
+
+fn read(path: &Path) -> Result<String> {
+  fs::read_to_string(path).map_err(|e| EmbedError::Load(format!("{}: {e}", path.display())))
+}
+
+/// [`Embedder`] running a Qwen3-Embedding model on the CPU.
+///
+/// Each text is embedded as the final hidden state of its last token, the
+/// end-of-text token, L2-normalised. Batches run on a dedicated thread pool
+/// of [`LocalEmbedderOptions::threads`] threads, so the async caller is never
+/// blocked and concurrent calls queue rather than oversubscribe the CPU.
+#[derive(Clone)]
+pub struct LocalEmbedder {
+  inner: Arc<Inner>,
+  pool: Arc<rayon::ThreadPool>,
+  options: LocalEmbedderOptions,
+}
+
+impl fmt::Debug for LocalEmbedder {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("LocalEmbedder")
+      .field("options", &self.options)
+      .finish_non_exhaustive()
+  }
+}
+
+struct Inner {
+  encoder: Encoder,
+  tokenizer: Tokenizer,
+  end_of_text: Option<u32>,
+  normalize: bool,
+}
+
+impl Inner {
+  fn tokenize(&self, text: &str) -> Result<Vec<u32>> {
+    let encoding = self
+      .tokenizer
+      .encode(text, true)
+      .map_err(|e| EmbedError::Inference(format!("tokenizing failed: {e}")))?;
+    let mut ids = encoding.get_ids().to_vec();
+    let max_len = self.encoder.max_len();
+    match self.end_of_text {
+      Some(end) if ids.last() != Some(&end) || ids.len() > max_len => {
+        ids.truncate(max_len - 1);
+        ids.push(end);
+      }
+      _ => ids.truncate(max_len),
+    }
+    if ids.is_empty() {
+      return Err(EmbedError::Inference(format!(
+        "{text:?} has no tokens to embed"
+      )));
+    }
+    Ok(ids)
+  }
+
+  fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let ids = texts
+      .iter()
+      .map(|text| self.tokenize(text))
+      .collect::<Result<Vec<_>>>()?;
+    let states = self
+      .encoder
+      .last_token_states(&ids)
+      .and_then(|states| states.to_vec2::<f32>())
+      .map_err(|e| EmbedError::Inference(e.to_string()))?;
+    Ok(
+      states
+        .into_iter()
+        .map(|mut vector| {
+          if self.normalize {
+            l2_normalize(&mut vector);
+          }
+          vector
+        })
+        .collect(),
+    )
+  }
+}
+
+impl Embedder for LocalEmbedder {
+  type Error = EmbedError;
+
+  fn model(&self) -> &EmbeddingModel {
+    &self.options.model
+  }
+
+  async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(self.options.batch_size) {
+      let (tx, rx) = oneshot::channel();
+      let inner = Arc::clone(&self.inner);
+      let batch = batch.to_vec();
+      self.pool.spawn(move || {
+        // the receiver only goes away if the caller stopped waiting
+        let _ = tx.send(inner.embed_batch(&batch));
+      });
+      let embedded = rx
+        .await
+        .map_err(|_| EmbedError::Inference("inference thread panicked".to_string()))??;
+      vectors.extend(embedded);
+    }
+    Ok(vectors)
+  }
+
+  fn query_prefix(&self) -> Option<&str> {
+    self.options.query_prefix.as_deref()
+  }
+
+  fn document_prefix(&self) -> Option<&str> {
+    self.options.document_prefix.as_deref()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use candle_core::Tensor;
+  use db::{EMBEDDING_DIM, QWEN3_QUERY_PREFIX};
+  use serde_json::json;
+
+  use super::*;
+
+  const VOCAB: &[&str] = &[END_OF_TEXT, "[UNK]", "a", "b", "c", "d", "e"];
+
+  /// Write a two-layer Qwen3 model with random weights and a word-level
+  /// tokenizer to `dir`, laid out like the Hugging Face release.
+  fn write_tiny_model(dir: &Path) {
+    let (hidden, inner, heads, kv_heads, head_dim) = (EMBEDDING_DIM, 32, 4, 2, 8);
+    let config = json!({
+      "architectures": ["Qwen3ForCausalLM"],
+      "vocab_size": VOCAB.len(),
+      "hidden_size": hidden,
+      "intermediate_size": inner,
+      "num_hidden_layers": 2,
+      "num_attention_heads": heads,
+      "num_key_value_heads": kv_heads,
+      "head_dim": head_dim,
+      "max_position_embeddings": 64,
+      "rope_theta": 1_000_000.0,
+      "rms_norm_eps": 1e-6,
+    });
+    fs::write(dir.join("config.json"), config.to_string()).unwrap();
+
+    let vocab: serde_json::Map<_, _> = VOCAB
+      .iter()
+      .enumerate()
+      .map(|(id, token)| (token.to_string(), json!(id)))
+      .collect();
+    let tokenizer = json!({
+      "version": "1.0",
+      "truncation": null,
+      "padding": null,
+      "added_tokens": [{
+        "id": 0, "content": END_OF_TEXT, "single_word": false, "lstrip": false,
+        "rstrip": false, "normalized": false, "special": true
+      }],
+      "normalizer": null,
+      "pre_tokenizer": {"type": "Whitespace"},
+      "post_processor": null,
+      "decoder": null,
+      "model": {"type": "WordLevel", "vocab": vocab, "unk_token": "[UNK]"},
+    });
+    fs::write(dir.join("tokenizer.json"), tokenizer.to_string()).unwrap();
+
+    let random = |shape: &[usize]| Tensor::randn(0f32, 0.2, shape, &Device::Cpu).unwrap();
+    let ones = |size: usize| Tensor::ones(size, DType::F32, &Device::Cpu).unwrap();
+    let mut tensors = HashMap::from([
+      (
+        "embed_tokens.weight".to_string(),
+        random(&[VOCAB.len(), hidden]),
+      ),
+      ("norm.weight".to_string(), ones(hidden)),
+    ]);
+    for layer in 0..2 {
+      let p = |name: &str| format!("layers.{layer}.{name}.weight");
+      tensors.extend([
+        (p("input_layernorm"), ones(hidden)),
+        (p("post_attention_layernorm"), ones(hidden)),
+        (p("self_attn.q_proj"), random(&[heads * head_dim, hidden])),
+        (
+          p("self_attn.k_proj"),
+          random(&[kv_heads * head_dim, hidden]),
+        ),
+        (
+          p("self_attn.v_proj"),
+          random(&[kv_heads * head_dim, hidden]),
+        ),
+        (p("self_attn.o_proj"), random(&[hidden, heads * head_dim])),
+        (p("self_attn.q_norm"), ones(head_dim)),
+        (p("self_attn.k_norm"), ones(head_dim)),
+        (p("mlp.gate_proj"), random(&[inner, hidden])),
+        (p("mlp.up_proj"), random(&[inner, hidden])),
+        (p("mlp.down_proj"), random(&[hidden, inner])),
+      ]);
+    }
+    candle_core::safetensors::save(&tensors, dir.join("model.safetensors")).unwrap();
+  }
+
+  fn texts(texts: &[&str]) -> Vec<String> {
+    texts.iter().map(|t| t.to_string()).collect()
+  }
+
+  fn assert_close(a: &[Vec<f32>], b: &[Vec<f32>]) {
+    assert_eq!(a.len(), b.len());
+    for (x, y) in a.iter().zip(b) {
+      let diff = x
+        .iter()
+        .zip(y)
+        .map(|(p, q)| (p - q).abs())
+        .fold(0.0, f32::max);
+      assert!(diff < 1e-4, "vectors differ by {diff}");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_batches_match_single_texts() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tiny_model(dir.path());
+    let input = texts(&["a b c", "b", "c a a b c d e", "e d"]);
+
+    let batched = LocalEmbedderOptions::new(dir.path())
+      .threads(2)
+      .batch_size(4)
+      .build()
+      .unwrap();
+    let vectors = batched.embed_documents(&input).await.unwrap();
+    assert_eq!(vectors.len(), 4);
+    for vector in &vectors {
+      assert_eq!(vector.len(), EMBEDDING_DIM);
+      let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+      assert!((norm - 1.0).abs() < 1e-4);
+    }
+    assert_ne!(vectors[0], vectors[1]);
+
+    // padding in a batch must not change any text's embedding
+    let single = LocalEmbedderOptions::new(dir.path())
+      .threads(1)
+      .batch_size(1)
+      .build()
+      .unwrap();
+    assert_close(&vectors, &single.embed_documents(&input).await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_truncation_keeps_end_of_text() {
+    let dir = tempfile::tempdir().unwrap();
+    write_tiny_model(dir.path());
+    let embedder = LocalEmbedderOptions::new(dir.path())
+      .max_tokens(4)
+      .build()
+      .unwrap();
+    assert_eq!(embedder.inner.tokenize("a b c d e").unwrap(), [2, 3, 4, 0]);
+    assert_eq!(embedder.inner.tokenize("").unwrap(), [0]);
+
+    let long = embedder.embed(&texts(&["a b c d e"])).await.unwrap();
+    let short = embedder.embed(&texts(&["a b c"])).await.unwrap();
+    assert_close(&long, &short);
+
+    assert_eq!(embedder.query_prefix(), Some(QWEN3_QUERY_PREFIX));
+    assert!(embedder.embed(&[]).await.unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_load_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let err = LocalEmbedderOptions::new(dir.path()).build().unwrap_err();
+    assert!(
+      matches!(err, EmbedError::Load(ref e) if e.contains("config.json")),
+      "{err:?}"
+    );
+
+    write_tiny_model(dir.path());
+    fs::remove_file(dir.path().join("model.safetensors")).unwrap();
+    let err = LocalEmbedderOptions::new(dir.path()).build().unwrap_err();
+    assert!(
+      matches!(err, EmbedError::Load(ref e) if e.contains("safetensors")),
+      "{err:?}"
+    );
+
+    let model = EmbeddingModel {
+      dimension: 384,
+      ..EmbeddingModel::QWEN3_EMBEDDING_0_6B
+    };
+    let err = LocalEmbedderOptions::new(dir.path())
+      .model(model)
+      .build()
+      .unwrap_err();
+    assert!(matches!(err, EmbedError::UnsupportedModel(_)), "{err:?}");
+  }
+}
@@ -0,0 +1,481 @@
+//! Client for servers with an OpenAI-compatible `/v1/embeddings` endpoint,
+//! such as llama.cpp, vLLM, Ollama or Text Embeddings Inference.
+
+use std::time::Duration;
+
+use db::{Embedder, EmbeddingModel};
+use http_retry::{Attempt, RetryPolicy, send_with_backoff};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EmbedError, Result};
+use crate::{check_model, default_query_prefix, l2_normalize};
+
+/// Texts per request.
+const DEFAULT_BATCH_SIZE: usize = 32;
+
+/// Settings for an [`OpenAiEmbedder`].
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> embeddings::Result<()> {
+/// use embeddings::{Embedder, EmbeddingModel, OpenAiEmbedderOptions};
+///
+/// // Ollama serving Qwen3-Embedding-0.6B
+/// let embedder = OpenAiEmbedderOptions::new(
+///   "http://localhost:11434/v1",
+///   EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+/// )
+/// .served_model("qwen3-embedding:0.6b")
+/// .build()?;
+/// let vectors = embedder.embed_queries(&["sudo make me a sandwich".into()]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbedderOptions {
+  base_url: String,
+  model: EmbeddingModel,
+  served_model: Option<String>,
+  api_key: Option<String>,
+  timeout: Duration,
+  retry: RetryPolicy,
+  batch_size: usize,
+  query_prefix: Option<String>,
+  document_prefix: Option<String>,
+}
+
+impl OpenAiEmbedderOptions {
+  /// Options for the API under `base_url`, e.g. `http://localhost:8080/v1`,
+  /// producing embeddings from `model`.
+  #[must_use]
+  pub fn new(base_url: impl Into<String>, model: EmbeddingModel) -> Self {
+    Self {
+      base_url: base_url.into(),
+      query_prefix: default_query_prefix(&model).map(str::to_string),
+      model,
+      served_model: None,
+      api_key: None,
+      timeout: Duration::from_secs(60),
+      retry: RetryPolicy::new(3, Duration::from_millis(500), Duration::from_secs(30)),
+      batch_size: DEFAULT_BATCH_SIZE,
+      document_prefix: None,
+    }
+  }
+
+  /// The name the server knows the model by, when it isn't the model id.
+  #[must_use]
+  pub fn served_model(mut self, name: impl Into<String>) -> Self {
+    self.served_model = Some(name.into());
+    self
+  }
+
+  /// Sent as a bearer token.
+  #[must_use]
+  pub fn api_key(mut self, key: impl Into<String>) -> Self {
+    self.api_key = Some(key.into());
+    self
+  }
+
+  #[must_use]
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// How often a request is retried after throttling, a server error or a
+  /// network failure before giving up.
+  #[must_use]
+  pub fn max_retries(mut self, retries: u32) -> Self {
+    self.retry.max_retries = retries;
+    self
+  }
+
+  /// Wait before the first retry and the cap it doubles up to, as in
+  /// [`RetryPolicy::new`].
+  #[must_use]
+  pub fn retry_delay(mut self, delay: Duration, max: Duration) -> Self {
+    self.retry = RetryPolicy::new(self.retry.max_retries, delay, max);
+    self
+  }
+
+  /// Texts sent per request (at least one).
+  #[must_use]
+  pub fn batch_size(mut self, batch_size: usize) -> Self {
+    self.batch_size = batch_size.max(1);
+    self
+  }
+
+  /// Instruction put before queries. Defaults to [`QWEN3_QUERY_PREFIX`]
+  /// for Qwen3-Embedding models and to none otherwise.
+  ///
+  /// [`QWEN3_QUERY_PREFIX`]: db::QWEN3_QUERY_PREFIX
+  #[must_use]
+  pub fn query_prefix(mut self, prefix: Option<String>) -> Self {
+    self.query_prefix = prefix;
+    self
+  }
+
+  /// Instruction put before documents. None by default.
+  #[must_use]
+  pub fn document_prefix(mut self, prefix: Option<String>) -> Self {
+    self.document_prefix = prefix;
+    self
+  }
+
+  /// Create the embedder.
+  ///
+  /// # Errors
+  /// Returns [`EmbedError::UnsupportedModel`] if the model's dimension
+  /// doesn't match [`EMBEDDING_DIM`](db::EMBEDDING_DIM), and
+  /// [`EmbedError::Http`] if the HTTP client can't be set up.
+  pub fn build(self) -> Result<OpenAiEmbedder> {
+    check_model(&self.model)?;
+    let http = reqwest::Client::builder()
+      .user_agent(concat!("insert-relevant-xkcd/", env!("CARGO_PKG_VERSION")))
+      .timeout(self.timeout)
+      .build()?;
+    let endpoint = format!("{}/embeddings", self.base_url.trim_end_matches('/'));
+    Ok(OpenAiEmbedder {
+      http,
+      endpoint,
+      options: self,
+    })
+  }
+}
+
+/// [`Embedder`] backed by an OpenAI-compatible embeddings server.
+///
+/// Texts are sent in batches, and each response is checked to hold one
+/// vector of the model's dimension per text. Vectors are L2-normalised here
+/// when the model's are meant to be, as not every server does it.
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbedder {
+  http: reqwest::Client,
+  endpoint: String,
+  options: OpenAiEmbedderOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+  model: &'a str,
+  input: &'a [String],
+  encoding_format: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+  data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+  index: usize,
+  embedding: Vec<f32>,
+}
+
+impl OpenAiEmbedder {
+  async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let response = self.post(texts).await?;
+    let mut vectors: Vec<Option<Vec<f32>>> = vec![None; texts.len()];
+    for data in response.data {
+      // servers may answer out of order
+      if let Some(slot) = vectors.get_mut(data.index) {
+        *slot = Some(data.embedding);
+      }
+    }
+    let placed = vectors.iter().filter(|v| v.is_some()).count();
+    if placed != texts.len() {
+      return Err(EmbedError::Count {
+        expected: texts.len(),
+        actual: placed,
+      });
+    }
+
+    let dimension = self.options.model.dimension;
+    vectors
+      .into_iter()
+      .flatten()
+      .map(|mut vector| {
+        if vector.len() != dimension {
+          return Err(EmbedError::Dimension {
+            expected: dimension,
+            actual: vector.len(),
+          });
+        }
+        if self.options.model.normalized {
+          l2_normalize(&mut vector);
+        }
+        Ok(vector)
+      })
+      .collect()
+  }
+
+  /// Send one batch, retrying throttling and transient failures with
+  /// backoff.
+  async fn post(&self, texts: &[String]) -> Result<EmbeddingResponse> {
+    let body = EmbeddingRequest {
+      model: self
+        .options
+        .served_model
+        .as_deref()
+        .unwrap_or(&self.options.model.id),
+      input: texts,
+      encoding_format: "float",
+    };
+    send_with_backoff(
+      &self.options.retry,
+      || {
+        let request = self.http.post(&self.endpoint).json(&body);
+        match &self.options.api_key {
+          Some(key) => request.bearer_auth(key),
+          None => request,
+        }
+      },
+      |response| async move {
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+          return Err(EmbedError::Status { status, body });
+        }
+        Ok(Attempt::Done(serde_json::from_str(&body)?))
+      },
+    )
+    .await
+  }
+}
+
+impl Embedder for OpenAiEmbedder {
+  type Error = EmbedError;
+
+  fn model(&self) -> &EmbeddingModel {
+    &self.options.model
+  }
+
+  async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(self.options.batch_size) {
+      vectors.extend(self.embed_batch(batch).await?);
+    }
+    Ok(vectors)
+  }
+
+  fn query_prefix(&self) -> Option<&str> {
+    self.options.query_prefix.as_deref()
+  }
+
+  fn document_prefix(&self) -> Option<&str> {
+    self.options.document_prefix.as_deref()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use db::{EMBEDDING_DIM, QWEN3_QUERY_PREFIX};
+  use reqwest::StatusCode;
+  use serde_json::json;
+  use wiremock::matchers::{body_partial_json, header, method, path};
+  use wiremock::{Mock, MockServer, ResponseTemplate};
+
+  use super::*;
+
+  /// A vector of `scale` along axis `axis`.
+  fn axis(axis: usize, scale: f32) -> Vec<f32> {
+    let mut vector = vec![0.0; EMBEDDING_DIM];
+    vector[axis] = scale;
+    vector
+  }
+
+  /// A response holding `vectors` with the given `index`es.
+  fn response(vectors: &[(usize, Vec<f32>)]) -> ResponseTemplate {
+    let data: Vec<_> = vectors
+      .iter()
+      .map(
+        |(index, embedding)| json!({"object": "embedding", "index": index, "embedding": embedding}),
+      )
+      .collect();
+    ResponseTemplate::new(200).set_body_json(json!({
+      "object": "list",
+      "data": data,
+      "model": "test",
+      "usage": {"prompt_tokens": 1, "total_tokens": 1},
+    }))
+  }
+
+  fn options(server: &MockServer) -> OpenAiEmbedderOptions {
+    OpenAiEmbedderOptions::new(
+      format!("{}/v1/", server.uri()),
+      EmbeddingModel::QWEN3_EMBEDDING_0_6B,
+    )
+    .retry_delay(Duration::from_millis(1), Duration::from_millis(10))
+  }
+
+  fn texts(texts: &[&str]) -> Vec<String> {
+    texts.iter().map(|t| t.to_string()).collect()
+  }
+
+  #[tokio::test]
+  async fn test_embeds_in_batches() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .and(path("/v1/embeddings"))
+      .and(header("authorization", "Bearer secret"))
+      .and(body_partial_json(
+        json!({"model": "qwen3-embedding:0.6b", "input": ["a", "b"]}),
+      ))
+      // out of order, and not normalised
+      .respond_with(response(&[(1, axis(1, 2.0)), (0, axis(0, 0.5))]))
+      .expect(1)
+      .mount(&server)
+      .await;
+    Mock::given(body_partial_json(json!({"input": ["c"]})))
+      .respond_with(response(&[(0, axis(2, 1.0))]))
+      .expect(1)
+      .mount(&server)
+      .await;
+
+    let embedder = options(&server)
+      .served_model("qwen3-embedding:0.6b")
+      .api_key("secret")
+      .batch_size(2)
+      .build()
+      .unwrap();
+    let vectors = embedder.embed(&texts(&["a", "b", "c"])).await.unwrap();
+    assert_eq!(vectors, [axis(0, 1.0), axis(1, 1.0), axis(2, 1.0)]);
+
+    // nothing to embed, nothing sent
+    assert!(embedder.embed(&[]).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_prefixes() {
+    let server = MockServer::start().await;
+    let query = format!("{QWEN3_QUERY_PREFIX}sandwich");
+    Mock::given(body_partial_json(json!({"input": [query]})))
+      .respond_with(response(&[(0, axis(0, 1.0))]))
+      .expect(1)
+      .mount(&server)
+      .await;
+    Mock::given(body_partial_json(json!({"input": ["sandwich"]})))
+      .respond_with(response(&[(0, axis(1, 1.0))]))
+      .expect(1)
+      .mount(&server)
+      .await;
+
+    let embedder = options(&server).build().unwrap();
+    assert_eq!(embedder.query_prefix(), Some(QWEN3_QUERY_PREFIX));
+    let input = texts(&["sandwich"]);
+    assert_eq!(
+      embedder.embed_queries(&input).await.unwrap(),
+      [axis(0, 1.0)]
+    );
+    assert_eq!(
+      embedder.embed_documents(&input).await.unwrap(),
+      [axis(1, 1.0)]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_retries_throttling_and_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+      .up_to_n_times(1)
+      .with_priority(1)
+      .mount(&server)
+      .await;
+    Mock::given(method("POST"))
+      .respond_with(ResponseTemplate::new(503))
+      .up_to_n_times(1)
+      .with_priority(2)
+      .mount(&server)
+      .await;
+    Mock::given(method("POST"))
+      .respond_with(response(&[(0, axis(0, 1.0))]))
+      .with_priority(3)
+      .mount(&server)
+      .await;
+
+    let embedder = options(&server).build().unwrap();
+    let vectors = embedder.embed(&texts(&["a"])).await.unwrap();
+    assert_eq!(vectors.len(), 1);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+  }
+
+  #[tokio::test]
+  async fn test_gives_up_after_max_retries() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .respond_with(ResponseTemplate::new(500))
+      .expect(2)
+      .mount(&server)
+      .await;
+
+    let embedder = options(&server).max_retries(1).build().unwrap();
+    let err = embedder.embed(&texts(&["a"])).await.unwrap_err();
+    assert!(
+      matches!(err, EmbedError::RetriesExhausted { attempts: 2, ref reason } if reason.contains("500")),
+      "{err:?}"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_rejects_bad_responses() {
+    let server = MockServer::start().await;
+    Mock::given(body_partial_json(json!({"input": ["short"]})))
+      .respond_with(response(&[(0, vec![1.0; 384])]))
+      .mount(&server)
+      .await;
+    Mock::given(body_partial_json(json!({"input": ["one", "two"]})))
+      .respond_with(response(&[(0, axis(0, 1.0)), (0, axis(1, 1.0))]))
+      .mount(&server)
+      .await;
+    Mock::given(body_partial_json(json!({"input": ["too long"]})))
+      .respond_with(ResponseTemplate::new(400).set_body_string("input too long"))
+      .mount(&server)
+      .await;
+
+    let embedder = options(&server).build().unwrap();
+    let err = embedder.embed(&texts(&["short"])).await.unwrap_err();
+    assert!(
+      matches!(
+        err,
+        EmbedError::Dimension {
+          expected: EMBEDDING_DIM,
+          actual: 384
+        }
+      ),
+      "{err:?}"
+    );
+    let err = embedder.embed(&texts(&["one", "two"])).await.unwrap_err();
+    assert!(
+      matches!(
+        err,
+        EmbedError::Count {
+          expected: 2,
+          actual: 1
+        }
+      ),
+      "{err:?}"
+    );
+    let err = embedder.embed(&texts(&["too long"])).await.unwrap_err();
+    assert!(
+      matches!(err, EmbedError::Status { status: StatusCode::BAD_REQUEST, ref body } if body == "input too long"),
+      "{err:?}"
+    );
+  }
+
+  #[test]
+  fn test_unsupported_model() {
+    let model = EmbeddingModel {
+      id: "sentence-transformers/all-MiniLM-L6-v2".into(),
+      dimension: 384,
+      ..EmbeddingModel::QWEN3_EMBEDDING_0_6B
+    };
+    let options = OpenAiEmbedderOptions::new("http://localhost:8080/v1", model);
+    assert!(options.query_prefix.is_none());
+    assert!(matches!(
+      options.build(),
+      Err(EmbedError::UnsupportedModel(_))
+    ));
+  }
+}
@@ -0,0 +1,284 @@
+//! The Qwen3 decoder stack used as a text encoder: no language-model head and
+//! no KV cache, with padding masked out so a batch of texts of different
+//! lengths embeds the same as each text on its own.
+
+use candle_core::{DType, Device, Module, Result, Tensor};
+use candle_nn::{Embedding, Linear, RmsNorm, VarBuilder};
+use serde::Deserialize;
+
+/// The fields of a Hugging Face `config.json` the encoder needs.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Config {
+  pub vocab_size: usize,
+  pub hidden_size: usize,
+  pub intermediate_size: usize,
+  pub num_hidden_layers: usize,
+  pub num_attention_heads: usize,
+  pub num_key_value_heads: usize,
+  pub head_dim: usize,
+  pub max_position_embeddings: usize,
+  pub rope_theta: f64,
+  pub rms_norm_eps: f64,
+}
+
+#[derive(Debug)]
+struct Rotary {
+  sin: Tensor,
+  cos: Tensor,
+}
+
+impl Rotary {
+  fn new(cfg: &Config, max_len: usize, device: &Device) -> Result<Self> {
+    let inv_freq: Vec<f32> = (0..cfg.head_dim)
+      .step_by(2)
+      .map(|i| 1.0 / cfg.rope_theta.powf(i as f64 / cfg.head_dim as f64) as f32)
+      .collect();
+    let inv_freq = Tensor::from_vec(inv_freq, (1, cfg.head_dim / 2), device)?;
+    let positions = Tensor::arange(0u32, max_len as u32, device)?
+      .to_dtype(DType::F32)?
+      .reshape((max_len, 1))?;
+    let freqs = positions.matmul(&inv_freq)?;
+    Ok(Self {
+      sin: freqs.sin()?,
+      cos: freqs.cos()?,
+    })
+  }
+
+  /// Rotate `x` of shape (batch, heads, len, head_dim) by position.
+  fn apply(&self, x: &Tensor) -> Result<Tensor> {
+    let len = x.dim(2)?;
+    candle_nn::rotary_emb::rope(
+      &x.contiguous()?,
+      &self.cos.narrow(0, 0, len)?,
+      &self.sin.narrow(0, 0, len)?,
+    )
+  }
+}
+
+#[derive(Debug)]
+struct Attention {
+  q_proj: Linear,
+  k_proj: Linear,
+  v_proj: Linear,
+  o_proj: Linear,
+  q_norm: RmsNorm,
+  k_norm: RmsNorm,
+  num_heads: usize,
+  num_kv_heads: usize,
+  head_dim: usize,
+}
+
+impl Attention {
+  fn new(cfg: &Config, vb: VarBuilder) -> Result<Self> {
+    let (heads, kv_heads, head_dim) = (
+      cfg.num_attention_heads,
+      cfg.num_key_value_heads,
+      cfg.head_dim,
+    );
+    Ok(Self {
+      q_proj: candle_nn::linear_no_bias(cfg.hidden_size, heads * head_dim, vb.pp("q_proj"))?,
+      k_proj: candle_nn::linear_no_bias(cfg.hidden_size, kv_heads * head_dim, vb.pp("k_proj"))?,
+      v_proj: candle_nn::linear_no_bias(cfg.hidden_size, kv_heads * head_dim, vb.pp("v_proj"))?,
+      o_proj: candle_nn::linear_no_bias(heads * head_dim, cfg.hidden_size, vb.pp("o_proj"))?,
+      q_norm: candle_nn::rms_norm(head_dim, cfg.rms_norm_eps, vb.pp("q_norm"))?,
+      k_norm: candle_nn::rms_norm(head_dim, cfg.rms_norm_eps, vb.pp("k_norm"))?,
+      num_heads: heads,
+      num_kv_heads: kv_heads,
+      head_dim,
+    })
+  }
+
+  fn forward(&self, x: &Tensor, mask: &Tensor, rotary: &Rotary) -> Result<Tensor> {
+    let (b, l, _) = x.dims3()?;
+    // (batch, len, heads, head_dim), normed per head, then heads first
+    let q = self
+      .q_norm
+      .forward(
+        &x.apply(&self.q_proj)?
+          .reshape((b, l, self.num_heads, self.head_dim))?,
+      )?
+      .transpose(1, 2)?;
+    let k = self
+      .k_norm
+      .forward(
+        &x.apply(&self.k_proj)?
+          .reshape((b, l, self.num_kv_heads, self.head_dim))?,
+      )?
+      .transpose(1, 2)?;
+    let v = x
+      .apply(&self.v_proj)?
+      .reshape((b, l, self.num_kv_heads, self.head_dim))?
+      .transpose(1, 2)?;
+
+    let q = rotary.apply(&q)?;
+    let k = rotary.apply(&k)?;
+    let groups = self.num_heads / self.num_kv_heads;
+    let k = repeat_kv(k, groups)?;
+    let v = repeat_kv(v, groups)?;
+
+    let scale = 1.0 / (self.head_dim as f64).sqrt();
+    let scores = (q.matmul(&k.t()?)? * scale)?.broadcast_add(mask)?;
+    let probs = candle_nn::ops::softmax_last_dim(&scores)?;
+    probs
+      .matmul(&v)?
+      .transpose(1, 2)?
+      .reshape((b, l, self.num_heads * self.head_dim))?
+      .apply(&self.o_proj)
+  }
+}
+
+/// Repeat each key/value head `n` times to match the query heads.
+fn repeat_kv(x: Tensor, n: usize) -> Result<Tensor> {
+  if n == 1 {
+    return x.contiguous();
+  }
+  let (b, heads, l, d) = x.dims4()?;
+  Tensor::cat(&vec![&x; n], 2)?
+    .reshape((b, heads * n, l, d))?
+    .contiguous()
+}
+
+#[derive(Debug)]
+struct Mlp {
+  gate_proj: Linear,
+  up_proj: Linear,
+  down_proj: Linear,
+}
+
+impl Module for Mlp {
+  fn forward(&self, x: &Tensor) -> Result<Tensor> {
+    let gate = x.apply(&self.gate_proj)?.silu()?;
+    (gate * x.apply(&self.up_proj)?)?.apply(&self.down_proj)
+  }
+}
+
+#[derive(Debug)]
+struct Layer {
+  input_norm: RmsNorm,
+  attention: Attention,
+  post_attention_norm: RmsNorm,
+  mlp: Mlp,
+}
+
+impl Layer {
+  fn new(cfg: &Config, vb: VarBuilder) -> Result<Self> {
+    let mlp = vb.pp("mlp");
+    Ok(Self {
+      input_norm: candle_nn::rms_norm(cfg.hidden_size, cfg.rms_norm_eps, vb.pp("input_layernorm"))?,
+      attention: Attention::new(cfg, vb.pp("self_attn"))?,
+      post_attention_norm: candle_nn::rms_norm(
+        cfg.hidden_size,
+        cfg.rms_norm_eps,
+        vb.pp("post_attention_layernorm"),
+      )?,
+      mlp: Mlp {
+        gate_proj: candle_nn::linear_no_bias(
+          cfg.hidden_size,
+          cfg.intermediate_size,
+          mlp.pp("gate_proj"),
+        )?,
+        up_proj: candle_nn::linear_no_bias(
+          cfg.hidden_size,
+          cfg.intermediate_size,
+          mlp.pp("up_proj"),
+        )?,
+        down_proj: candle_nn::linear_no_bias(
+          cfg.intermediate_size,
+          cfg.hidden_size,
+          mlp.pp("down_proj"),
+        )?,
+      },
+    })
+  }
+
+  fn forward(&self, x: &Tensor, mask: &Tensor, rotary: &Rotary) -> Result<Tensor> {
+    let h = self
+      .attention
+      .forward(&x.apply(&self.input_norm)?, mask, rotary)?;
+    let x = (x + h)?;
+    let h = x.apply(&self.post_attention_norm)?.apply(&self.mlp)?;
+    x + h
+  }
+}
+
+#[derive(Debug)]
+pub(crate) struct Encoder {
+  embed_tokens: Embedding,
+  layers: Vec<Layer>,
+  norm: RmsNorm,
+  rotary: Rotary,
+  max_len: usize,
+  device: Device,
+}
+
+impl Encoder {
+  /// Build the encoder from `vb` for inputs of up to `max_len` tokens.
+  /// Weights saved from the bare model and from a causal-LM wrapper (under
+  /// `model.`) both load.
+  pub(crate) fn new(cfg: &Config, vb: VarBuilder, max_len: usize) -> Result<Self> {
+    let vb = if vb.contains_tensor("model.embed_tokens.weight") {
+      vb.pp("model")
+    } else {
+      vb
+    };
+    let max_len = max_len.min(cfg.max_position_embeddings);
+    let layers = (0..cfg.num_hidden_layers)
+      .map(|i| Layer::new(cfg, vb.pp("layers").pp(i)))
+      .collect::<Result<_>>()?;
+    Ok(Self {
+      embed_tokens: candle_nn::embedding(cfg.vocab_size, cfg.hidden_size, vb.pp("embed_tokens"))?,
+      layers,
+      norm: candle_nn::rms_norm(cfg.hidden_size, cfg.rms_norm_eps, vb.pp("norm"))?,
+      rotary: Rotary::new(cfg, max_len, vb.device())?,
+      max_len,
+      device: vb.device().clone(),
+    })
+  }
+
+  pub(crate) fn max_len(&self) -> usize {
+    self.max_len
+  }
+
+  /// Final hidden state at the last token of each sequence, shape
+  /// (sequences, hidden). Sequences must be non-empty and at most
+  /// [`Encoder::max_len`] long.
+  pub(crate) fn last_token_states(&self, sequences: &[Vec<u32>]) -> Result<Tensor> {
+    let len = sequences.iter().map(Vec::len).max().unwrap_or(0);
+    // left padding puts every last token in the final column
+    let pads: Vec<usize> = sequences.iter().map(|s| len - s.len()).collect();
+    let ids: Vec<u32> = sequences
+      .iter()
+      .zip(&pads)
+      .flat_map(|(s, &pad)| std::iter::repeat_n(0, pad).chain(s.iter().copied()))
+      .collect();
+    let ids = Tensor::from_vec(ids, (sequences.len(), len), &self.device)?;
+    let mask = self.mask(&pads, len)?;
+
+    let mut h = self.embed_tokens.forward(&ids)?;
+    for layer in &self.layers {
+      h = layer.forward(&h, &mask, &self.rotary)?;
+    }
+    h.apply(&self.norm)?.narrow(1, len - 1, 1)?.squeeze(1)
+  }
+
+  /// Additive attention mask, shape (batch, 1, len, len): causal, and hiding
+  /// each sequence's padding. Padding rows see themselves so their softmax
+  /// stays finite; nothing reads them.
+  fn mask(&self, pads: &[usize], len: usize) -> Result<Tensor> {
+    let mask: Vec<f32> = pads
+      .iter()
+      .flat_map(|&pad| {
+        (0..len).flat_map(move |i| {
+          (0..len).map(move |j| {
+            if j == i || (j < i && j >= pad) {
+              0.0
+            } else {
+              f32::NEG_INFINITY
+            }
+          })
+        })
+      })
+      .collect();
+    Tensor::from_vec(mask, (pads.len(), 1, len, len), &self.device)
+  }
+}
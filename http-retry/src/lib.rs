@@ -0,0 +1,267 @@
+//! Sending HTTP requests with retries and exponential backoff, shared by the
+//! clients for the wiki and for embedding servers.
+
+use std::time::Duration;
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+/// How often a request is retried and how long to wait in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+  /// Retries after the first attempt.
+  pub max_retries: u32,
+  /// Wait before the first retry, doubled for each one after it.
+  pub delay: Duration,
+  /// Longest wait, also for a server's `Retry-After`.
+  pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+  /// A policy whose `max_delay` is at least `delay`.
+  #[must_use]
+  pub fn new(max_retries: u32, delay: Duration, max_delay: Duration) -> Self {
+    Self {
+      max_retries,
+      delay,
+      max_delay: max_delay.max(delay),
+    }
+  }
+
+  /// The wait before retry number `attempt` (from zero): the exponential
+  /// delay or the server's `retry_after`, whichever is longer, capped at
+  /// `max_delay`.
+  #[must_use]
+  pub fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let exponential = self.delay.saturating_mul(2u32.saturating_pow(attempt));
+    exponential
+      .max(retry_after.unwrap_or_default())
+      .min(self.max_delay)
+  }
+}
+
+/// What handling a response ended in.
+#[derive(Debug)]
+pub enum Attempt<T> {
+  Done(T),
+  /// Send the request again after backing off.
+  Retry {
+    reason: String,
+    retry_after: Option<Duration>,
+  },
+}
+
+/// Every attempt failed in a way worth retrying.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetriesExhausted {
+  pub attempts: u32,
+  /// Why the last attempt failed.
+  pub reason: String,
+}
+
+/// The `Retry-After` of `response`, when given in seconds.
+#[must_use]
+pub fn retry_after(response: &Response) -> Option<Duration> {
+  response
+    .headers()
+    .get(RETRY_AFTER)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.trim().parse().ok())
+    .map(Duration::from_secs)
+}
+
+/// Send the request built by `request` and pass the response to `read`,
+/// retrying under `policy`.
+///
+/// Timeouts, connection failures, `429 Too Many Requests` and server errors
+/// are retried here, honouring `Retry-After`; any other response goes to
+/// `read`, which can ask for a retry itself. Other send failures and errors
+/// from `read` are returned at once.
+///
+/// # Errors
+/// Returns the send failure or `read`'s error, or [`RetriesExhausted`] once
+/// `policy.max_retries` retries have failed.
+pub async fn send_with_backoff<T, E, Req, Read, Fut>(
+  policy: &RetryPolicy,
+  request: Req,
+  read: Read,
+) -> Result<T, E>
+where
+  Req: Fn() -> RequestBuilder,
+  Read: Fn(Response) -> Fut,
+  Fut: Future<Output = Result<Attempt<T>, E>>,
+  E: From<reqwest::Error> + From<RetriesExhausted>,
+{
+  let mut attempt = 0;
+  loop {
+    let (reason, retry_after) = match send_once(request(), &read).await? {
+      Attempt::Done(value) => return Ok(value),
+      Attempt::Retry {
+        reason,
+        retry_after,
+      } => (reason, retry_after),
+    };
+    if attempt >= policy.max_retries {
+      return Err(
+        RetriesExhausted {
+          attempts: attempt + 1,
+          reason,
+        }
+        .into(),
+      );
+    }
+    tokio::time::sleep(policy.backoff(attempt, retry_after)).await;
+    attempt += 1;
+  }
+}
+
+async fn send_once<T, E, Read, Fut>(request: RequestBuilder, read: &Read) -> Result<Attempt<T>, E>
+where
+  Read: Fn(Response) -> Fut,
+  Fut: Future<Output = Result<Attempt<T>, E>>,
+  E: From<reqwest::Error>,
+{
+  let response = match request.send().await {
+    Ok(response) => response,
+    Err(e) if e.is_timeout() || e.is_connect() => {
+      return Ok(Attempt::Retry {
+        reason: e.to_string(),
+        retry_after: None,
+      });
+    }
+    Err(e) => return Err(e.into()),
+  };
+
+  let status = response.status();
+  if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+    return Ok(Attempt::Retry {
+      reason: format!("HTTP {status}"),
+      retry_after: retry_after(&response),
+    });
+  }
+  read(response).await
+}
+
+#[cfg(test)]
+mod tests {
+  use wiremock::matchers::method;
+  use wiremock::{Mock, MockServer, ResponseTemplate};
+
+  use super::*;
+
+  #[derive(Debug)]
+  enum TestError {
+    Http,
+    Exhausted(RetriesExhausted),
+  }
+
+  impl From<reqwest::Error> for TestError {
+    fn from(_: reqwest::Error) -> Self {
+      Self::Http
+    }
+  }
+
+  impl From<RetriesExhausted> for TestError {
+    fn from(e: RetriesExhausted) -> Self {
+      Self::Exhausted(e)
+    }
+  }
+
+  fn policy(max_retries: u32) -> RetryPolicy {
+    RetryPolicy::new(
+      max_retries,
+      Duration::from_millis(1),
+      Duration::from_millis(10),
+    )
+  }
+
+  async fn send(server: &MockServer, max_retries: u32) -> Result<String, TestError> {
+    let http = reqwest::Client::new();
+    send_with_backoff(
+      &policy(max_retries),
+      || http.get(server.uri()),
+      |response| async move {
+        let body = response.text().await?;
+        Ok(if body == "later" {
+          Attempt::Retry {
+            reason: body,
+            retry_after: None,
+          }
+        } else {
+          Attempt::Done(body)
+        })
+      },
+    )
+    .await
+  }
+
+  #[tokio::test]
+  async fn test_retries_throttling_server_errors_and_read_retries() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+      .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+      .up_to_n_times(1)
+      .mount(&server)
+      .await;
+    Mock::given(method("GET"))
+      .respond_with(ResponseTemplate::new(503))
+      .up_to_n_times(1)
+      .mount(&server)
+      .await;
+    Mock::given(method("GET"))
+      .respond_with(ResponseTemplate::new(200).set_body_string("later"))
+      .up_to_n_times(1)
+      .mount(&server)
+      .await;
+    Mock::given(method("GET"))
+      .respond_with(ResponseTemplate::new(200).set_body_string("done"))
+      .mount(&server)
+      .await;
+
+    assert_eq!(send(&server, 3).await.unwrap(), "done");
+    assert_eq!(server.received_requests().await.unwrap().len(), 4);
+  }
+
+  #[tokio::test]
+  async fn test_gives_up_after_max_retries() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+      .respond_with(ResponseTemplate::new(500))
+      .mount(&server)
+      .await;
+
+    let err = send(&server, 2).await.unwrap_err();
+    assert!(
+      matches!(err, TestError::Exhausted(RetriesExhausted { attempts: 3, ref reason }) if reason.contains("500")),
+      "{err:?}"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_client_errors_go_to_read() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+      .respond_with(ResponseTemplate::new(404).set_body_string("missing"))
+      .mount(&server)
+      .await;
+
+    assert_eq!(send(&server, 3).await.unwrap(), "missing");
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+  }
+
+  #[test]
+  fn test_backoff() {
+    let policy = RetryPolicy::new(5, Duration::from_secs(1), Duration::from_secs(10));
+    assert_eq!(policy.backoff(0, None), Duration::from_secs(1));
+    assert_eq!(policy.backoff(2, None), Duration::from_secs(4));
+    assert_eq!(policy.backoff(10, None), Duration::from_secs(10));
+    assert_eq!(
+      policy.backoff(0, Some(Duration::from_secs(7))),
+      Duration::from_secs(7)
+    );
+    assert_eq!(
+      policy.backoff(0, Some(Duration::from_secs(600))),
+      Duration::from_secs(10)
+    );
+  }
+}
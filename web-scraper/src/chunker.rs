@@ -0,0 +1,466 @@
+//! Splitting parsed pages into chunks for embedding.
+
+use std::fmt;
+
+use db::{Chunks, ComicNumber, SectionType};
+
+use crate::wiki::ParsedPage;
+
+/// Default token budget per chunk.
+pub const DEFAULT_MAX_TOKENS: usize = 256;
+
+/// Default number of tokens repeated from the end of one chunk at the start
+/// of the next.
+pub const DEFAULT_OVERLAP_TOKENS: usize = 32;
+
+/// Abbreviations whose full stop doesn't end a sentence.
+const ABBREVIATIONS: &[&str] = &[
+  "e.g.", "i.e.", "etc.", "vs.", "cf.", "approx.", "Mr.", "Mrs.", "Ms.", "Dr.", "St.", "No.",
+];
+
+/// Rough token count for English text, about four characters a token. Used
+/// until a real tokenizer is plugged in with [`Chunker::token_counter`].
+pub fn estimate_tokens(text: &str) -> usize {
+  text.chars().count().div_ceil(4)
+}
+
+/// A stretch of section text, with the separator that goes before it when it
+/// follows another piece.
+#[derive(Debug, Clone, Copy)]
+struct Piece<'a> {
+  separator: &'static str,
+  text: &'a str,
+}
+
+fn join(pieces: &[Piece]) -> String {
+  let mut out = String::new();
+  for (i, piece) in pieces.iter().enumerate() {
+    if i > 0 {
+      out.push_str(piece.separator);
+    }
+    out.push_str(piece.text);
+  }
+  out
+}
+
+/// Splits the sections of a [`ParsedPage`] into token-budgeted chunks.
+///
+/// Text is broken at paragraphs where it can, then at sentences and lines,
+/// and at words only when a single sentence is over the budget. A chunk never
+/// spans two sections, and the title and hover text always make up a chunk of
+/// their own. Each chunk after the first in a section starts with up to
+/// `overlap_tokens` from the end of the one before, so context isn't lost at
+/// the cut.
+///
+/// # Example
+/// ```
+/// use web_scraper::chunker::Chunker;
+///
+/// let chunker = Chunker::new(8, 2).token_counter(|text| text.split_whitespace().count());
+/// let chunks = chunker.split("One two three four five. Six seven eight nine ten.");
+/// assert_eq!(
+///   chunks,
+///   ["One two three four five.", "four five. Six seven eight nine ten."]
+/// );
+/// ```
+pub struct Chunker {
+  max_tokens: usize,
+  overlap_tokens: usize,
+  count_tokens: Box<dyn Fn(&str) -> usize + Send + Sync>,
+}
+
+impl fmt::Debug for Chunker {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Chunker")
+      .field("max_tokens", &self.max_tokens)
+      .field("overlap_tokens", &self.overlap_tokens)
+      .finish_non_exhaustive()
+  }
+}
+
+impl Default for Chunker {
+  fn default() -> Self {
+    Self::new(DEFAULT_MAX_TOKENS, DEFAULT_OVERLAP_TOKENS)
+  }
+}
+
+impl Chunker {
+  /// A chunker with a budget of `max_tokens` per chunk, `overlap_tokens` of
+  /// which may be carried over from the previous chunk. The overlap is
+  /// capped at half the budget so every chunk makes progress.
+  pub fn new(max_tokens: usize, overlap_tokens: usize) -> Self {
+    let max_tokens = max_tokens.max(1);
+    Self {
+      max_tokens,
+      overlap_tokens: overlap_tokens.min(max_tokens / 2),
+      count_tokens: Box::new(estimate_tokens),
+    }
+  }
+
+  /// Count tokens with `count`, e.g. the embedding model's tokenizer,
+  /// instead of [`estimate_tokens`].
+  pub fn token_counter(mut self, count: impl Fn(&str) -> usize + Send + Sync + 'static) -> Self {
+    self.count_tokens = Box::new(count);
+    self
+  }
+
+  pub fn max_tokens(&self) -> usize {
+    self.max_tokens
+  }
+
+  pub fn overlap_tokens(&self) -> usize {
+    self.overlap_tokens
+  }
+
+  fn fits(&self, text: &str, budget: usize) -> bool {
+    (self.count_tokens)(text) <= budget
+  }
+
+  /// Chunks for a comic: the title and hover text first, then each section
+  /// of `page` in order. Chunks are numbered from 0 across the whole page and
+  /// have empty embeddings, to be filled in before
+  /// [`insert_chunks_batch`](db::Database::insert_chunks_batch).
+  pub fn chunk_page(&self, comic_number: ComicNumber, page: &ParsedPage) -> Vec<Chunks> {
+    let title_hover = match (&page.title, &page.hover_text) {
+      (Some(title), Some(hover)) => Some(format!("Title: {title}\nHover text: {hover}")),
+      (Some(title), None) => Some(format!("Title: {title}")),
+      (None, Some(hover)) => Some(format!("Hover text: {hover}")),
+      (None, None) => None,
+    };
+    let texts = title_hover
+      .into_iter()
+      .map(|text| (SectionType::TitleHover, text))
+      .chain(page.sections.iter().flat_map(|section| {
+        self
+          .split(&section.text)
+          .into_iter()
+          .map(|text| (section.section_type, text))
+      }));
+
+    texts
+      .enumerate()
+      .map(|(i, (section_type, chunk_text))| Chunks {
+        id: None,
+        comic_number,
+        chunk_text,
+        chunk_index: i as u64,
+        section_type: Some(section_type),
+        embedding: Vec::new(),
+      })
+      .collect()
+  }
+
+  /// Split one section's text into chunks within the token budget.
+  ///
+  /// A single word over the budget can't be broken and becomes a chunk of
+  /// its own.
+  pub fn split(&self, text: &str) -> Vec<String> {
+    let text = text.trim();
+    if text.is_empty() {
+      return Vec::new();
+    }
+    let mut pieces = Vec::new();
+    self.segment(
+      Piece {
+        separator: "",
+        text,
+      },
+      &mut pieces,
+    );
+
+    let mut chunks = Vec::new();
+    let mut current: Vec<Piece> = Vec::new();
+    for piece in pieces {
+      if !current.is_empty() {
+        current.push(piece);
+        if self.fits(&join(&current), self.max_tokens) {
+          continue;
+        }
+        current.pop();
+        chunks.push(join(&current));
+
+        let mut overlap = Vec::new();
+        self.overlap(&current, &mut overlap);
+        // drop overlap from the front until the new piece fits beside it
+        loop {
+          overlap.push(piece);
+          if overlap.len() == 1 || self.fits(&join(&overlap), self.max_tokens) {
+            break;
+          }
+          overlap.pop();
+          overlap.remove(0);
+        }
+        current = overlap;
+        continue;
+      }
+      current.push(piece);
+    }
+    if !current.is_empty() {
+      chunks.push(join(&current));
+    }
+    chunks
+  }
+
+  /// Break `piece` down until every part fits the budget.
+  fn segment<'a>(&self, piece: Piece<'a>, out: &mut Vec<Piece<'a>>) {
+    if self.fits(piece.text, self.max_tokens) {
+      out.push(piece);
+      return;
+    }
+    let parts = finer(piece);
+    if parts.len() == 1 {
+      out.push(piece);
+      return;
+    }
+    for part in parts {
+      self.segment(part, out);
+    }
+  }
+
+  /// Prepend to `taken` as much of the end of `pieces` as fits in the
+  /// overlap budget. Whole pieces are preferred; the last piece is only cut
+  /// into sentences or words when none of it would fit otherwise.
+  fn overlap<'a>(&self, pieces: &[Piece<'a>], taken: &mut Vec<Piece<'a>>) {
+    for &piece in pieces.iter().rev() {
+      taken.insert(0, piece);
+      if self.fits(&join(taken), self.overlap_tokens) {
+        continue;
+      }
+      taken.remove(0);
+      let parts = finer(piece);
+      if taken.is_empty() && parts.len() > 1 {
+        self.overlap(&parts, taken);
+      }
+      return;
+    }
+  }
+}
+
+/// The next level down from `piece`: its paragraphs, else its sentences and
+/// lines, else its words. The first part keeps `piece`'s separator.
+fn finer(piece: Piece) -> Vec<Piece> {
+  let text = piece.text;
+  let paragraphs: Vec<&str> = text
+    .split("\n\n")
+    .map(str::trim)
+    .filter(|p| !p.is_empty())
+    .collect();
+  let mut parts: Vec<Piece> = if paragraphs.len() > 1 {
+    paragraphs
+      .into_iter()
+      .map(|text| Piece {
+        separator: "\n\n",
+        text,
+      })
+      .collect()
+  } else {
+    let sentences = sentences(text);
+    if sentences.len() > 1 {
+      sentences
+    } else {
+      text
+        .split_whitespace()
+        .map(|text| Piece {
+          separator: " ",
+          text,
+        })
+        .collect()
+    }
+  };
+  if let Some(first) = parts.first_mut() {
+    first.separator = piece.separator;
+  }
+  parts
+}
+
+/// Split at line breaks and after sentence-ending punctuation followed by
+/// whitespace.
+fn sentences(text: &str) -> Vec<Piece<'_>> {
+  let mut out = Vec::new();
+  let mut separator = "";
+  for line in text.lines() {
+    let line = line.trim();
+    if line.is_empty() {
+      continue;
+    }
+    let mut start = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+      if !matches!(c, '.' | '!' | '?') {
+        continue;
+      }
+      // take closing quotes and brackets with the sentence
+      let mut end = i + c.len_utf8();
+      while let Some(&(j, next)) = chars.peek() {
+        if matches!(next, '"' | '\'' | ')' | ']' | '”' | '’' | '.' | '!' | '?') {
+          end = j + next.len_utf8();
+          chars.next();
+        } else {
+          break;
+        }
+      }
+      let followed_by_space = chars.peek().is_some_and(|&(_, next)| next.is_whitespace());
+      let sentence = &line[start..end];
+      let last_word = sentence.rsplit(' ').next().unwrap_or(sentence);
+      if followed_by_space && !ABBREVIATIONS.contains(&last_word) {
+        out.push(Piece {
+          separator,
+          text: sentence.trim(),
+        });
+        separator = " ";
+        start = end;
+      }
+    }
+    let rest = line[start..].trim();
+    if !rest.is_empty() {
+      out.push(Piece {
+        separator,
+        text: rest,
+      });
+    }
+    separator = "\n";
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::wiki::Section;
+
+  fn words(text: &str) -> usize {
+    text.split_whitespace().count()
+  }
+
+  fn chunker(max: usize, overlap: usize) -> Chunker {
+    Chunker::new(max, overlap).token_counter(words)
+  }
+
+  #[test]
+  fn test_short_text_is_one_chunk() {
+    let chunks = chunker(50, 5).split("  A single short paragraph.\n\nAnd another.  ");
+    assert_eq!(chunks, ["A single short paragraph.\n\nAnd another."]);
+    assert!(chunker(50, 5).split(" \n ").is_empty());
+  }
+
+  #[test]
+  fn test_breaks_at_paragraphs_then_sentences() {
+    let text = "One two three. Four five six.\n\nSeven eight nine ten. Eleven twelve.";
+    let chunks = chunker(7, 0).split(text);
+    assert_eq!(
+      chunks,
+      [
+        "One two three. Four five six.",
+        "Seven eight nine ten. Eleven twelve."
+      ]
+    );
+
+    let chunks = chunker(4, 0).split(text);
+    assert_eq!(
+      chunks,
+      [
+        "One two three.",
+        "Four five six.",
+        "Seven eight nine ten.",
+        "Eleven twelve."
+      ]
+    );
+  }
+
+  #[test]
+  fn test_breaks_long_sentences_at_words() {
+    let text = "a b c d e f g h i j";
+    let chunks = chunker(4, 0).split(text);
+    assert_eq!(chunks, ["a b c d", "e f g h", "i j"]);
+
+    let chunks = chunker(4, 1).split(text);
+    assert_eq!(chunks, ["a b c d", "d e f g", "g h i j"]);
+    assert!(chunks.iter().all(|c| words(c) <= 4));
+  }
+
+  #[test]
+  fn test_overlap_prefers_whole_sentences() {
+    let text = "First one here. Second one. Third one now. Fourth.";
+    let chunks = chunker(6, 3).split(text);
+    assert_eq!(
+      chunks,
+      [
+        "First one here. Second one.",
+        "Second one. Third one now. Fourth."
+      ]
+    );
+  }
+
+  #[test]
+  fn test_sentences() {
+    let texts: Vec<&str> =
+      sentences("He said \"Stop.\" Then left, e.g. to eat. Pi is 3.14 exactly!\n:Cueball: Hi. ")
+        .iter()
+        .map(|p| p.text)
+        .collect();
+    assert_eq!(
+      texts,
+      [
+        "He said \"Stop.\"",
+        "Then left, e.g. to eat.",
+        "Pi is 3.14 exactly!",
+        ":Cueball: Hi."
+      ]
+    );
+  }
+
+  #[test]
+  fn test_chunk_page() {
+    let page = ParsedPage {
+      number: Some(149),
+      title: Some("Sandwich".to_string()),
+      hover_text: Some("Proper User Policy apparently means Simon Says.".to_string()),
+      date: None,
+      sections: vec![
+        Section {
+          section_type: SectionType::Explanation,
+          heading: "Explanation".to_string(),
+          text: "Sudo runs commands as root. Megan complies.".to_string(),
+        },
+        Section {
+          section_type: SectionType::Transcript,
+          heading: "Transcript".to_string(),
+          text: "Cueball: Make me a sandwich.".to_string(),
+        },
+      ],
+    };
+    let chunks = chunker(5, 0).chunk_page(ComicNumber::new(149).unwrap(), &page);
+    let summary: Vec<(u64, Option<SectionType>, &str)> = chunks
+      .iter()
+      .map(|c| (c.chunk_index, c.section_type, c.chunk_text.as_str()))
+      .collect();
+    assert_eq!(
+      summary,
+      [
+        (
+          0,
+          Some(SectionType::TitleHover),
+          "Title: Sandwich\nHover text: Proper User Policy apparently means Simon Says."
+        ),
+        (
+          1,
+          Some(SectionType::Explanation),
+          "Sudo runs commands as root."
+        ),
+        (2, Some(SectionType::Explanation), "Megan complies."),
+        // would fit after "Megan complies." but is a different section
+        (
+          3,
+          Some(SectionType::Transcript),
+          "Cueball: Make me a sandwich."
+        ),
+      ]
+    );
+    assert!(chunks.iter().all(|c| c.comic_number.get() == 149));
+    assert!(
+      chunks
+        .iter()
+        .all(|c| c.id.is_none() && c.embedding.is_empty())
+    );
+  }
+}
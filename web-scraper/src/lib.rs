@@ -0,0 +1,4 @@
+//! Fetching and processing explainxkcd pages for the comic database.
+
+pub mod chunker;
+pub mod wiki;
@@ -0,0 +1,580 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use http_retry::{Attempt, RetryPolicy, send_with_backoff};
+use serde::Deserialize;
+
+use super::error::{Result, WikiError};
+
+/// The explainxkcd API endpoint.
+pub const EXPLAINXKCD_API: &str = "https://www.explainxkcd.com/wiki/api.php";
+
+/// Titles per request, MediaWiki's limit for clients without the bot right.
+const DEFAULT_BATCH_SIZE: usize = 50;
+
+/// The latest revision of a page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+  pub revid: u64,
+  pub parent_id: Option<u64>,
+  pub timestamp: DateTime<Utc>,
+  /// The page source, when it was asked for.
+  pub wikitext: Option<String>,
+}
+
+/// An explainxkcd page found for a comic number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+  pub comic_number: u64,
+  pub page_id: u64,
+  /// Title the comic number resolved to, e.g. `149: Sandwich`.
+  pub title: String,
+  pub revision: Revision,
+}
+
+/// Settings for a [`WikiClient`].
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> web_scraper::wiki::Result<()> {
+/// use web_scraper::wiki::{EXPLAINXKCD_API, WikiClientOptions};
+///
+/// let client = WikiClientOptions::new(EXPLAINXKCD_API)
+///   .user_agent("xkcd-bot/0.1 (admin@example.com)")
+///   .max_retries(3)
+///   .build()?;
+/// let page = client.page(149).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WikiClientOptions {
+  endpoint: String,
+  user_agent: String,
+  timeout: Duration,
+  maxlag: Option<u32>,
+  retry: RetryPolicy,
+  batch_size: usize,
+}
+
+impl WikiClientOptions {
+  /// Options for the `api.php` at `endpoint`.
+  #[must_use]
+  pub fn new(endpoint: impl Into<String>) -> Self {
+    Self {
+      endpoint: endpoint.into(),
+      user_agent: concat!("insert-relevant-xkcd/", env!("CARGO_PKG_VERSION")).to_string(),
+      timeout: Duration::from_secs(30),
+      maxlag: Some(5),
+      retry: RetryPolicy::new(5, Duration::from_secs(1), Duration::from_secs(60)),
+      batch_size: DEFAULT_BATCH_SIZE,
+    }
+  }
+
+  /// Wikis ask API clients to identify themselves with contact details.
+  #[must_use]
+  pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+    self.user_agent = user_agent.into();
+    self
+  }
+
+  #[must_use]
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// The `maxlag` sent with every request: the server refuses work while its
+  /// replicas are more than this many seconds behind. `None` never defers.
+  #[must_use]
+  pub fn maxlag(mut self, seconds: Option<u32>) -> Self {
+    self.maxlag = seconds;
+    self
+  }
+
+  /// How often a request is retried after lag, throttling, a server error
+  /// or a network failure before giving up.
+  #[must_use]
+  pub fn max_retries(mut self, retries: u32) -> Self {
+    self.retry.max_retries = retries;
+    self
+  }
+
+  /// First retry delay and the longest wait, doubling in between; see
+  /// [`RetryPolicy`].
+  #[must_use]
+  pub fn retry_delay(mut self, delay: Duration, max: Duration) -> Self {
+    self.retry = RetryPolicy::new(self.retry.max_retries, delay, max);
+    self
+  }
+
+  /// Titles looked up per request (at least one).
+  #[must_use]
+  pub fn batch_size(mut self, batch_size: usize) -> Self {
+    self.batch_size = batch_size.max(1);
+    self
+  }
+
+  /// Create the client.
+  ///
+  /// # Errors
+  /// Returns [`WikiError::Http`] if the HTTP client can't be set up.
+  pub fn build(self) -> Result<WikiClient> {
+    let http = reqwest::Client::builder()
+      .user_agent(&self.user_agent)
+      .timeout(self.timeout)
+      .build()?;
+    Ok(WikiClient {
+      http,
+      options: self,
+    })
+  }
+}
+
+/// Client for a MediaWiki `api.php`, explainxkcd's by default.
+///
+/// Pages are looked up by comic number: explainxkcd redirects the bare
+/// number to the page titled `<number>: <title>`.
+#[derive(Debug, Clone)]
+pub struct WikiClient {
+  http: reqwest::Client,
+  options: WikiClientOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+  error: Option<ApiError>,
+  #[serde(rename = "continue")]
+  continuation: Option<HashMap<String, serde_json::Value>>,
+  query: Option<QueryResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+  code: String,
+  info: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct QueryResult {
+  #[serde(default)]
+  normalized: Vec<TitleMapping>,
+  #[serde(default)]
+  redirects: Vec<TitleMapping>,
+  #[serde(default)]
+  pages: Vec<RawPage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TitleMapping {
+  from: String,
+  to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawPage {
+  pageid: Option<u64>,
+  title: String,
+  #[serde(default)]
+  revisions: Vec<RawRevision>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRevision {
+  revid: u64,
+  parentid: Option<u64>,
+  timestamp: DateTime<Utc>,
+  /// Content on wikis without multi-content revisions.
+  content: Option<String>,
+  slots: Option<Slots>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Slots {
+  main: Slot,
+}
+
+#[derive(Debug, Deserialize)]
+struct Slot {
+  content: Option<String>,
+}
+
+impl RawRevision {
+  fn into_revision(self) -> Revision {
+    Revision {
+      revid: self.revid,
+      parent_id: self.parentid.filter(|&id| id != 0),
+      timestamp: self.timestamp,
+      wikitext: self.slots.and_then(|s| s.main.content).or(self.content),
+    }
+  }
+}
+
+impl WikiClient {
+  /// A client for explainxkcd with the default options.
+  ///
+  /// # Errors
+  /// Returns [`WikiError::Http`] if the HTTP client can't be set up.
+  pub fn new() -> Result<Self> {
+    WikiClientOptions::new(EXPLAINXKCD_API).build()
+  }
+
+  /// The page for `comic_number` with its latest wikitext, or `None` if the
+  /// wiki has no such page.
+  ///
+  /// # Errors
+  /// Returns an error if the request fails after retries or the API reports
+  /// an error.
+  pub async fn page(&self, comic_number: u64) -> Result<Option<Page>> {
+    Ok(self.pages(&[comic_number]).await?.pop())
+  }
+
+  /// The pages for `comic_numbers` with their latest wikitext, in the order
+  /// asked for. Comics without a page are left out.
+  ///
+  /// # Errors
+  /// Returns an error if a request fails after retries or the API reports
+  /// an error.
+  pub async fn pages(&self, comic_numbers: &[u64]) -> Result<Vec<Page>> {
+    self.query_revisions(comic_numbers, true).await
+  }
+
+  /// Like [`WikiClient::pages`] but without the wikitext, to check cheaply
+  /// which comics changed since they were scraped.
+  ///
+  /// # Errors
+  /// Returns an error if a request fails after retries or the API reports
+  /// an error.
+  pub async fn latest_revisions(&self, comic_numbers: &[u64]) -> Result<Vec<Page>> {
+    self.query_revisions(comic_numbers, false).await
+  }
+
+  async fn query_revisions(&self, comic_numbers: &[u64], content: bool) -> Result<Vec<Page>> {
+    let mut found: HashMap<u64, Page> = HashMap::new();
+    for batch in comic_numbers.chunks(self.options.batch_size) {
+      let titles: Vec<String> = batch.iter().map(u64::to_string).collect();
+      let mut params = vec![
+        ("action", "query".to_string()),
+        ("prop", "revisions".to_string()),
+        ("titles", titles.join("|")),
+        ("redirects", "1".to_string()),
+      ];
+      if content {
+        params.push(("rvprop", "ids|timestamp|content".to_string()));
+        params.push(("rvslots", "main".to_string()));
+      } else {
+        params.push(("rvprop", "ids|timestamp".to_string()));
+      }
+
+      let mut continuation: Vec<(String, String)> = Vec::new();
+      loop {
+        let response = self.get(&params, &continuation).await?;
+        let query = response.query.unwrap_or_default();
+        let targets: Vec<String> = titles.iter().map(|t| resolve_title(&query, t)).collect();
+        for page in query.pages {
+          let (Some(page_id), Some(revision)) = (page.pageid, page.revisions.into_iter().next())
+          else {
+            // missing, or its revision comes in a later continuation
+            continue;
+          };
+          let revision = revision.into_revision();
+          for (comic_number, target) in batch.iter().zip(&targets) {
+            if *target == page.title {
+              found.entry(*comic_number).or_insert_with(|| Page {
+                comic_number: *comic_number,
+                page_id,
+                title: page.title.clone(),
+                revision: revision.clone(),
+              });
+            }
+          }
+        }
+
+        match response.continuation {
+          Some(next) => continuation = continuation_params(next),
+          None => break,
+        }
+      }
+    }
+
+    Ok(
+      comic_numbers
+        .iter()
+        .filter_map(|n| found.remove(n))
+        .collect(),
+    )
+  }
+
+  /// Send one GET to the API, retrying lag, throttling and transient
+  /// failures with backoff.
+  async fn get(
+    &self,
+    params: &[(&str, String)],
+    continuation: &[(String, String)],
+  ) -> Result<ApiResponse> {
+    send_with_backoff(
+      &self.options.retry,
+      || {
+        let request = self
+          .http
+          .get(&self.options.endpoint)
+          .query(&[("format", "json"), ("formatversion", "2")])
+          .query(params)
+          .query(continuation);
+        match self.options.maxlag {
+          Some(maxlag) => request.query(&[("maxlag", maxlag)]),
+          None => request,
+        }
+      },
+      |response| async move {
+        let status = response.status();
+        if !status.is_success() {
+          return Err(WikiError::Status(status));
+        }
+        let retry_after = http_retry::retry_after(&response);
+        let parsed: ApiResponse = serde_json::from_str(&response.text().await?)?;
+        match parsed.error {
+          Some(error) if error.code == "maxlag" => Ok(Attempt::Retry {
+            reason: error.info,
+            retry_after,
+          }),
+          Some(error) => Err(WikiError::Api {
+            code: error.code,
+            info: error.info,
+          }),
+          None => Ok(Attempt::Done(parsed)),
+        }
+      },
+    )
+    .await
+  }
+}
+
+/// The page title `title` ends up at after normalisation and redirects.
+fn resolve_title(query: &QueryResult, title: &str) -> String {
+  let steps: HashMap<&str, &str> = query
+    .normalized
+    .iter()
+    .chain(&query.redirects)
+    .map(|m| (m.from.as_str(), m.to.as_str()))
+    .collect();
+  let mut resolved = title;
+  // bounded, in case of a redirect loop
+  for _ in 0..=steps.len() {
+    match steps.get(resolved) {
+      Some(next) => resolved = next,
+      None => break,
+    }
+  }
+  resolved.to_string()
+}
+
+/// Turn a `continue` object into parameters for the next request.
+fn continuation_params(next: HashMap<String, serde_json::Value>) -> Vec<(String, String)> {
+  next
+    .into_iter()
+    .map(|(key, value)| {
+      let value = match value {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+      };
+      (key, value)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use reqwest::StatusCode;
+  use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+  use wiremock::{Mock, MockServer, ResponseTemplate};
+
+  fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/api/{name}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"))
+  }
+
+  fn json(name: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(fixture(name), "application/json")
+  }
+
+  fn client(server: &MockServer) -> WikiClient {
+    WikiClientOptions::new(format!("{}/api.php", server.uri()))
+      .retry_delay(Duration::from_millis(1), Duration::from_millis(10))
+      .build()
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_page_follows_redirect() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+      .and(path("/api.php"))
+      .and(query_param("action", "query"))
+      .and(query_param("titles", "149"))
+      .and(query_param("rvprop", "ids|timestamp|content"))
+      .and(query_param("maxlag", "5"))
+      .respond_with(json("page_149.json"))
+      .expect(1)
+      .mount(&server)
+      .await;
+
+    let page = client(&server).page(149).await.unwrap().unwrap();
+    assert_eq!(page.comic_number, 149);
+    assert_eq!(page.page_id, 1254);
+    assert_eq!(page.title, "149: Sandwich");
+    assert_eq!(page.revision.revid, 342_118);
+    assert_eq!(page.revision.parent_id, Some(339_870));
+    assert_eq!(
+      page.revision.timestamp.to_rfc3339(),
+      "2024-06-02T17:41:09+00:00"
+    );
+    let wikitext = page.revision.wikitext.unwrap();
+    assert!(wikitext.starts_with("{{comic\n| number    = 149"));
+    assert!(wikitext.contains("==Transcript=="));
+  }
+
+  #[tokio::test]
+  async fn test_missing_page() {
+    let server = MockServer::start().await;
+    Mock::given(query_param("titles", "99999"))
+      .respond_with(json("missing.json"))
+      .mount(&server)
+      .await;
+    assert_eq!(client(&server).page(99999).await.unwrap(), None);
+  }
+
+  #[tokio::test]
+  async fn test_batches_and_continuation() {
+    let server = MockServer::start().await;
+    Mock::given(query_param("titles", "3|1|2"))
+      .and(query_param_is_missing("rvcontinue"))
+      .respond_with(json("batch_1.json"))
+      .expect(1)
+      .mount(&server)
+      .await;
+    Mock::given(query_param("titles", "3|1|2"))
+      .and(query_param("rvcontinue", "2|2391"))
+      .and(query_param("continue", "||"))
+      .respond_with(json("batch_2.json"))
+      .expect(1)
+      .mount(&server)
+      .await;
+    Mock::given(query_param("titles", "149"))
+      .respond_with(json("page_149.json"))
+      .expect(1)
+      .mount(&server)
+      .await;
+
+    let client = WikiClientOptions::new(format!("{}/api.php", server.uri()))
+      .batch_size(3)
+      .build()
+      .unwrap();
+    let pages = client.pages(&[3, 1, 2, 149]).await.unwrap();
+    let found: Vec<(u64, &str)> = pages
+      .iter()
+      .map(|p| (p.comic_number, p.title.as_str()))
+      .collect();
+    assert_eq!(
+      found,
+      vec![
+        (3, "3: Island (sketch)"),
+        (1, "1: Barrel - Part 1"),
+        (2, "2: Petit Trees (sketch)"),
+        (149, "149: Sandwich"),
+      ]
+    );
+    // a first revision's parentid of 0 means there is none
+    assert_eq!(pages[2].revision.parent_id, None);
+    assert!(pages.iter().all(|p| p.revision.wikitext.is_some()));
+  }
+
+  #[tokio::test]
+  async fn test_latest_revisions_skip_content() {
+    let server = MockServer::start().await;
+    Mock::given(query_param("titles", "149|327"))
+      .and(query_param("rvprop", "ids|timestamp"))
+      .and(query_param_is_missing("rvslots"))
+      .respond_with(json("revisions_only.json"))
+      .expect(1)
+      .mount(&server)
+      .await;
+
+    let pages = client(&server).latest_revisions(&[149, 327]).await.unwrap();
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[1].revision.revid, 351_002);
+    assert!(pages.iter().all(|p| p.revision.wikitext.is_none()));
+  }
+
+  #[tokio::test]
+  async fn test_backs_off_on_maxlag_and_throttling() {
+    let server = MockServer::start().await;
+    Mock::given(query_param("titles", "149"))
+      .respond_with(json("maxlag.json").insert_header("Retry-After", "7"))
+      .up_to_n_times(1)
+      .with_priority(1)
+      .mount(&server)
+      .await;
+    Mock::given(query_param("titles", "149"))
+      .respond_with(ResponseTemplate::new(503))
+      .up_to_n_times(1)
+      .with_priority(2)
+      .mount(&server)
+      .await;
+    Mock::given(query_param("titles", "149"))
+      .respond_with(json("page_149.json"))
+      .with_priority(3)
+      .mount(&server)
+      .await;
+
+    let page = client(&server).page(149).await.unwrap();
+    assert_eq!(page.unwrap().revision.revid, 342_118);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+  }
+
+  #[tokio::test]
+  async fn test_gives_up_after_max_retries() {
+    let server = MockServer::start().await;
+    Mock::given(query_param("titles", "149"))
+      .respond_with(json("maxlag.json"))
+      .expect(3)
+      .mount(&server)
+      .await;
+
+    let client = WikiClientOptions::new(format!("{}/api.php", server.uri()))
+      .max_retries(2)
+      .retry_delay(Duration::from_millis(1), Duration::from_millis(10))
+      .build()
+      .unwrap();
+    let err = client.page(149).await.unwrap_err();
+    assert!(
+      matches!(err, WikiError::RetriesExhausted { attempts: 3, ref reason } if reason.contains("lagged")),
+      "{err:?}"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_api_error() {
+    let server = MockServer::start().await;
+    Mock::given(query_param("titles", "149"))
+      .respond_with(json("bad_value.json"))
+      .expect(1)
+      .mount(&server)
+      .await;
+    let err = client(&server).page(149).await.unwrap_err();
+    assert!(
+      matches!(err, WikiError::Api { ref code, .. } if code == "badvalue"),
+      "{err:?}"
+    );
+
+    let server = MockServer::start().await;
+    Mock::given(query_param("titles", "149"))
+      .respond_with(ResponseTemplate::new(404))
+      .mount(&server)
+      .await;
+    let err = client(&server).page(149).await.unwrap_err();
+    assert!(matches!(err, WikiError::Status(StatusCode::NOT_FOUND)));
+  }
+}
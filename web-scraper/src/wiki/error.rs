@@ -0,0 +1,37 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Errors from talking to the MediaWiki API.
+#[derive(Error, Debug)]
+pub enum WikiError {
+  /// The request couldn't be sent or the response couldn't be read
+  #[error("HTTP request failed: {0}")]
+  Http(#[from] reqwest::Error),
+
+  /// The server answered with an unexpected status
+  #[error("Unexpected HTTP status: {0}")]
+  Status(StatusCode),
+
+  /// The API reported an error
+  #[error("API error {code}: {info}")]
+  Api { code: String, info: String },
+
+  /// The response wasn't the JSON the client expects
+  #[error("Failed to decode API response: {0}")]
+  Decode(#[from] serde_json::Error),
+
+  /// The server kept asking the client to back off
+  #[error("Gave up after {attempts} attempts: {reason}")]
+  RetriesExhausted { attempts: u32, reason: String },
+}
+
+impl From<http_retry::RetriesExhausted> for WikiError {
+  fn from(e: http_retry::RetriesExhausted) -> Self {
+    Self::RetriesExhausted {
+      attempts: e.attempts,
+      reason: e.reason,
+    }
+  }
+}
+
+pub type Result<T> = std::result::Result<T, WikiError>;
@@ -0,0 +1,9 @@
+//! Access to the explainxkcd MediaWiki.
+
+mod client;
+mod error;
+mod parser;
+
+pub use client::{EXPLAINXKCD_API, Page, Revision, WikiClient, WikiClientOptions};
+pub use error::{Result, WikiError};
+pub use parser::{ParsedPage, Section, parse_wikitext};
@@ -0,0 +1,618 @@
+use db::SectionType;
+
+/// Link namespaces whose links are media or page metadata, not prose.
+const HIDDEN_NAMESPACES: &[&str] = &["file", "image", "media", "category"];
+
+/// Inline tags dropped while their contents are kept.
+const INLINE_TAGS: &[&str] = &[
+  "b",
+  "big",
+  "blockquote",
+  "center",
+  "code",
+  "del",
+  "div",
+  "font",
+  "i",
+  "ins",
+  "p",
+  "poem",
+  "pre",
+  "s",
+  "small",
+  "span",
+  "strike",
+  "strong",
+  "sub",
+  "sup",
+  "tt",
+  "u",
+];
+
+/// The parts of an explainxkcd page worth indexing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedPage {
+  /// The `number` from the `{{comic}}` infobox.
+  pub number: Option<u64>,
+  pub title: Option<String>,
+  /// The comic's title text, shown when hovering over it on xkcd.
+  pub hover_text: Option<String>,
+  /// The publication date as written in the infobox, e.g. `July 28, 2006`.
+  pub date: Option<String>,
+  /// Top-level sections in page order, without the discussion.
+  pub sections: Vec<Section>,
+}
+
+/// A top-level section of a page, as plain text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+  pub section_type: SectionType,
+  /// The heading as written on the page.
+  pub heading: String,
+  /// Paragraphs separated by blank lines. List items, transcript lines and
+  /// table rows keep a line each.
+  pub text: String,
+}
+
+impl ParsedPage {
+  /// The sections of type `section_type`, joined by blank lines.
+  pub fn text_of(&self, section_type: SectionType) -> Option<String> {
+    let parts: Vec<&str> = self
+      .sections
+      .iter()
+      .filter(|s| s.section_type == section_type)
+      .map(|s| s.text.as_str())
+      .collect();
+    (!parts.is_empty()).then(|| parts.join("\n\n"))
+  }
+}
+
+/// Parse the wikitext of an explainxkcd comic page.
+///
+/// The `{{comic}}` infobox provides the number, title, hover text and date.
+/// The body is split at its level-two headings into [`Section`]s typed by
+/// heading, with templates expanded or dropped, links reduced to their
+/// label and markup removed. Everything from the `Discussion` heading on is
+/// skipped.
+pub fn parse_wikitext(wikitext: &str) -> ParsedPage {
+  let source = strip_comments(wikitext);
+  let mut page = ParsedPage::default();
+
+  let body = match find_template(&source, "comic") {
+    Some((start, end)) => {
+      let args = split_args(&source[start + 2..end - 2]);
+      for (name, value) in args.iter().skip(1).filter_map(|a| a.split_once('=')) {
+        let value = inline_text(value);
+        let value = (!value.is_empty()).then_some(value);
+        match name.trim().to_ascii_lowercase().as_str() {
+          "number" => page.number = value.and_then(|v| v.parse().ok()),
+          "title" => page.title = value,
+          "titletext" => page.hover_text = value,
+          "date" => page.date = value,
+          _ => {}
+        }
+      }
+      format!("{}{}", &source[..start], &source[end..])
+    }
+    None => source,
+  };
+
+  let mut heading: Option<String> = None;
+  let mut lines: Vec<&str> = Vec::new();
+  for line in body.lines() {
+    if let Some(next) = top_level_heading(line) {
+      push_section(&mut page.sections, heading.take(), &lines);
+      lines.clear();
+      if next.eq_ignore_ascii_case("discussion") {
+        return page;
+      }
+      heading = Some(next.to_string());
+    } else {
+      lines.push(line);
+    }
+  }
+  push_section(&mut page.sections, heading, &lines);
+  page
+}
+
+fn section_type(heading: &str) -> SectionType {
+  let heading = heading.to_ascii_lowercase();
+  if heading.starts_with("explanation") {
+    SectionType::Explanation
+  } else if heading.starts_with("transcript") {
+    SectionType::Transcript
+  } else if heading.starts_with("trivia") {
+    SectionType::Trivia
+  } else {
+    SectionType::Other
+  }
+}
+
+fn push_section(sections: &mut Vec<Section>, heading: Option<String>, lines: &[&str]) {
+  let text = to_plain_text(&lines.join("\n"));
+  if text.is_empty() {
+    return;
+  }
+  let (section_type, heading) = match heading {
+    Some(heading) => (section_type(&heading), heading),
+    // text above the first heading, usually notices
+    None => (SectionType::Other, String::new()),
+  };
+  sections.push(Section {
+    section_type,
+    heading,
+    text,
+  });
+}
+
+/// The text of a `== Heading ==` line, but not of deeper headings.
+fn top_level_heading(line: &str) -> Option<&str> {
+  let inner = line
+    .trim_end()
+    .strip_prefix("==")?
+    .strip_suffix("==")?
+    .trim();
+  (!inner.starts_with('=') && !inner.ends_with('=') && !inner.is_empty()).then_some(inner)
+}
+
+/// The text of a heading line of any level.
+fn any_heading(line: &str) -> Option<&str> {
+  let line = line.trim_end();
+  let level = line.bytes().take_while(|&b| b == b'=').count();
+  if level < 2 || line.len() < 2 * level || !line.ends_with(&"=".repeat(level)) {
+    return None;
+  }
+  Some(line[level..line.len() - level].trim())
+}
+
+/// Block-level conversion of a section body: inline markup first, then
+/// headings, lists and tables line by line.
+fn to_plain_text(wikitext: &str) -> String {
+  let inline = inline_markup(wikitext);
+  let mut out: Vec<String> = Vec::new();
+  let mut row: Vec<String> = Vec::new();
+  let mut in_table = false;
+
+  for line in inline.lines() {
+    let line = line.trim();
+    if in_table {
+      if line.starts_with("|}") {
+        flush_row(&mut out, &mut row);
+        in_table = false;
+      } else if line.starts_with("|-") {
+        flush_row(&mut out, &mut row);
+      } else if let Some(caption) = line.strip_prefix("|+") {
+        out.push(collapse_spaces(cell_content(caption)));
+      } else if let Some(cells) = line.strip_prefix('|') {
+        row.extend(cells.split("||").map(|c| collapse_spaces(cell_content(c))));
+      } else if let Some(cells) = line.strip_prefix('!') {
+        row.extend(cells.split("!!").map(|c| collapse_spaces(cell_content(c))));
+      } else if let Some(last) = row.last_mut() {
+        // a cell continued on the next line
+        if !line.is_empty() {
+          last.push(' ');
+          last.push_str(&collapse_spaces(line));
+        }
+      }
+      continue;
+    }
+
+    if line.starts_with("{|") {
+      in_table = true;
+    } else if line.starts_with("----") || is_magic_word(line) {
+      continue;
+    } else if let Some(heading) = any_heading(line) {
+      out.push(String::new());
+      out.push(collapse_spaces(heading));
+      out.push(String::new());
+    } else {
+      let item = line.trim_start_matches(['*', '#', ':', ';']);
+      out.push(collapse_spaces(item));
+    }
+  }
+  flush_row(&mut out, &mut row);
+
+  // collapse runs of blank lines into paragraph breaks
+  let mut text = String::new();
+  let mut blank = false;
+  for line in out {
+    if line.is_empty() {
+      blank = !text.is_empty();
+      continue;
+    }
+    if !text.is_empty() {
+      text.push_str(if blank { "\n\n" } else { "\n" });
+    }
+    text.push_str(&line);
+    blank = false;
+  }
+  text
+}
+
+fn flush_row(out: &mut Vec<String>, row: &mut Vec<String>) {
+  let cells: Vec<String> = row.drain(..).filter(|c| !c.is_empty()).collect();
+  if !cells.is_empty() {
+    out.push(cells.join(" | "));
+  }
+}
+
+/// A table cell without its `style="..." |` attributes.
+fn cell_content(cell: &str) -> &str {
+  match cell.split_once('|') {
+    Some((attrs, content)) if attrs.contains('=') => content,
+    _ => cell,
+  }
+}
+
+fn is_magic_word(line: &str) -> bool {
+  line.len() > 4 && line.starts_with("__") && line.ends_with("__")
+}
+
+/// Inline conversion for single-line values such as infobox fields.
+fn inline_text(wikitext: &str) -> String {
+  collapse_spaces(&inline_markup(wikitext).replace('\n', " "))
+}
+
+fn collapse_spaces(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Expand templates, resolve links, drop tags and emphasis, and decode
+/// entities. Line structure is kept for the block-level pass.
+fn inline_markup(wikitext: &str) -> String {
+  let mut out = String::with_capacity(wikitext.len());
+  let mut rest = wikitext;
+  while let Some(c) = rest.chars().next() {
+    if rest.starts_with("{{") {
+      if let Some(end) = matching_close(rest, "{{", "}}") {
+        let args = split_args(&rest[2..end - 2]);
+        out.push_str(&inline_markup(&expand_template(&args)));
+        rest = &rest[end..];
+        continue;
+      }
+    } else if rest.starts_with("[[") {
+      if let Some(end) = matching_close(rest, "[[", "]]") {
+        out.push_str(&inline_markup(&link_label(&rest[2..end - 2])));
+        rest = &rest[end..];
+        continue;
+      }
+    } else if c == '[' && is_external_link(&rest[1..]) {
+      if let Some(end) = rest.find(']') {
+        // `[url label]` shows the label; a bare `[url]` shows nothing useful
+        if let Some((_, label)) = rest[1..end].split_once(' ') {
+          out.push_str(&inline_markup(label));
+        }
+        rest = &rest[end + 1..];
+        continue;
+      }
+    } else if c == '<' {
+      if let Some((replacement, len)) = tag(rest) {
+        out.push_str(&replacement);
+        rest = &rest[len..];
+        continue;
+      }
+    } else if rest.starts_with("''") {
+      rest = rest.trim_start_matches('\'');
+      continue;
+    } else if c == '&'
+      && let Some((decoded, len)) = entity(rest)
+    {
+      out.push(decoded);
+      rest = &rest[len..];
+      continue;
+    }
+    out.push(c);
+    rest = &rest[c.len_utf8()..];
+  }
+  out
+}
+
+/// Byte offset just past the `close` that balances the `open` at the start of
+/// `text`, if there is one.
+fn matching_close(text: &str, open: &str, close: &str) -> Option<usize> {
+  let mut depth = 0usize;
+  let mut i = 0;
+  while i < text.len() {
+    if text[i..].starts_with(open) {
+      depth += 1;
+      i += open.len();
+    } else if text[i..].starts_with(close) {
+      depth -= 1;
+      i += close.len();
+      if depth == 0 {
+        return Some(i);
+      }
+    } else {
+      i += text[i..].chars().next().map_or(1, char::len_utf8);
+    }
+  }
+  None
+}
+
+/// Split template or link contents at the `|`s that aren't inside a nested
+/// template or link.
+fn split_args(inner: &str) -> Vec<&str> {
+  let mut args = Vec::new();
+  let mut depth = 0usize;
+  let mut start = 0;
+  let bytes = inner.as_bytes();
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'{' | b'[' if bytes.get(i + 1) == Some(&bytes[i]) => {
+        depth += 1;
+        i += 2;
+        continue;
+      }
+      b'}' | b']' if bytes.get(i + 1) == Some(&bytes[i]) && depth > 0 => {
+        depth -= 1;
+        i += 2;
+        continue;
+      }
+      b'|' if depth == 0 => {
+        args.push(&inner[start..i]);
+        start = i + 1;
+      }
+      _ => {}
+    }
+    i += 1;
+  }
+  args.push(&inner[start..]);
+  args
+}
+
+/// The byte range of the first top-level `{{name ...}}` in `text`.
+fn find_template(text: &str, name: &str) -> Option<(usize, usize)> {
+  let mut from = 0;
+  while let Some(offset) = text[from..].find("{{") {
+    let start = from + offset;
+    let end = matching_close(&text[start..], "{{", "}}")? + start;
+    let args = split_args(&text[start + 2..end - 2]);
+    if template_name(args[0]) == name {
+      return Some((start, end));
+    }
+    from = end;
+  }
+  None
+}
+
+/// Template names are case-insensitive in their first letter on MediaWiki;
+/// explainxkcd is loose about the rest too.
+fn template_name(raw: &str) -> String {
+  collapse_spaces(&raw.replace('_', " ")).to_lowercase()
+}
+
+/// Wikitext to put in place of a template, from its name and arguments.
+fn expand_template(args: &[&str]) -> String {
+  let name = template_name(args[0]);
+  let positional: Vec<&str> = args[1..]
+    .iter()
+    .filter(|a| !a.contains('='))
+    .map(|a| a.trim())
+    .collect();
+  match name.as_str() {
+    // links out: `{{w|Target|label}}` shows the label, else the target
+    "w" | "wp" | "wikipedia" | "wikt" | "wiktionary" | "tvtropes" | "what if" | "xkcd"
+    | "explain" => positional.last().copied().unwrap_or_default().to_string(),
+    // formatting that wraps its first argument
+    "nowrap" | "tooltip" | "smallcaps" | "big" | "small" | "sic" => {
+      positional.first().copied().unwrap_or_default().to_string()
+    }
+    "color" | "colour" | "font color" => positional.get(1).copied().unwrap_or_default().to_string(),
+    // escapes for characters that are syntax inside templates
+    "=" => "=".to_string(),
+    "!" => "|".to_string(),
+    // notices, citation markers and navigation boxes carry no content
+    _ => String::new(),
+  }
+}
+
+/// The text an internal link shows.
+fn link_label(inner: &str) -> String {
+  let args = split_args(inner);
+  let target = args[0].trim();
+  if !target.starts_with(':')
+    && let Some((namespace, _)) = target.split_once(':')
+    && HIDDEN_NAMESPACES.contains(&namespace.trim().to_ascii_lowercase().as_str())
+  {
+    return String::new();
+  }
+  match args.last() {
+    Some(label) if args.len() > 1 => label.to_string(),
+    _ => target.trim_start_matches(':').to_string(),
+  }
+}
+
+fn is_external_link(text: &str) -> bool {
+  ["http://", "https://", "//", "ftp://", "mailto:"]
+    .iter()
+    .any(|scheme| text.starts_with(scheme))
+}
+
+/// Replacement text and length of the tag or comment at the start of `text`,
+/// or `None` if it isn't markup.
+fn tag(text: &str) -> Option<(String, usize)> {
+  let close = text.find('>')?;
+  let inside = &text[1..close];
+  let name: String = inside
+    .trim_start_matches('/')
+    .chars()
+    .take_while(|c| c.is_ascii_alphanumeric())
+    .collect::<String>()
+    .to_ascii_lowercase();
+  match name.as_str() {
+    "br" => Some(("\n".to_string(), close + 1)),
+    "ref" | "nowiki" if inside.ends_with('/') => Some((String::new(), close + 1)),
+    "ref" => {
+      let end = text
+        .find("</ref>")
+        .map_or(close + 1, |e| e + "</ref>".len());
+      Some((String::new(), end))
+    }
+    // `<nowiki>` contents are literal
+    "nowiki" if !inside.starts_with('/') => {
+      let end = text.find("</nowiki>")?;
+      let literal = &text[close + 1..end];
+      Some((literal.to_string(), end + "</nowiki>".len()))
+    }
+    name if INLINE_TAGS.contains(&name) => Some((String::new(), close + 1)),
+    _ => None,
+  }
+}
+
+/// The character and length of the HTML entity at the start of `text`.
+fn entity(text: &str) -> Option<(char, usize)> {
+  let end = text.bytes().take(10).position(|b| b == b';')?;
+  let name = &text[1..end];
+  let decoded = match name {
+    "amp" => '&',
+    "lt" => '<',
+    "gt" => '>',
+    "quot" => '"',
+    "apos" => '\'',
+    "nbsp" => ' ',
+    "ndash" => '–',
+    "mdash" => '—',
+    "hellip" => '…',
+    "times" => '×',
+    _ => {
+      let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => name.strip_prefix('#')?.parse().ok()?,
+      };
+      char::from_u32(code)?
+    }
+  };
+  Some((decoded, end + 1))
+}
+
+fn strip_comments(wikitext: &str) -> String {
+  let mut out = String::with_capacity(wikitext.len());
+  let mut rest = wikitext;
+  while let Some(start) = rest.find("<!--") {
+    out.push_str(&rest[..start]);
+    rest = match rest[start..].find("-->") {
+      Some(end) => &rest[start + end + 3..],
+      None => "",
+    };
+  }
+  out.push_str(rest);
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fmt::Write;
+  use std::fs;
+  use std::path::Path;
+
+  use super::*;
+
+  /// Render a parsed page in the layout of the `.txt` golden files.
+  fn render(page: &ParsedPage) -> String {
+    let mut out = String::new();
+    let field = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+    writeln!(
+      out,
+      "number: {}",
+      page.number.map_or("-".to_string(), |n| n.to_string())
+    )
+    .unwrap();
+    writeln!(out, "title: {}", field(&page.title)).unwrap();
+    writeln!(out, "hover_text: {}", field(&page.hover_text)).unwrap();
+    writeln!(out, "date: {}", field(&page.date)).unwrap();
+    for section in &page.sections {
+      write!(
+        out,
+        "\n=== {} ({}) ===\n{}\n",
+        section.heading, section.section_type, section.text
+      )
+      .unwrap();
+    }
+    out
+  }
+
+  /// Each `tests/fixtures/wikitext/*.wiki` must parse to its `.txt`. Run
+  /// with `UPDATE_GOLDEN=1` to rewrite the expectations after a deliberate
+  /// change, and review the diff.
+  #[test]
+  fn test_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/wikitext");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut checked = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+      let path = entry.unwrap().path();
+      if path.extension().is_none_or(|e| e != "wiki") {
+        continue;
+      }
+      let actual = render(&parse_wikitext(&fs::read_to_string(&path).unwrap()));
+      let golden = path.with_extension("txt");
+      if update {
+        fs::write(&golden, &actual).unwrap();
+      } else {
+        let expected = fs::read_to_string(&golden).unwrap();
+        assert_eq!(actual, expected, "{}", path.display());
+      }
+      checked += 1;
+    }
+    assert!(checked > 0);
+  }
+
+  #[test]
+  fn test_templates_and_links() {
+    let text = inline_text(
+      "A {{w|Buffer overflow|buffer overrun}} in [[Help:Formatting|formatting]] \
+       [[149: Sandwich]]es{{citation needed}} and {{w|SQL}}.",
+    );
+    assert_eq!(
+      text,
+      "A buffer overrun in formatting 149: Sandwiches and SQL."
+    );
+
+    // nested templates, hidden namespaces, external links and emphasis
+    let text = inline_text(
+      "{{nowrap|{{w|Bobby Tables}}}}[[File:a.png|thumb|x]][[Category:Comics]] \
+       ''see'' [https://xkcd.com/327 '''the comic'''] [https://example.com]",
+    );
+    assert_eq!(text, "Bobby Tables see the comic");
+  }
+
+  #[test]
+  fn test_tags_and_entities() {
+    let text = inline_text(
+      "a<ref>source</ref> b<ref name=\"x\" /> <nowiki>[[literal]]</nowiki> \
+       <span style=\"color:red\">c</span> &lt;3 &amp; x < y &#8212; &#x2013;",
+    );
+    assert_eq!(text, "a b [[literal]] c <3 & x < y — –");
+  }
+
+  #[test]
+  fn test_sections() {
+    let page = parse_wikitext(
+      "{{comic\n| number = 1\n| title = Barrel - Part 1\n| titletext = Don't we all.\n}}\n\
+       ==Explanation==\nFirst.\n===More===\nSecond.\n\
+       == Transcript ==\n:[A boy sits in a barrel.]\n:Boy: I wonder.\n\
+       ==Trivia==\n* One\n* Two\n\
+       ==Discussion==\n{{comic discussion}}\nComments.",
+    );
+    assert_eq!(page.number, Some(1));
+    assert_eq!(page.title.as_deref(), Some("Barrel - Part 1"));
+    assert_eq!(page.hover_text.as_deref(), Some("Don't we all."));
+    let kinds: Vec<SectionType> = page.sections.iter().map(|s| s.section_type).collect();
+    assert_eq!(
+      kinds,
+      [
+        SectionType::Explanation,
+        SectionType::Transcript,
+        SectionType::Trivia
+      ]
+    );
+    assert_eq!(page.sections[0].text, "First.\n\nMore\n\nSecond.");
+    assert_eq!(
+      page.text_of(SectionType::Transcript).as_deref(),
+      Some("[A boy sits in a barrel.]\nBoy: I wonder.")
+    );
+    assert_eq!(page.sections[2].text, "One\nTwo");
+    assert_eq!(page.text_of(SectionType::Other), None);
+  }
+}